@@ -1,9 +1,11 @@
 use std::{
-    io::{self, Result}, net::{IpAddr, SocketAddr, TcpStream}, str::FromStr, sync::mpsc::{self, Sender}, thread::{self, sleep}, time::Duration
+    io::{self, Result}, net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs}, str::FromStr, sync::mpsc::{self, Sender, TryRecvError}, thread::{self, sleep}, time::Duration
 };
 
 use crossterm::{cursor::{MoveDown, MoveLeft, MoveRight, MoveUp}, event::{self, Event, KeyCode, KeyEvent, KeyEventKind}, execute, terminal::{self, Clear, ClearType, DisableLineWrap, EnableLineWrap, disable_raw_mode, enable_raw_mode}};
-use mini_holdem::{cards::{Card, format_cards}, events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound, ShowdownInfo}, game::Pot, networking::{client_network_loop, send_event}};
+use mini_holdem::{cards::{Card, ShowdownDecidingFactor}, equity::{estimate_win_probability, pot_odds}, events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound, ShowdownInfo}, game::{Pot, ShowdownStep}, networking::{client_network_loop, send_event}, protocol::{MAX_CHAT_LENGTH, PROTOCOL_VERSION, validate_username}};
+
+const ODDS_ITERATIONS: u32 = 5000;
 
 struct Player {
     username: String,
@@ -17,6 +19,8 @@ struct InGameInfo {
     private_cards: [Card; 2],
     public_cards: Vec<Card>,
     pot_data: Vec<Pot>,
+    pot_total: u32,
+    round_contributions: Vec<u32>, // by seat, what everyone put in on this street
 }
 
 #[derive(Debug)]
@@ -32,13 +36,16 @@ struct ClientData {
     notifs: Vec<String>,
     conn: TcpStream,
     in_game_info: Option<InGameInfo>,
-    display_mode: DisplayMode
+    display_mode: DisplayMode,
+    color: bool, // off with --plain or NO_COLOR, for terminals that don't do ANSI
+    background_notifs: Sender<String>, // for work done off the main thread, like the odds
+    queued_showdowns: Vec<ShowdownInfo>, // the second run of a board run twice
 }
 
 fn main() -> Result<()> {
     let conn: TcpStream;
     loop {
-        println!("Enter the server ip address.");
+        println!("Enter the server address, either an ip or host:port.");
         let mut buf = String::new();
         io::stdin().read_line(&mut buf)?;
         buf = buf.trim_end().to_string();
@@ -47,8 +54,10 @@ fn main() -> Result<()> {
             conn_attempt = TcpStream::connect_timeout(&SocketAddr::from(([127, 0, 0, 1], 9194)), Duration::from_secs(5));
         } else if let Ok(addr) = IpAddr::from_str(&buf) {
             conn_attempt = TcpStream::connect_timeout(&SocketAddr::new(addr, 9194), Duration::from_secs(5));
+        } else if let Some(addr) = buf.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()) {
+            conn_attempt = TcpStream::connect_timeout(&addr, Duration::from_secs(5));
         } else {
-            println!("Invalid IP address.");
+            println!("Invalid address.");
             continue;
         }
         if let Ok(c) = conn_attempt {
@@ -68,25 +77,36 @@ fn main() -> Result<()> {
     let (tx, rx) = mpsc::channel();
     thread::spawn(move || read_continuously(tx));
     
-    let mut client_data = ClientData { player_list: Vec::new(), player_index: None, notifs: Vec::new(), conn, in_game_info: None, display_mode: DisplayMode::PlayerList };
+    let (background_notifs, finished_notifs) = mpsc::channel();
+    let mut client_data = ClientData { player_list: Vec::new(), player_index: None, notifs: Vec::new(), conn, in_game_info: None, display_mode: DisplayMode::PlayerList, color: !std::env::args().any(|arg| arg == "--plain") && std::env::var_os("NO_COLOR").is_none(), background_notifs, queued_showdowns: Vec::new() };
     
     let mut notif_cooldown = 0; // ms
     
     let (tx, received_events) = mpsc::channel();
     let mut cloned = client_data.conn.try_clone().expect("Failed to clone stream.");
-    thread::spawn(move || client_network_loop(&mut cloned, tx));
+    let network = thread::spawn(move || client_network_loop(&mut cloned, tx));
     
+    send_event(&mut client_data.conn, ServerBound::Hello(PROTOCOL_VERSION))?;
+    send_event(&mut client_data.conn, ServerBound::Spectate)?; // so the lobby shows up before joining
     send_event(&mut client_data.conn, ServerBound::GetPlayerList)?;
 
     let mut line = String::new();
     let mut last_notif = String::new();
     let mut do_render = false;
-    loop {
-        while let Ok(event) = received_events.try_recv() {
-            handle_event(event, &mut client_data);
-            do_render = true;
+    'main: loop {
+        loop {
+            match received_events.try_recv() {
+                Ok(event) => {
+                    handle_event(event, &mut client_data);
+                    do_render = true;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => break 'main, // the network thread is gone
+            }
         }
 
+        client_data.notifs.extend(finished_notifs.try_iter());
+
         if let Ok(key) = rx.try_recv() {
             if matches!(key, KeyCode::Esc) {
                 break;
@@ -97,7 +117,7 @@ fn main() -> Result<()> {
         }
 
         if do_render {
-            render(&mut client_data, &line, &last_notif)?;
+            render(&client_data, &line, &last_notif)?;
             do_render = false;
         }
 
@@ -108,10 +128,10 @@ fn main() -> Result<()> {
         if notif_cooldown == 0 && let Some(notif) = client_data.notifs.pop() {
             last_notif = notif.clone();
             execute!(io::stdout(), MoveUp(2), Clear(ClearType::CurrentLine))?;
-            if line.len() != 0 { execute!(io::stdout(), MoveLeft(line.len() as u16))? }
+            if !line.is_empty() { execute!(io::stdout(), MoveLeft(line.len() as u16))? }
             print!("{}", notif);
             execute!(io::stdout(), MoveDown(2), MoveLeft(notif.len() as u16))?;
-            if line.len() != 0 { execute!(io::stdout(), MoveRight(line.len() as u16))? }
+            if !line.is_empty() { execute!(io::stdout(), MoveRight(line.len() as u16))? }
             notif_cooldown = 2000;
         }
 
@@ -120,6 +140,13 @@ fn main() -> Result<()> {
 
     disable_raw_mode()?;
     execute!(io::stdout(), EnableLineWrap)?;
+    if network.is_finished() {
+        match network.join() {
+            Ok(Ok(())) => println!("\nThe server closed the connection."),
+            Ok(Err(e)) => println!("\nLost the connection to the server: {}", e),
+            Err(_) => println!("\nThe network thread crashed."),
+        }
+    }
     Ok(())
 }
 
@@ -135,10 +162,11 @@ fn handle_event(event: ClientBound, client_data: &mut ClientData) {
         ClientBound::PlayerLeft(player) => client_data.notifs.push(player+" left the game."),
         ClientBound::PlayerJoined(player) => client_data.notifs.push(player+" joined the game."),
         ClientBound::GameStarted(cards) => {
-            for player in client_data.player_list.iter_mut() {
+            for player in client_data.player_list.iter_mut().filter(|p| !matches!(p.player_state, PlayerState::SittingOut)) {
                 player.player_state = PlayerState::InGame;
             }
-            client_data.in_game_info = Some(InGameInfo { current_turn: 0, current_bet: 0, private_cards: cards, public_cards: Vec::new(), pot_data: Vec::new() });
+            client_data.queued_showdowns.clear();
+            client_data.in_game_info = Some(InGameInfo { current_turn: 0, current_bet: 0, private_cards: cards, public_cards: Vec::new(), pot_data: Vec::new(), pot_total: 0, round_contributions: vec![0; client_data.player_list.len()] });
         },
         ClientBound::GameEvent(game_event) => {
             if let Some(game_info) = client_data.in_game_info.as_mut() {
@@ -149,13 +177,43 @@ fn handle_event(event: ClientBound, client_data: &mut ClientData) {
                         let username = &client_data.player_list[player as usize].username;
                         match action {
                             GamePlayerAction::Check => client_data.notifs.push(username.clone()+" checked."),
-                            GamePlayerAction::AddMoney(money) => client_data.notifs.push(username.clone()+" added "+&money.to_string()),
+                            GamePlayerAction::AddMoney(money) => {
+                                client_data.notifs.push(username.clone()+" added "+&money.to_string());
+                                if let Some(contribution) = game_info.round_contributions.get_mut(player as usize) {
+                                    *contribution += money;
+                                }
+                            },
                             GamePlayerAction::Fold => {
                                 client_data.notifs.push(username.to_owned()+" folded.");
                                 client_data.player_list[player as usize].player_state = PlayerState::Folded;
-                            }
+                            },
+                            GamePlayerAction::Muck => client_data.notifs.push(username.clone()+" mucked."),
+                            GamePlayerAction::Show => client_data.notifs.push(username.clone()+" showed."),
+                            GamePlayerAction::Call => client_data.notifs.push(username.clone()+" called."),
+                            GamePlayerAction::Bet(_) | GamePlayerAction::Raise(_) => {}, // the server only sends out what was added
+                            GamePlayerAction::RunItTwice(true) => client_data.notifs.push(username.clone()+" wants to run it twice."),
+                            GamePlayerAction::RunItTwice(false) => client_data.notifs.push(username.clone()+" wants to run it once."),
+                        }
+                    },
+                    GameEvent::MuckOrShow(seats) => {
+                        if client_data.player_index.is_some_and(|index| seats.contains(&index)) {
+                            client_data.notifs.push("You lost, use \"muck\" to keep your cards hidden or \"show\" to reveal them.".to_string());
+                        } else {
+                            client_data.notifs.push("Waiting for the beaten players to muck or show.".to_string());
                         }
                     },
+                    GameEvent::RunItTwiceOffer(seats) => {
+                        if client_data.player_index.is_some_and(|index| seats.contains(&index)) {
+                            client_data.notifs.push("Everyone is all-in, use \"twice\" to run the rest of the board twice or \"once\" to run it once.".to_string());
+                        } else {
+                            client_data.notifs.push("Waiting for the all-in players to decide whether to run it twice.".to_string());
+                        }
+                    },
+                    GameEvent::RevealSecondBoard(cards) => client_data.notifs.push(format!("Running it twice, the second board gets {}.", render_cards(&cards, client_data.color))),
+                    GameEvent::PlayerAllIn(player) => {
+                        let username = client_data.player_list.get(player as usize).map(|p| p.username.clone()).unwrap_or("?".to_string());
+                        client_data.notifs.push(username+" is all-in!");
+                    },
                     GameEvent::InGamePlayerLeave(player) => client_data.player_list[player as usize].player_state = PlayerState::Left,
                     GameEvent::UpdateCurrentBet(money) => game_info.current_bet = money,
                     GameEvent::UpdatePots(pots) => {
@@ -164,54 +222,181 @@ fn handle_event(event: ClientBound, client_data: &mut ClientData) {
                             game_info.pot_data.push(pot);
                         }
                     },
-                    GameEvent::RevealFlop(cards) => game_info.public_cards.extend(cards),
-                    GameEvent::RevealTurn(card) | GameEvent::RevealRiver(card) => game_info.public_cards.push(card),
+                    GameEvent::RevealFlop(cards) => {
+                        game_info.public_cards.extend(cards);
+                        game_info.round_contributions.fill(0);
+                    },
+                    GameEvent::RevealTurn(card) | GameEvent::RevealRiver(card) => {
+                        game_info.public_cards.push(card);
+                        game_info.round_contributions.fill(0);
+                    },
+                    GameEvent::Showdown(info) if matches!(client_data.display_mode, DisplayMode::ShowdownHandRanks(_) | DisplayMode::ShowdownSteps(_)) => {
+                        client_data.queued_showdowns.push(info);
+                    },
                     GameEvent::Showdown(info) => {
                         client_data.display_mode = DisplayMode::ShowdownHandRanks((client_data.player_list.iter().map(|p| p.username.clone()).collect(), info))
+                    },
+                    GameEvent::WonUncontested(player, winnings) => {
+                        let username = client_data.player_list.get(player as usize).map(|p| p.username.clone()).unwrap_or("?".to_string());
+                        client_data.notifs.push(format!("{} won {} after everyone else folded.", username, winnings));
+                        client_data.in_game_info = None;
                     }
                 }
             }
-        }
+        },
+        ClientBound::Welcome { version, accepted } => {
+            if !accepted {
+                client_data.notifs.push(format!("The server speaks protocol version {} but this client speaks {}, update it to play.", version, PROTOCOL_VERSION));
+            }
+        },
+        ClientBound::ChatBroadcast { player, message } => {
+            let name = client_data.player_list.get(player as usize).map(|p| p.username.clone()).unwrap_or("?".to_string());
+            client_data.notifs.push(format!("{}: {}", name, message));
+        },
+        ClientBound::SessionToken(token) => client_data.notifs.push(format!("If you lose connection, reconnect and type \"rejoin {}\" to get your seat back.", token)),
+        ClientBound::RoomCreated(room) => client_data.notifs.push(format!("Created room {}, others can join it with \"room {}\".", room, room)),
+        ClientBound::LoginRejected(reason) => client_data.notifs.push(reason),
+        ClientBound::RebuyResult { accepted: true, money } => client_data.notifs.push(format!("Rebought, you now have {}.", money)),
+        ClientBound::RebuyResult { accepted: false, money } => client_data.notifs.push(format!("Couldn't rebuy, you can only do it between hands and without going over the table's max buy-in. You have {}.", money)),
+        ClientBound::TimeBankUsed { player, remaining } if client_data.player_index == Some(player) => client_data.notifs.push(format!("Your time is up, you're on your time bank with {} seconds left.", remaining)),
+        ClientBound::TimeBankUsed { player, remaining } => {
+            let name = client_data.player_list.get(player as usize).map(|p| p.username.clone()).unwrap_or_default();
+            client_data.notifs.push(format!("{} is using their time bank, {} seconds left.", name, remaining));
+        },
+        ClientBound::PotTotal(money) => {
+            if let Some(game_info) = client_data.in_game_info.as_mut() {
+                game_info.pot_total = money;
+            }
+        },
+        ClientBound::Countdown(0) => client_data.notifs.push("Someone isn't ready anymore, the hand won't start yet.".to_string()),
+        ClientBound::Countdown(secs) => client_data.notifs.push(format!("Everyone is ready, the hand starts in {} seconds.", secs)),
+        ClientBound::YourTurn { can_check: true, min_raise, .. } => client_data.notifs.push(format!("It's your turn, you can check or raise by at least {}.", min_raise)),
+        ClientBound::YourTurn { to_call, min_raise, .. } => client_data.notifs.push(format!("It's your turn, {} to call or raise by at least {}.", to_call, min_raise)),
+        ClientBound::Error { message, .. } => client_data.notifs.push(format!("Couldn't do that, {}.", message)),
+        ClientBound::ServerFull => client_data.notifs.push("The server is full, try again later.".to_string()),
+        ClientBound::ServerShutdown => {
+            client_data.notifs.push("The server is shutting down.".to_string());
+            let _ = send_event(&mut client_data.conn, ServerBound::Disconnect);
+        },
+        ClientBound::Ping | ClientBound::Pong => {} // the network loop answers these
     }
 }
 
 fn handle_command(cmd: String, args: Vec<String>, client_data: &mut ClientData) -> Result<bool> {
     match cmd.as_str() {
         "join" => {
-            if let Some(username) = args.get(0) {
-                if username == "" {
-                    return Ok(false);
-                }
-                if !username.is_ascii() {
-                    client_data.notifs.push("Usernames can only contain ASCII characters!".to_string());
-                    return Ok(false);
-                }
-                if username.len() < 3 {
-                    client_data.notifs.push("Usernames have to have at least 3 characters!".to_string());
+            if let Some(username) = args.first() {
+                let buy_in = match args.get(1).map(|a| a.parse::<u32>()) {
+                    Some(Ok(buy_in)) if buy_in > 0 => Some(buy_in),
+                    None => None,
+                    _ => {
+                        client_data.notifs.push("Usage: join <username> [buy-in]".to_string());
+                        return Ok(false);
+                    }
+                };
+                if username.is_empty() {
                     return Ok(false);
                 }
-                if username.len() > 16 {
-                    client_data.notifs.push("Usernames can't have more than 16 characters!".to_string());
+                if let Err(reason) = validate_username(username) {
+                    client_data.notifs.push(reason.to_string());
                     return Ok(false);
                 }
                 if client_data.player_list.iter().any(|p| p.username == *username) {
                     client_data.notifs.push("This username is already taken!".to_string());
                     return Ok(false);
                 }
-                send_event(&mut client_data.conn, ServerBound::Login(username.clone()))?;
+                send_event(&mut client_data.conn, ServerBound::Login { username: username.clone(), buy_in })?;
             } else {
-                client_data.notifs.push("Usage: join <username>".to_string());
+                client_data.notifs.push("Usage: join <username> [buy-in]".to_string());
             }
         }
         "ready" => send_event(&mut client_data.conn, ServerBound::Ready(true))?,
         "notready" => send_event(&mut client_data.conn, ServerBound::Ready(false))?,
-        "check" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::Check))?,
-        "addmoney" => {
+        "state" => send_event(&mut client_data.conn, ServerBound::RequestGameState)?,
+        "sitout" => send_event(&mut client_data.conn, ServerBound::SitOut(true))?,
+        "sitin" => send_event(&mut client_data.conn, ServerBound::SitOut(false))?,
+        "check" | "call" | "bet" | "raise" | "allin" | "addmoney" | "fold" => {
+            match betting_action(&cmd, &args, client_data) {
+                Ok(action) => send_event(&mut client_data.conn, ServerBound::GameAction(action))?,
+                Err(message) => client_data.notifs.push(message.to_string()),
+            }
+        },
+        "muck" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::Muck))?,
+        "show" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::Show))?,
+        "twice" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::RunItTwice(true)))?,
+        "once" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::RunItTwice(false)))?,
+        "rejoin" => {
+            if args.len() == 1 && let Ok(token) = args[0].parse::<u64>() {
+                send_event(&mut client_data.conn, ServerBound::Reconnect(token))?;
+            } else {
+                client_data.notifs.push("Usage: rejoin <token>".to_string());
+            }
+        },
+        "room" => {
+            if args.len() == 1 && let Ok(room) = args[0].parse::<u32>() {
+                send_event(&mut client_data.conn, ServerBound::JoinRoom(room))?;
+            } else {
+                client_data.notifs.push("Usage: room <id>".to_string());
+            }
+        },
+        "odds" => {
+            let (Some(game_info), Some(index)) = (&client_data.in_game_info, client_data.player_index) else {
+                client_data.notifs.push("There's no game going on!".to_string());
+                return Ok(false);
+            };
+            let opponents = count_opponents(&client_data.player_list, index);
+            let (private_cards, public_cards) = (game_info.private_cards, game_info.public_cards.clone());
+            let sender = client_data.background_notifs.clone();
+            client_data.notifs.push("Working out your odds...".to_string());
+            thread::spawn(move || {
+                let probability = estimate_win_probability(&private_cards, &public_cards, opponents, ODDS_ITERATIONS);
+                let _ = sender.send(format!("You win about {:.1}% of the time against {} opponent(s).", probability * 100.0, opponents));
+            });
+        },
+        "potodds" => {
+            let (Some(game_info), Some(index)) = (&client_data.in_game_info, client_data.player_index) else {
+                client_data.notifs.push("There's no game going on!".to_string());
+                return Ok(false);
+            };
+            // the pots already count every bet made on this street
+            let pot = game_info.pot_total;
+            let money = client_data.player_list.get(index as usize).map(|p| p.money).unwrap_or(0);
+            let to_call = game_info.current_bet.saturating_sub(game_info.round_contributions.get(index as usize).copied().unwrap_or(0)).min(money);
+            if to_call == 0 {
+                client_data.notifs.push("There's nothing to call, checking is free.".to_string());
+            } else {
+                let (ratio, break_even) = pot_odds(to_call, pot);
+                client_data.notifs.push(format!("Calling {} into a pot of {} gets you {:.1} to 1, you need {:.1}% equity to break even.", to_call, pot, ratio, break_even));
+            }
+        },
+        "rebuy" => {
             if args.len() == 1 && let Ok(money) = args[0].parse::<u32>() {
-                send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::AddMoney(money)))?;
+                send_event(&mut client_data.conn, ServerBound::Rebuy(money))?;
+            } else {
+                client_data.notifs.push("Usage: rebuy <amount>".to_string());
+            }
+        },
+        "newroom" => send_event(&mut client_data.conn, ServerBound::CreateRoom)?,
+        "admin" => {
+            if args.is_empty() {
+                client_data.notifs.push("Usage: admin kick <seat> | setmoney <seat> <amount> | startmoney <amount> | blinds <sb> <bb>".to_string());
+            } else {
+                send_event(&mut client_data.conn, ServerBound::Admin(args.join(" ")))?;
+            }
+        },
+        "chat" => {
+            let message = args.join(" ");
+            if message.is_empty() {
+                client_data.notifs.push("Usage: chat <message>".to_string());
+                return Ok(false);
+            }
+            if message.len() > MAX_CHAT_LENGTH {
+                client_data.notifs.push(format!("Chat messages can't be longer than {} characters!", MAX_CHAT_LENGTH));
+                return Ok(false);
             }
+            client_data.notifs.push(format!("You: {}", message));
+            send_event(&mut client_data.conn, ServerBound::Chat(message))?;
         },
-        "fold" => send_event(&mut client_data.conn, ServerBound::GameAction(GamePlayerAction::Fold))?,
         "next" => {
             if let DisplayMode::ShowdownSteps((players, info, idx)) = &client_data.display_mode {
                 client_data.display_mode = DisplayMode::ShowdownSteps((players.clone(), info.clone(), idx + 1))
@@ -219,9 +404,13 @@ fn handle_command(cmd: String, args: Vec<String>, client_data: &mut ClientData)
             if let DisplayMode::ShowdownHandRanks((players, info)) = &client_data.display_mode {
                 client_data.display_mode = DisplayMode::ShowdownSteps((players.clone(), info.clone(), 0));
             }
-            if let DisplayMode::ShowdownSteps((_, (_, steps), idx)) = &client_data.display_mode && steps.len() == *idx {
-                client_data.display_mode = DisplayMode::PlayerList;
-                client_data.in_game_info = None;
+            if let DisplayMode::ShowdownSteps((players, (_, steps, _), idx)) = &client_data.display_mode && steps.len() == *idx {
+                if client_data.queued_showdowns.is_empty() {
+                    client_data.display_mode = DisplayMode::PlayerList;
+                    client_data.in_game_info = None;
+                } else {
+                    client_data.display_mode = DisplayMode::ShowdownHandRanks((players.clone(), client_data.queued_showdowns.remove(0)));
+                }
             }
         }
         _ => return Ok(false)
@@ -232,50 +421,35 @@ fn handle_command(cmd: String, args: Vec<String>, client_data: &mut ClientData)
 fn render(client_data: &ClientData, line: &String, notif: &String) -> Result<()> {
     execute!(io::stdout(), Clear(ClearType::All), MoveLeft(line.len() as u16))?;
 
-    if let Some(game_info) = &client_data.in_game_info {
-        for (i, pot) in game_info.pot_data.iter().enumerate() {
-            let eligibility = if let Some(id) = client_data.player_index {
-                if pot.eligible_players.contains(&id) {
-                    "(eligible)"
-                } else {
-                    "(not eligible)"
-                }
-            } else {
-                ""
-            };
-            println!("Pot {}: ${} {}\r", i+1, pot.money, eligibility);
-        }
-
-        print!("\nCurrent bet: {}\r\n\n", game_info.current_bet);
-        
-        let public_cards_display = if game_info.public_cards.is_empty() {
-            String::from("No cards yet")
-        } else {
-            format_cards(&game_info.public_cards)
-        };
-        println!("Public cards: {}\r", public_cards_display);
-        println!("Private cards: {} {}\r\n", game_info.private_cards[0], game_info.private_cards[1]);
-    }
-
-    if let DisplayMode::ShowdownHandRanks((players, (hand_ranks, _))) = &client_data.display_mode {
+    if let DisplayMode::ShowdownHandRanks((players, (hand_ranks, steps, reveal_order))) = &client_data.display_mode {
         print!("SHOWDOWN!\r\n\n");
-        for (i, player) in players.iter().enumerate() {
-            if let Some(hand_rank) = hand_ranks.get(i) {
-                println!("{}{}: {} | {}     {}\r", player, " ".repeat(16-player.len()), format_cards(&hand_rank.0), format_cards(&hand_rank.1), hand_rank.2.to_string());
+        for &seat in reveal_order {
+            let Some(player) = players.get(seat as usize) else { continue };
+            match hand_ranks.get(seat as usize) {
+                Some(Some(hand_rank)) => println!("{}{}: {} | {}     {}\r", player, " ".repeat(16-player.len()), render_cards(&hand_rank.0, client_data.color), render_cards(&hand_rank.1, client_data.color), hand_rank.2),
+                Some(None) => println!("{}{}: mucked\r", player, " ".repeat(16-player.len())),
+                None => {}
             }
         }
+        println!("\r");
+        for line in showdown_summary(players, steps, client_data.color) {
+            println!("{}\r", line);
+        }
         print!("\nUse the command \"next\" to go to showdown steps.\r\n\n");
     }
 
-    if let DisplayMode::ShowdownSteps((players, (_hand_ranks, steps), idx)) = &client_data.display_mode {
+    if let DisplayMode::ShowdownSteps((players, (_hand_ranks, steps, _), idx)) = &client_data.display_mode {
         print!("Showdown step {} of {}\r\n\n", idx+1, steps.len());
         let step = &steps[*idx];
-        if step.pot_start_index == step.pot_start_index {
+        if step.pot_start_index == step.pot_end_index {
             print!("Fighting for pot {} worth {} money\r\n\n", step.pot_start_index+1, step.winnings);
         } else {
             print!("This step was for pots from {} to {} worth {} money in total\r\n\n", step.pot_start_index+1, step.pot_end_index+1, step.winnings);
         }
-        if step.eligible_players.len() == 0 || step.winners.len() == 0 { 
+        if step.rake_taken > 0 {
+            print!("The house took {} money as rake\r\n\n", step.rake_taken);
+        }
+        if step.eligible_players.is_empty() || step.winners.is_empty() { 
             // do nothing, illegal state
         } else if step.eligible_players.len() == 1 {
             if let Some(name) = players.get(step.eligible_players[0] as usize) {
@@ -296,18 +470,9 @@ fn render(client_data: &ClientData, line: &String, notif: &String) -> Result<()>
                 } else {
                     print!("There were {} eligible players for these winnings, and {} of them have tied to receive a split amount: {}\r\n\n", step.eligible_players.len(), username_list.len(), username_list.join(", "))
                 }
-                // this is quite a bit of work that i realized may not be that groundbreaking
-                // gonna leave this here for now
-                //
-                // if let Some(winner_name) = players.get(step.winners[0] as usize) && let Some(reason) = &step.win_reason && let Some(compared_name) = players.get(reason.1 as usize) && let Some(winner_hand_rank) = hand_ranks.get(step.winners[0] as usize) && let Some(compared_hand_rank) = hand_ranks.get(reason.1 as usize) {
-                //     println!("Comparing the hand of {} with the hand of a player who hasn't won this round: {}\r", winner_name, compared_name);
-                //     println!("{}{}: {} | {}     {}\r", winner_name, " ".repeat(16-winner_name.len()), format_cards(&winner_hand_rank.0), format_cards(&winner_hand_rank.1), winner_hand_rank.2.to_string());
-                //     println!("{}{}: {} | {}     {}\r", compared_name, " ".repeat(16-compared_name.len()), format_cards(&compared_hand_rank.0), format_cards(&compared_hand_rank.1), compared_hand_rank.2.to_string());
-                //     match reason.0 {
-                //         ShowdownDecidingFactor::Category =>
-                //         ShowdownDecidingFactor::Primary((), ())
-                //     }
-                // }
+                if username_list.len() == 1 && let Some((factor, loser)) = &step.win_reason && let Some(loser_name) = players.get(*loser as usize) {
+                    print!("{} won {} over {}\r\n\n", username_list[0], describe_win_reason(factor, client_data.color), loser_name);
+                }
             }
         }
         if idx - 1 != steps.len() {
@@ -327,7 +492,7 @@ fn render(client_data: &ClientData, line: &String, notif: &String) -> Result<()>
         let username_padding = " ".repeat(16 - player.username.len());
         let money_padding = " ".repeat(11-player.money.to_string().len());
         let username_display = if let Some(index) = client_data.player_index && index == i as u8 {
-            &("\x1b[32m".to_owned()+&player.username+&"\x1b[0m")
+            &("\x1b[32m".to_owned()+&player.username+"\x1b[0m")
         } else {
             &player.username
         };
@@ -337,6 +502,8 @@ fn render(client_data: &ClientData, line: &String, notif: &String) -> Result<()>
             "folded"
         } else if matches!(player.player_state, PlayerState::Left) {
             "left"
+        } else if matches!(player.player_state, PlayerState::SittingOut) {
+            "sitting out"
         } else if let Some(game_info) = &client_data.in_game_info && game_info.current_turn == i as u8 {
             "current turn"
         } else {
@@ -345,14 +512,141 @@ fn render(client_data: &ClientData, line: &String, notif: &String) -> Result<()>
         println!("{}.  {}{} ${}{}{}\r", i+1, username_display, username_padding, player.money, money_padding, extra);
     }
 
-    print!("\n");
+    draw_game_state(client_data);
+
+    println!();
     println!("{}\r", notif);
-    print!("\n");
+    println!();
     print!("{}", line);
     execute!(io::stdout())?;
     Ok(())
 }
 
+// everyone still in the hand besides us
+fn count_opponents(players: &[Player], index: u8) -> usize {
+    players.iter().enumerate().filter(|(i, p)| *i != index as usize && matches!(p.player_state, PlayerState::InGame)).count()
+}
+
+// what a betting command means for the money on the table, or why it can't be sent right now
+fn betting_action(cmd: &str, args: &[String], client_data: &ClientData) -> std::result::Result<GamePlayerAction, &'static str> {
+    let (Some(game_info), Some(index)) = (&client_data.in_game_info, client_data.player_index) else {
+        return Err("There's no game going on!");
+    };
+    if game_info.current_turn != index {
+        return Err("It's not your turn!");
+    }
+    let money = client_data.player_list.get(index as usize).map(|p| p.money).unwrap_or(0);
+    let to_call = game_info.current_bet.saturating_sub(game_info.round_contributions.get(index as usize).copied().unwrap_or(0));
+
+    match cmd {
+        "check" => Ok(GamePlayerAction::Check),
+        "fold" => Ok(GamePlayerAction::Fold),
+        "call" if to_call == 0 => Err("There's nothing to call, use \"check\"."),
+        "call" => Ok(GamePlayerAction::Call),
+        "bet" if game_info.current_bet > 0 => Err("There's already a bet, use \"raise\"."),
+        "bet" => {
+            let Some(Ok(bet)) = args.first().map(|a| a.parse::<u32>()) else { return Err("Usage: bet <amount>") };
+            if bet > money {
+                return Err("You don't have that much money, use \"allin\" instead.");
+            }
+            Ok(GamePlayerAction::Bet(bet))
+        },
+        "raise" => {
+            let Some(Ok(raise)) = args.first().map(|a| a.parse::<u32>()) else { return Err("Usage: raise <amount over the current bet>") };
+            if to_call + raise > money {
+                return Err("You don't have that much money, use \"allin\" instead.");
+            }
+            // with nothing to raise yet it's the opening bet
+            Ok(if game_info.current_bet == 0 { GamePlayerAction::Bet(raise) } else { GamePlayerAction::Raise(raise) })
+        },
+        "allin" => Ok(GamePlayerAction::AddMoney(money)),
+        _ => match args.first().map(|a| a.parse::<u32>()) {
+            Some(Ok(amount)) => Ok(GamePlayerAction::AddMoney(amount)),
+            _ => Err("Usage: addmoney <amount>"),
+        },
+    }
+}
+
+fn draw_game_state(client_data: &ClientData) {
+    if let Some(game_info) = &client_data.in_game_info {
+        print!("\n{}", format_game_state(game_info, client_data.player_index, client_data.color));
+    }
+}
+
+// the pots, the bet to match and the cards, every line ends in \r\n for raw mode
+fn format_game_state(game_info: &InGameInfo, player_index: Option<u8>, color: bool) -> String {
+    let mut text = String::new();
+    for (i, pot) in game_info.pot_data.iter().enumerate() {
+        let eligibility = match player_index {
+            Some(id) if pot.eligible_players.contains(&id) => "(eligible)",
+            Some(_) => "(not eligible)",
+            None => "",
+        };
+        text.push_str(&format!("Pot {}: ${} {}\r\n", i+1, pot.money, eligibility));
+    }
+
+    text.push_str(&format!("\r\nCurrent bet: {}\r\n\r\n", game_info.current_bet));
+
+    let public_cards_display = if game_info.public_cards.is_empty() {
+        String::from("No cards yet")
+    } else {
+        render_cards(&game_info.public_cards, color)
+    };
+    text.push_str(&format!("Public cards: {}\r\n", public_cards_display));
+    text.push_str(&format!("Private cards: {}\r\n", render_cards(&game_info.private_cards, color)));
+    text
+}
+
+// who won which pots and how much, one line per showdown step
+fn showdown_summary(players: &[String], steps: &[ShowdownStep], color: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    for step in steps {
+        let pots = if step.pot_start_index == step.pot_end_index {
+            format!("pot {}", step.pot_start_index+1)
+        } else {
+            format!("pots {} to {}", step.pot_start_index+1, step.pot_end_index+1)
+        };
+        let names: Vec<&str> = step.winners.iter().map(|&w| players.get(w as usize).map(|n| n.as_str()).unwrap_or("?")).collect();
+        let line = if names.len() == 1 && step.eligible_players.len() == 1 {
+            format!("{} won {} worth {} uncontested", names[0], pots, step.winnings)
+        } else if names.len() == 1 && let Some((factor, loser)) = &step.win_reason {
+            let loser = players.get(*loser as usize).map(|n| n.as_str()).unwrap_or("?");
+            format!("{} won {} worth {} {} over {}", names[0], pots, step.winnings, describe_win_reason(factor, color), loser)
+        } else if names.len() == 1 {
+            format!("{} won {} worth {}", names[0], pots, step.winnings)
+        } else {
+            format!("{} split {} worth {}", names.join(", "), pots, step.winnings)
+        };
+        lines.push(line);
+    }
+    lines
+}
+
+fn describe_win_reason(factor: &ShowdownDecidingFactor, color: bool) -> String {
+    match factor {
+        ShowdownDecidingFactor::Category => "with a better kind of hand".to_string(),
+        ShowdownDecidingFactor::Primary(won, lost) => format!("with higher cards, {} against {},", render_cards(won, color), render_cards(lost, color)),
+        ShowdownDecidingFactor::Secondary(won, lost) => format!("with a higher second pair, {} against {},", render_cards(won, color), render_cards(lost, color)),
+        ShowdownDecidingFactor::Kicker(won, lost) => format!("with a higher kicker, {} against {},", render_cards(won, color), render_cards(lost, color)),
+        ShowdownDecidingFactor::Tie => "on a tie".to_string(),
+    }
+}
+
+// rank and suit glyph, hearts and diamonds in red when color is on
+fn render_card(card: &Card, color: bool) -> String {
+    let rank = card.to_string().chars().next().unwrap_or('?');
+    let suit = card.suit_symbol();
+    if color && card.suit_is_red() {
+        format!("{}\x1b[31m{}\x1b[0m", rank, suit)
+    } else {
+        format!("{}{}", rank, suit)
+    }
+}
+
+fn render_cards(cards: &[Card], color: bool) -> String {
+    cards.iter().map(|card| render_card(card, color)).collect::<Vec<_>>().join(" ")
+}
+
 fn handle_key(key: KeyCode, line: &mut String, client_data: &mut ClientData) -> Result<bool> {
     match key {
         KeyCode::Char(c) => {
@@ -370,10 +664,10 @@ fn handle_key(key: KeyCode, line: &mut String, client_data: &mut ClientData) ->
             let parts: Vec<String> = line.split(" ").map(|s| s.to_string()).collect();
             execute!(io::stdout(), Clear(terminal::ClearType::CurrentLine), MoveLeft(line.len() as u16))?;
             line.clear();
-            if parts.len() >= 1 {
+            if !parts.is_empty() {
                 let cmd = &parts[0];
                 let args = &parts.get(1..).unwrap_or(&[]).to_vec();
-                return Ok(handle_command(cmd.to_string(), args.to_vec(), client_data)?);
+                return handle_command(cmd.to_string(), args.to_vec(), client_data);
             }
         },
         _ => {}
@@ -392,3 +686,118 @@ fn read_continuously(tx: Sender<KeyCode>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards(notation: &str) -> Vec<Card> {
+        notation.split_whitespace().map(|card| card.parse().unwrap()).collect()
+    }
+
+    fn game_info(public_cards: &str) -> InGameInfo {
+        InGameInfo {
+            current_turn: 0,
+            current_bet: 40,
+            private_cards: cards("As Kd").try_into().unwrap(),
+            public_cards: cards(public_cards),
+            pot_data: vec![Pot { money: 120, eligible_players: vec![0, 1, 2] }, Pot { money: 60, eligible_players: vec![1, 2] }],
+            pot_total: 180,
+            round_contributions: vec![0, 40, 40],
+        }
+    }
+
+    #[test]
+    fn game_state_without_color() {
+        let text = format_game_state(&game_info("2c 7h 9s"), Some(0), false);
+        assert_eq!(text, "Pot 1: $120 (eligible)\r\nPot 2: $60 (not eligible)\r\n\r\nCurrent bet: 40\r\n\r\nPublic cards: 2♣ 7♥ 9♠\r\nPrivate cards: A♠ K♦\r\n");
+        assert!(!text.contains('\x1b'));
+        // spectators aren't eligible for anything, and before the flop there's no board
+        let text = format_game_state(&game_info(""), None, false);
+        assert!(text.starts_with("Pot 1: $120 \r\nPot 2: $60 \r\n"));
+        assert!(text.contains("Public cards: No cards yet\r\n"));
+    }
+
+    // seated at index 0 with the given money, conn goes nowhere anyone reads
+    fn client_data(in_game_info: Option<InGameInfo>, money: u32) -> ClientData {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let conn = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let player_list = ["alice", "bob", "carol"].iter().map(|username| Player { username: username.to_string(), money, player_state: PlayerState::InGame }).collect();
+        ClientData { player_list, player_index: Some(0), notifs: Vec::new(), conn, in_game_info, display_mode: DisplayMode::PlayerList, color: false, background_notifs: mpsc::channel().0, queued_showdowns: Vec::new() }
+    }
+
+    fn action(command: &str, client_data: &ClientData) -> std::result::Result<String, &'static str> {
+        let parts: Vec<String> = command.split(' ').map(String::from).collect();
+        betting_action(&parts[0], &parts[1..], client_data).map(|action| format!("{:?}", action))
+    }
+
+    #[test]
+    fn betting_commands() {
+        // 40 to call with 500 behind
+        let client_data = client_data(Some(game_info("2c 7h 9s")), 500);
+        assert_eq!(action("call", &client_data), Ok("Call".to_string()));
+        assert_eq!(action("fold", &client_data), Ok("Fold".to_string()));
+        assert_eq!(action("raise 100", &client_data), Ok("Raise(100)".to_string()));
+        // the call counts towards what a raise costs
+        assert_eq!(action("raise 460", &client_data), Ok("Raise(460)".to_string()));
+        assert_eq!(action("raise 461", &client_data), Err("You don't have that much money, use \"allin\" instead."));
+        assert_eq!(action("raise lots", &client_data), Err("Usage: raise <amount over the current bet>"));
+        assert_eq!(action("bet 100", &client_data), Err("There's already a bet, use \"raise\"."));
+        assert_eq!(action("allin", &client_data), Ok("AddMoney(500)".to_string()));
+        assert_eq!(action("addmoney 60", &client_data), Ok("AddMoney(60)".to_string()));
+
+        // nothing to call once our 40 is in, and a raise with no bet opens the betting
+        let mut client_data = client_data;
+        client_data.in_game_info.as_mut().unwrap().round_contributions[0] = 40;
+        assert_eq!(action("call", &client_data), Err("There's nothing to call, use \"check\"."));
+        client_data.in_game_info.as_mut().unwrap().current_bet = 0;
+        assert_eq!(action("raise 50", &client_data), Ok("Bet(50)".to_string()));
+        assert_eq!(action("bet 50", &client_data), Ok("Bet(50)".to_string()));
+
+        client_data.in_game_info.as_mut().unwrap().current_turn = 1;
+        assert_eq!(action("check", &client_data), Err("It's not your turn!"));
+        client_data.in_game_info = None;
+        assert_eq!(action("check", &client_data), Err("There's no game going on!"));
+    }
+
+    #[test]
+    fn cards_render_with_suit_glyphs() {
+        let [ten_of_hearts, ace_of_spades, two_of_diamonds, king_of_clubs] = cards("Th As 2d Kc").try_into().unwrap();
+        assert_eq!(render_card(&ten_of_hearts, false), "T♥");
+        assert_eq!(render_card(&ten_of_hearts, true), "T\x1b[31m♥\x1b[0m");
+        assert_eq!(render_card(&two_of_diamonds, true), "2\x1b[31m♦\x1b[0m");
+        // black suits are never colored
+        assert_eq!(render_card(&ace_of_spades, true), "A♠");
+        assert_eq!(render_card(&king_of_clubs, true), "K♣");
+    }
+
+    fn step(winners: Vec<u8>, winnings: u32, pots: (u8, u8), eligible_players: Vec<u8>, win_reason: Option<(ShowdownDecidingFactor, u8)>) -> ShowdownStep {
+        ShowdownStep { winners, winnings, rake_taken: 0, pot_start_index: pots.0, pot_end_index: pots.1, eligible_players, win_reason }
+    }
+
+    #[test]
+    fn showdown_lines() {
+        let players: Vec<String> = ["alice", "bob", "carol"].iter().map(|name| name.to_string()).collect();
+        let steps = [
+            step(vec![0], 300, (0, 1), vec![0, 1, 2], Some((ShowdownDecidingFactor::Kicker(cards("Ks"), cards("Qh")), 2))),
+            step(vec![1], 50, (2, 2), vec![1], None),
+            step(vec![0, 2], 90, (3, 3), vec![0, 2], None),
+            step(vec![2], 40, (4, 4), vec![1, 2], Some((ShowdownDecidingFactor::Category, 1))),
+        ];
+        assert_eq!(showdown_summary(&players, &steps, false), [
+            "alice won pots 1 to 2 worth 300 with a higher kicker, K♠ against Q♥, over carol",
+            "bob won pot 3 worth 50 uncontested",
+            "alice, carol split pot 4 worth 90",
+            "carol won pot 5 worth 40 with a better kind of hand over bob",
+        ]);
+    }
+
+    #[test]
+    fn opponents_are_the_others_still_in() {
+        let player = |player_state| Player { username: String::new(), money: 1000, player_state };
+        let players = [player(PlayerState::InGame), player(PlayerState::Folded), player(PlayerState::InGame), player(PlayerState::Left), player(PlayerState::SittingOut), player(PlayerState::InGame)];
+        assert_eq!(count_opponents(&players, 0), 2);
+        // it doesn't matter whether we're still in ourselves
+        assert_eq!(count_opponents(&players, 1), 3);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use rand::{RngCore, SeedableRng, rngs::StdRng, thread_rng};
+
+use mini_holdem::{cards::{Card, DeckKind, HandCategory, all_distinct, get_best_hand_rank}, equity::{category_distribution, head_to_head}, game::get_shuffled_deck};
+
+const DEFAULT_ITERATIONS: u32 = 100000;
+
+struct Args {
+    iterations: u32,
+    seed: Option<u64>,
+    csv: bool,
+    versus: Option<([Card; 2], [Card; 2])>, // two hands to play against each other instead of counting categories
+    hole: Option<[Card; 2]>, // count categories with these private cards and a random board
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { iterations: DEFAULT_ITERATIONS, seed: None, csv: false, versus: None, hole: None };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--iterations" => {
+                let value = args.next().ok_or("--iterations needs a number")?;
+                parsed.iterations = value.parse().ok().filter(|&n| n > 0).ok_or(format!("invalid iteration count: {}", value))?;
+            },
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                parsed.seed = Some(value.parse().map_err(|_| format!("invalid seed: {}", value))?);
+            },
+            "--csv" => parsed.csv = true,
+            "versus" => {
+                let (Some(first), Some(second)) = (args.next(), args.next()) else { return Err("versus needs two hands like AsKs 7d2c".to_string()) };
+                let (first, second) = (parse_hand(&first)?, parse_hand(&second)?);
+                if !all_distinct(&[first[0], first[1], second[0], second[1]]) {
+                    return Err("the same card can't be in both hands".to_string());
+                }
+                parsed.versus = Some((first, second));
+            },
+            "hole" => {
+                let hand = args.next().ok_or("hole needs a hand like AsKs")?;
+                parsed.hole = Some(parse_hand(&hand)?);
+            },
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+// two cards written together, like AsKs
+fn parse_hand(hand: &str) -> Result<[Card; 2], String> {
+    let invalid = || format!("invalid hand: {}", hand);
+    if hand.len() != 4 || !hand.is_ascii() {
+        return Err(invalid());
+    }
+    Ok([hand[..2].parse().map_err(|_| invalid())?, hand[2..].parse().map_err(|_| invalid())?])
+}
+
+// deals seven random cards over and over and counts what the best hand out of them is
+fn simulate(iterations: u32, seed: u64) -> BTreeMap<HandCategory, u32> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut counts = BTreeMap::new();
+    for _ in 0..iterations {
+        let deck = get_shuffled_deck(DeckKind::Standard, rng.next_u64());
+        let cards: [Card; 7] = deck[..7].try_into().unwrap();
+        let (_, hand_rank) = get_best_hand_rank(&cards);
+        *counts.entry(hand_rank.category).or_insert(0) += 1;
+    }
+    counts
+}
+
+// a header and then every category, even the ones that never came up
+fn csv_rows(counts: &BTreeMap<HandCategory, u32>, iterations: u32) -> Vec<String> {
+    let mut rows = vec!["category,count,probability".to_string()];
+    for category in (0..10).filter_map(HandCategory::from_byte) {
+        let count = counts.get(&category).copied().unwrap_or(0);
+        rows.push(format!("{:?},{},{}", category, count, count as f64 / iterations as f64));
+    }
+    rows
+}
+
+fn main() {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: monte-carlo [--iterations <n>] [--seed <seed>] [--csv] [versus <hand> <hand> | hole <hand>]");
+            std::process::exit(1);
+        }
+    };
+
+    let seed = args.seed.unwrap_or_else(|| thread_rng().next_u64());
+
+    if let Some((first, second)) = args.versus {
+        let (wins, ties, losses) = head_to_head(first, second, Vec::new(), args.iterations, seed);
+        if args.csv {
+            println!("win,tie,loss");
+            println!("{},{},{}", wins, ties, losses);
+        } else {
+            println!("{} {} against {} {} over {} boards, seed {}", first[0], first[1], second[0], second[1], args.iterations, seed);
+            println!("win: {:.2}%  tie: {:.2}%  loss: {:.2}%", wins * 100.0, ties * 100.0, losses * 100.0);
+        }
+        return;
+    }
+
+    let counts = match args.hole {
+        // back to counts so both kinds of runs print the same way
+        Some(hole) => category_distribution(hole, args.iterations, seed).into_iter().map(|(category, p)| (category, (p * args.iterations as f64).round() as u32)).collect(),
+        None => simulate(args.iterations, seed),
+    };
+
+    if args.csv {
+        for row in csv_rows(&counts, args.iterations) {
+            println!("{}", row);
+        }
+        return;
+    }
+
+    match args.hole {
+        Some(hole) => println!("{} boards with {} {}, seed {}", args.iterations, hole[0], hole[1], seed),
+        None => println!("{} hands of seven random cards, seed {}", args.iterations, seed),
+    }
+    for category in (0..10).filter_map(HandCategory::from_byte) {
+        let count = counts.get(&category).copied().unwrap_or(0);
+        println!("{:?}: {} ({:.4}%)", category, count, count as f64 / args.iterations as f64 * 100.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &str) -> Result<Args, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn command_line_flags() {
+        let parsed = args("--iterations 500 --seed 42 --csv versus AsKs 7d2c").unwrap();
+        assert_eq!((parsed.iterations, parsed.seed, parsed.csv), (500, Some(42), true));
+        let (first, second) = parsed.versus.unwrap();
+        assert!(first[0].full_eq(&"As".parse().unwrap()) && second[1].full_eq(&"2c".parse().unwrap()));
+        assert!(args("hole QhQd").unwrap().hole.is_some());
+        assert_eq!(args("").unwrap().iterations, DEFAULT_ITERATIONS);
+
+        assert_eq!(args("--iterations 0").err().unwrap(), "invalid iteration count: 0");
+        assert_eq!(args("--seed x").err().unwrap(), "invalid seed: x");
+        assert_eq!(args("versus AsKs").err().unwrap(), "versus needs two hands like AsKs 7d2c");
+        assert_eq!(args("versus AsKs AsQd").err().unwrap(), "the same card can't be in both hands");
+        assert_eq!(args("hole AsK").err().unwrap(), "invalid hand: AsK");
+        assert_eq!(args("--fast").err().unwrap(), "unknown argument: --fast");
+    }
+
+    #[test]
+    fn csv_has_a_row_for_every_category() {
+        let counts = BTreeMap::from([(HandCategory::OnePair, 3), (HandCategory::HighCard, 1)]);
+        let rows = csv_rows(&counts, 4);
+        assert_eq!(rows.len(), 11);
+        assert_eq!(rows[0], "category,count,probability");
+        assert_eq!(rows[1], "HighCard,1,0.25");
+        assert_eq!(rows[2], "OnePair,3,0.75");
+        assert_eq!(rows[10], "RoyalFlush,0,0");
+    }
+}
@@ -1,13 +1,47 @@
-use std::{collections::{HashMap, HashSet}, net::{SocketAddr, TcpListener}, sync::mpsc::{self, Sender}, thread};
+use std::{collections::{HashMap, HashSet}, net::{SocketAddr, TcpListener}, sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc::{self, Receiver, Sender}}, thread, time::{Duration, Instant}};
 
-use mini_holdem::{events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound}, game::{Game, make_game}, networking::handle_client};
+use log::{error, info, warn};
+use rand::{RngCore, thread_rng};
+
+use mini_holdem::{bot::Bot, events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound}, game::{ActionError, BuyInConfig, Game, GameConfig, MAX_PLAYERS, blind_seats, make_game}, networking::{Transport, frame_packet, handle_client}, protocol::{MAX_CHAT_LENGTH, PROTOCOL_VERSION, encode_client_bound, validate_username}, strategy::Strategy};
+
+#[cfg(feature = "serde")]
+use std::{io::{Error, ErrorKind}, path::{Path, PathBuf}};
+#[cfg(feature = "serde")]
+use mini_holdem::game::GameSnapshot;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 type ClientChannels = HashMap<u64, Sender<ClientBound>>;
 
+const MIN_PLAYERS: usize = 3;
+const BOT_THINK_TIME: Duration = Duration::from_millis(800);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(30);
+const RECONNECT_GRACE: Duration = Duration::from_secs(30);
+const TURN_TIME: Duration = Duration::from_secs(60);
+const TIME_BANK: Duration = Duration::from_secs(30);
+const MUCK_TIME: Duration = Duration::from_secs(10); // beaten players who take longer muck
+const START_COUNTDOWN: Duration = Duration::from_secs(5);
+const MAX_CONNECTIONS: usize = 256; // every connection gets its own thread
+const REFUSAL_READ_LIMIT: usize = 1024; // bytes read from a connection that's turned away before it's closed
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2); // how long clients get to leave on their own before being cut off
+
 struct User {
     money: u32,
     username: String,
     ready: bool,
+    bot: Option<Box<dyn Strategy>>, // bots have no connection, the server plays for them
+    session_token: u64,
+    disconnected_at: Option<Instant>, // the seat is kept for a while in case they reconnect
+    sitting_out: bool, // keeps the seat without being dealt in
+    time_bank: Duration, // what's left of it, it lasts for as long as they're at the table
+    missed_blinds: u32, // owed for sitting out hands, posted as dead money when they're dealt in again
+}
+impl User {
+    // players short of the minimum stack sit out until they rebuy
+    fn sits_out(&self, config: &GameConfig) -> bool {
+        self.sitting_out || !config.buy_in.can_be_dealt(self.money)
+    }
 }
 
 struct Lobby {
@@ -15,83 +49,460 @@ struct Lobby {
     player_order: Vec<u64>,
     network_to_game: HashMap<u64, u8>,
     default_money: u32,
+    config: GameConfig,
+    button: u8, // where the button goes next hand
     game: Option<Game>,
     queued_for_removal: HashSet<u8>,
+    fill_with_bots: bool,
+    next_bot_id: u64, // counts down from u64::MAX so it never meets the connection ids
+    bot_turn_started: Option<Instant>,
+    showdown_started: Option<Instant>, // when the beaten players started deciding to muck or show
+    start_countdown: Duration, // how long everyone being ready has to last before the hand is dealt
+    countdown_started: Option<Instant>,
+    spectators: HashSet<u64>, // get everything public but have no seat
+    restored_at: Option<Instant>, // after a restart nobody is played for until they had the chance to reconnect
+}
+impl Lobby {
+    fn new(fill_with_bots: bool, default_money: u32, buy_in: BuyInConfig, start_countdown: Duration, run_it_twice: bool) -> Self {
+        Lobby { players: HashMap::new(), player_order: Vec::new(), network_to_game: HashMap::new(), default_money, config: GameConfig { min_players: MIN_PLAYERS, turn_time: Some(TURN_TIME), time_bank: TIME_BANK, buy_in, run_it_twice, ..Default::default() }, button: 0, game: None, queued_for_removal: HashSet::new(), fill_with_bots, next_bot_id: u64::MAX, bot_turn_started: None, showdown_started: None, start_countdown, countdown_started: None, spectators: HashSet::new(), restored_at: None }
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self, path: &Path) -> std::io::Result<()> {
+        let players = self.player_order.iter().map(|id| &self.players[id]).map(|user| SavedUser { username: user.username.clone(), money: user.money, ready: user.ready, bot: user.bot.is_some(), session_token: user.session_token, sitting_out: user.sitting_out, time_bank: user.time_bank, missed_blinds: user.missed_blinds }).collect();
+        let saved = SavedLobby { players, default_money: self.default_money, config: self.config, button: self.button, fill_with_bots: self.fill_with_bots, start_countdown: self.start_countdown, game: self.game.as_ref().map(Game::full_snapshot), queued_for_removal: self.queued_for_removal.iter().copied().collect() };
+        std::fs::write(path, serde_json::to_string_pretty(&saved).map_err(Error::other)?)
+    }
+
+    // the connections are gone, so everyone gets a placeholder id and the usual grace to come back with their session token
+    // a hand saved in the middle of its showdown is called off, the stacks are still the ones from before it
+    #[cfg(feature = "serde")]
+    fn load(path: &Path) -> std::io::Result<Lobby> {
+        let saved: SavedLobby = serde_json::from_str(&std::fs::read_to_string(path)?).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        let mut lobby = Lobby::new(saved.fill_with_bots, saved.default_money, saved.config.buy_in, saved.start_countdown, saved.config.run_it_twice);
+        lobby.config = saved.config;
+        lobby.button = saved.button;
+        lobby.restored_at = Some(Instant::now());
+        for user in saved.players {
+            let id = lobby.next_bot_id;
+            lobby.next_bot_id -= 1;
+            let bot: Option<Box<dyn Strategy>> = if user.bot { Some(Box::new(Bot)) } else { None };
+            lobby.players.insert(id, User { money: user.money, username: user.username, ready: user.ready, disconnected_at: bot.is_none().then(Instant::now), bot, session_token: user.session_token, sitting_out: user.sitting_out, time_bank: user.time_bank, missed_blinds: user.missed_blinds });
+            lobby.player_order.push(id);
+        }
+        if let Some(snapshot) = saved.game.filter(|snapshot| snapshot.current_phase < 4) {
+            let mut game = Game::from_snapshot(&snapshot).ok_or_else(|| Error::new(ErrorKind::InvalidData, "the saved hand is missing cards"))?;
+            for (seat, id) in lobby.player_order.iter().take(game.players.len()).enumerate() {
+                lobby.network_to_game.insert(*id, seat as u8);
+                game.set_time_bank(seat as u8, lobby.players[id].time_bank);
+            }
+            lobby.game = Some(game);
+            lobby.queued_for_removal = saved.queued_for_removal.into_iter().collect();
+        }
+        Ok(lobby)
+    }
+}
+
+// what a restart keeps of a lobby
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedLobby {
+    players: Vec<SavedUser>, // in seat order
+    default_money: u32,
+    config: GameConfig,
+    button: u8,
+    fill_with_bots: bool,
+    start_countdown: Duration,
+    game: Option<GameSnapshot>,
+    queued_for_removal: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedUser {
+    username: String,
+    money: u32,
+    ready: bool,
+    bot: bool,
+    session_token: u64,
+    sitting_out: bool,
+    time_bank: Duration,
+    #[serde(default)]
+    missed_blinds: u32,
+}
+
+// every room is its own lobby with its own table
+struct Server {
+    rooms: HashMap<u32, Lobby>,
+    client_rooms: HashMap<u64, u32>, // connections that aren't in here are in room 0
+    next_room_id: u32,
+    greeted: HashSet<u64>, // connections that sent a matching Hello, nothing else is listened to before that
+    fill_with_bots: bool,
+    default_money: u32,
+    buy_in: BuyInConfig,
+    start_countdown: Duration,
+    run_it_twice: bool,
+}
+impl Server {
+    fn room_of(&self, client: u64) -> u32 {
+        self.client_rooms.get(&client).copied().unwrap_or(0)
+    }
+
+    fn is_seated(&self, client: u64) -> bool {
+        self.rooms.get(&self.room_of(client)).is_some_and(|lobby| lobby.players.contains_key(&client))
+    }
+
+    // only for connections without a seat, they come in as spectators
+    fn move_to_room(&mut self, client: u64, room: u32, client_channels: &ClientChannels) {
+        if let Some(lobby) = self.rooms.get_mut(&self.room_of(client)) {
+            lobby.spectators.remove(&client);
+        }
+        self.client_rooms.insert(client, room);
+        if let Some(lobby) = self.rooms.get_mut(&room) {
+            lobby.spectators.insert(client);
+            send_player_list_update(lobby, client_channels, Some(client));
+        }
+    }
+}
+
+struct Args {
+    bind: SocketAddr,
+    default_money: u32,
+    min_buy_in: Option<u32>, // both default to the default money
+    max_buy_in: Option<u32>,
+    min_stack: u32,
+    start_countdown: Duration,
+    fill_with_bots: bool,
+    run_it_twice: bool,
+    max_connections: usize,
+    #[cfg(feature = "serde")]
+    state: Option<PathBuf>, // the first room is saved here on shutdown and loaded back on startup
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args { bind: SocketAddr::from(([0, 0, 0, 0], 9194)), default_money: 1000, min_buy_in: None, max_buy_in: None, min_stack: 1, start_countdown: START_COUNTDOWN, fill_with_bots: false, run_it_twice: false, max_connections: MAX_CONNECTIONS, #[cfg(feature = "serde")] state: None };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => {
+                let value = args.next().ok_or("--bind needs an address like 127.0.0.1:7000")?;
+                parsed.bind = value.parse().map_err(|_| format!("invalid bind address: {}", value))?;
+            },
+            "--default-money" => {
+                let value = args.next().ok_or("--default-money needs an amount")?;
+                parsed.default_money = value.parse().ok().filter(|&money| money > 0).ok_or(format!("invalid default money: {}", value))?;
+            },
+            "--min-buy-in" => {
+                let value = args.next().ok_or("--min-buy-in needs an amount")?;
+                parsed.min_buy_in = Some(value.parse().ok().filter(|&money| money > 0).ok_or(format!("invalid min buy-in: {}", value))?);
+            },
+            "--max-buy-in" => {
+                let value = args.next().ok_or("--max-buy-in needs an amount")?;
+                parsed.max_buy_in = Some(value.parse().ok().filter(|&money| money > 0).ok_or(format!("invalid max buy-in: {}", value))?);
+            },
+            "--min-stack" => {
+                let value = args.next().ok_or("--min-stack needs an amount")?;
+                parsed.min_stack = value.parse().ok().filter(|&money| money > 0).ok_or(format!("invalid min stack: {}", value))?;
+            },
+            "--countdown" => {
+                let value = args.next().ok_or("--countdown needs a number of seconds")?;
+                let secs: u8 = value.parse().map_err(|_| format!("invalid countdown: {}", value))?;
+                parsed.start_countdown = Duration::from_secs(secs as u64);
+            },
+            "--bots" => parsed.fill_with_bots = true,
+            "--run-it-twice" => parsed.run_it_twice = true,
+            "--max-connections" => {
+                let value = args.next().ok_or("--max-connections needs a number")?;
+                parsed.max_connections = value.parse().ok().filter(|&n| n > 0).ok_or(format!("invalid max connections: {}", value))?;
+            },
+            #[cfg(feature = "serde")]
+            "--state" => parsed.state = Some(args.next().ok_or("--state needs a file")?.into()),
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+    if parsed.min_buy_in.is_some_and(|min| min > parsed.default_money) {
+        return Err("the min buy-in can't be above the default money".to_string());
+    }
+    if parsed.max_buy_in.is_some_and(|max| max < parsed.default_money) {
+        return Err("the max buy-in can't be below the default money".to_string());
+    }
+    if parsed.min_stack > parsed.default_money {
+        return Err("the min stack can't be above the default money".to_string());
+    }
+    Ok(parsed)
 }
 
 fn main() -> std::io::Result<()> {
-    let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], 9194))).expect("Couldn't bind to 0.0.0.0:9194.");
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: server [--bind <ip:port>] [--default-money <amount>] [--min-buy-in <amount>] [--max-buy-in <amount>] [--min-stack <amount>] [--countdown <secs>] [--bots] [--run-it-twice] [--max-connections <n>]");
+            std::process::exit(1);
+        }
+    };
+
+    env_logger::init(); // quiet unless RUST_LOG is set, RUST_LOG=info logs every hand
+
+    let listener = TcpListener::bind(args.bind).unwrap_or_else(|_| panic!("Couldn't bind to {}.", args.bind));
     listener.set_nonblocking(true)?;
-    println!("Bound to 0.0.0.0 with port 9194.");
+    println!("Bound to {}.", args.bind);
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let handler_flag = shutdown.clone();
+    ctrlc::set_handler(move || handler_flag.store(true, Ordering::SeqCst)).expect("Couldn't set the Ctrl-C handler.");
 
     let mut client_channels: HashMap<u64, Sender<ClientBound>> = HashMap::new();
 
     let (server_bound_sender, server_bound_receiver) = mpsc::channel();
 
-    let mut lobby = Lobby { players: HashMap::new(), player_order: Vec::new(), network_to_game: HashMap::new(), default_money: 1000, game: None, queued_for_removal: HashSet::new() };
+    let buy_in = BuyInConfig { min: args.min_buy_in.unwrap_or(args.default_money), max: args.max_buy_in.unwrap_or(args.default_money), min_stack: args.min_stack };
+    let mut server = Server { rooms: HashMap::from([(0, Lobby::new(args.fill_with_bots, args.default_money, buy_in, args.start_countdown, args.run_it_twice))]), client_rooms: HashMap::new(), next_room_id: 1, greeted: HashSet::new(), fill_with_bots: args.fill_with_bots, default_money: args.default_money, buy_in, start_countdown: args.start_countdown, run_it_twice: args.run_it_twice };
+    #[cfg(feature = "serde")]
+    if let Some(path) = args.state.as_deref().filter(|path| path.exists()) {
+        match Lobby::load(path) {
+            Ok(lobby) => {
+                info!("loaded the lobby from {}", path.display());
+                server.rooms.insert(0, lobby);
+            },
+            Err(e) => error!("couldn't load the lobby from {}: {}", path.display(), e),
+        }
+    }
     let mut next_id: u64 = 0;
+    let connections = Arc::new(AtomicUsize::new(0)); // connection threads still running, counted down by the threads themselves
 
-    loop {
+    while !shutdown.load(Ordering::SeqCst) {
         match listener.accept() {
-            Ok((stream, _)) => {
-                let id = next_id;
-                next_id += 1;
-                let (tx, rx) = mpsc::channel();
-                client_channels.insert(id, tx.clone());
-                let cloned = server_bound_sender.clone();
-                thread::spawn(move || {
-                    if let Err(e) = handle_client(id, stream, rx, cloned) {
-                        println!("Error handling client id {}: {}", id, e);
-                    }
-                });
+            Ok((stream, address)) => {
+                if !accept_connection(stream, &mut next_id, &connections, args.max_connections, &mut client_channels, &server_bound_sender) {
+                    info!("turned away {}, the server is full", address);
+                }
             },
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {},
             Err(e) => return Err(e),
         }
 
+        run_rooms(&mut server, &mut client_channels, &server_bound_receiver);
+        thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    println!("Shutting down.");
+    #[cfg(feature = "serde")]
+    if let Some(path) = &args.state && let Err(e) = server.rooms[&0].save(path) {
+        error!("couldn't save the lobby to {}: {}", path.display(), e);
+    }
+    shut_down(&mut client_channels, &server_bound_receiver, SHUTDOWN_GRACE);
+    Ok(())
+}
+
+// gives a new connection its own thread under the next id, false if max_connections are already open and it was turned away
+fn accept_connection(stream: impl Transport + Send + 'static, next_id: &mut u64, connections: &Arc<AtomicUsize>, max_connections: usize, client_channels: &mut ClientChannels, server_bound_sender: &Sender<(u64, ServerBound)>) -> bool {
+    if connections.load(Ordering::SeqCst) >= max_connections {
+        thread::spawn(move || turn_away(stream));
+        return false;
+    }
+    connections.fetch_add(1, Ordering::SeqCst);
+    let connections = connections.clone();
+    let id = *next_id;
+    *next_id += 1;
+    let (tx, rx) = mpsc::channel();
+    client_channels.insert(id, tx);
+    let server_bound_sender = server_bound_sender.clone();
+    thread::spawn(move || {
+        if let Err(e) = handle_client(id, stream, rx, server_bound_sender, CLIENT_TIMEOUT) {
+            warn!("error handling client id {}: {}", id, e);
+        }
+        connections.fetch_sub(1, Ordering::SeqCst);
+    });
+    true
+}
+
+// on its own thread, so a client that keeps sending can't hold up the accept loop
+fn turn_away(mut stream: impl Transport) {
+    // closing with the client's hello still unread resets the connection, which can lose the message before it's read
+    // whatever already arrived is read, up to REFUSAL_READ_LIMIT so a client that keeps sending can't keep the thread either
+    let _ = stream.set_nonblocking(true);
+    let mut unread = REFUSAL_READ_LIMIT;
+    while unread > 0 && let Ok(n @ 1..) = stream.read(&mut [0; 64]) {
+        unread = unread.saturating_sub(n);
+    }
+    let _ = stream.write_all(&frame_packet(encode_client_bound(ClientBound::ServerFull)));
+}
+
+// one pass of the main loop besides taking new connections: what the clients sent, then the clocks and the bots
+fn run_rooms(server: &mut Server, client_channels: &mut ClientChannels, server_bound_receiver: &Receiver<(u64, ServerBound)>) {
+    for (client_id, event) in server_bound_receiver.try_iter() {
+        handle_event(event, client_id, server, client_channels);
+    }
+
+    for lobby in server.rooms.values_mut() {
+        drive_showdown(lobby, client_channels);
+        drive_countdown(lobby, client_channels);
+        drive_bots(lobby, client_channels);
+        if let Some(game) = lobby.game.as_mut() {
+            let was_using_time_bank = game.using_time_bank();
+            let events = game.check_timeout(Instant::now());
+            if !was_using_time_bank && game.using_time_bank() {
+                let (player, remaining) = (game.current_turn, game.time_bank(game.current_turn).as_secs() as u32);
+                broadcast_event(lobby, client_channels, ClientBound::TimeBankUsed { player, remaining });
+            }
+            if !events.is_empty() {
+                apply_game_events(events, lobby, client_channels);
+            }
+        }
+        expire_disconnected(lobby, client_channels);
+    }
+    // rooms besides the first one close once nobody is left in them
+    server.rooms.retain(|&id, lobby| id == 0 || !lobby.players.is_empty() || !lobby.spectators.is_empty());
+}
+
+// tells everyone the server is going away, then waits for them to leave until the grace runs out
+// whoever is still connected after that gets their channel dropped, which closes the connection
+fn shut_down(client_channels: &mut ClientChannels, server_bound_receiver: &Receiver<(u64, ServerBound)>, grace: Duration) {
+    for channel in client_channels.values() {
+        let _ = channel.send(ClientBound::ServerShutdown);
+    }
+    let deadline = Instant::now() + grace;
+    while !client_channels.is_empty() && Instant::now() < deadline {
         for (client_id, event) in server_bound_receiver.try_iter() {
-            handle_event(event, client_id, &mut lobby, &mut client_channels);
+            if matches!(event, ServerBound::Disconnect) {
+                client_channels.remove(&client_id);
+            }
         }
+        thread::sleep(Duration::from_millis(1));
+    }
+    client_channels.clear();
+}
 
-        thread::sleep(std::time::Duration::from_millis(1));
+fn handle_event(event: ServerBound, client: u64, server: &mut Server, client_channels: &mut ClientChannels) {
+    if !server.greeted.contains(&client) && !matches!(event, ServerBound::Hello(_) | ServerBound::Disconnect) {
+        return;
+    }
+
+    match event {
+        ServerBound::Hello(version) => {
+            let accepted = version == PROTOCOL_VERSION;
+            if let Some(channel) = client_channels.get(&client) {
+                let _ = channel.send(ClientBound::Welcome { version: PROTOCOL_VERSION, accepted });
+            }
+            if accepted {
+                server.greeted.insert(client);
+            } else {
+                // dropping the channel makes the connection close once the welcome is out
+                client_channels.remove(&client);
+            }
+        },
+        ServerBound::Disconnect => {
+            client_channels.remove(&client);
+            server.greeted.remove(&client);
+            let room = server.client_rooms.remove(&client).unwrap_or(0);
+            if let Some(lobby) = server.rooms.get_mut(&room) {
+                handle_lobby_event(ServerBound::Disconnect, client, lobby, client_channels);
+            }
+        },
+        ServerBound::CreateRoom => {
+            if server.is_seated(client) {
+                return;
+            }
+            let room = server.next_room_id;
+            server.next_room_id += 1;
+            server.rooms.insert(room, Lobby::new(server.fill_with_bots, server.default_money, server.buy_in, server.start_countdown, server.run_it_twice));
+            if let Some(channel) = client_channels.get(&client) {
+                let _ = channel.send(ClientBound::RoomCreated(room));
+            }
+            server.move_to_room(client, room, client_channels);
+        },
+        ServerBound::JoinRoom(room) => {
+            if !server.is_seated(client) && server.rooms.contains_key(&room) {
+                server.move_to_room(client, room, client_channels);
+            }
+        },
+        ServerBound::Reconnect(token) => {
+            if server.is_seated(client) {
+                return;
+            }
+            let Some(room) = server.rooms.iter().find(|(_, lobby)| lobby.players.values().any(|u| u.disconnected_at.is_some() && u.session_token == token)).map(|(&id, _)| id) else { return };
+            if let Some(lobby) = server.rooms.get_mut(&server.room_of(client)) {
+                lobby.spectators.remove(&client);
+            }
+            server.client_rooms.insert(client, room);
+            if let Some(lobby) = server.rooms.get_mut(&room) {
+                handle_lobby_event(ServerBound::Reconnect(token), client, lobby, client_channels);
+            }
+        },
+        event => {
+            if let Some(lobby) = server.rooms.get_mut(&server.room_of(client)) {
+                handle_lobby_event(event, client, lobby, client_channels);
+            }
+        }
     }
 }
 
-fn handle_event(event: ServerBound, client: u64, lobby: &mut Lobby, client_channels: &mut ClientChannels) {
+// everything that happens inside the client's room
+fn handle_lobby_event(event: ServerBound, client: u64, lobby: &mut Lobby, client_channels: &mut ClientChannels) {
     match event {
-        ServerBound::Login(name) => {
-            if !name.is_ascii() || name.len() > 16 || name.len() < 3 || name.contains(" ") || lobby.players.values().any(|n| n.username.eq_ignore_ascii_case(&name)) {
+        ServerBound::Login { username: name, buy_in } => {
+            // a second login from the same connection would seat it twice
+            let rejection = if lobby.players.contains_key(&client) {
+                Err("You're already seated at this table!")
+            } else if lobby.players.len() >= MAX_PLAYERS {
+                Err("The table is full!")
+            } else if lobby.players.values().any(|n| n.username.eq_ignore_ascii_case(&name)) {
+                Err("This username is already taken!")
+            } else {
+                validate_username(&name)
+            };
+            if let Err(reason) = rejection {
+                if let Some(channel) = client_channels.get(&client) {
+                    let _ = channel.send(ClientBound::LoginRejected(reason.to_string()));
+                }
                 return;
             }
-            lobby.players.insert(client, User { money: lobby.default_money, username: name.clone(), ready: false });
+            lobby.spectators.remove(&client);
+            let session_token = thread_rng().next_u64();
+            let money = buy_in.map_or(lobby.default_money, |buy_in| lobby.config.buy_in.clamp(buy_in));
+            lobby.players.insert(client, User { money, username: name.clone(), ready: false, bot: None, session_token, disconnected_at: None, sitting_out: false, time_bank: lobby.config.time_bank, missed_blinds: 0 });
             lobby.player_order.push(client);
+            if let Some(channel) = client_channels.get(&client) {
+                let _ = channel.send(ClientBound::SessionToken(session_token));
+            }
             send_player_list_update(lobby, client_channels, None);
-            broadcast_event(client_channels, ClientBound::PlayerJoined(name));
+            broadcast_event(lobby, client_channels, ClientBound::PlayerJoined(name));
+            update_bots(lobby, client_channels);
         },
         ServerBound::Disconnect => {
-            client_channels.remove(&client);
-
-            if let Some(player) = lobby.players.get(&client) {
-                broadcast_event(client_channels, ClientBound::PlayerLeft(player.username.clone()));
+            lobby.spectators.remove(&client);
+            if let Some(user) = lobby.players.get(&client) {
+                warn!("{} disconnected", user.username);
             }
 
-            if let Some(&id) = lobby.network_to_game.get(&client) && let Some(game) = &mut lobby.game {
-                lobby.queued_for_removal.insert(id);
-                broadcast_event(client_channels, ClientBound::GameEvent(GameEvent::InGamePlayerLeave(id)));
-                if id == game.current_turn {
-                    advance_game(GamePlayerAction::Fold, lobby, client_channels);
-                } else {
-                    (*game.player_mut(id)).has_folded = true;
-                }
-            } else {
-                lobby.players.remove(&client);
-                lobby.player_order.retain(|&p| p != client);
+            // the seat stays until RECONNECT_GRACE runs out, the server checks or folds for them meanwhile
+            if let Some(user) = lobby.players.get_mut(&client) {
+                user.disconnected_at = Some(Instant::now());
+                user.ready = false;
                 send_player_list_update(lobby, client_channels, None);
-                check_for_game_start(client_channels, lobby);
             }
+        },
+        ServerBound::Reconnect(token) => {
+            if lobby.players.contains_key(&client) {
+                return;
+            }
+            let Some(old_id) = lobby.players.iter().find(|(_, u)| u.disconnected_at.is_some() && u.session_token == token).map(|(&id, _)| id) else { return };
+
+            let mut user = lobby.players.remove(&old_id).unwrap();
+            user.disconnected_at = None;
+            lobby.players.insert(client, user);
+            for id in lobby.player_order.iter_mut().filter(|id| **id == old_id) {
+                *id = client;
+            }
+            if let Some(seat) = lobby.network_to_game.remove(&old_id) {
+                lobby.network_to_game.insert(client, seat);
+            }
+            lobby.spectators.remove(&client);
 
-            lobby.network_to_game.remove(&client);
+            send_player_list_update(lobby, client_channels, None);
+            send_game_state(client, lobby, client_channels);
         },
+        ServerBound::RequestGameState => send_game_state(client, lobby, client_channels),
         ServerBound::Ready(ready) => {
             if let Some(user) = lobby.players.get_mut(&client) {
                 user.ready = ready;
@@ -101,85 +512,472 @@ fn handle_event(event: ServerBound, client: u64, lobby: &mut Lobby, client_chann
 
         },
         ServerBound::GameAction(action) => {
-            if let Some(game) = lobby.game.as_ref() && let Some(&id) = lobby.network_to_game.get(&client) && game.current_turn == id {
-                advance_game(action, lobby, client_channels);
+            if let Some(game) = lobby.game.as_mut() && let Some(&id) = lobby.network_to_game.get(&client) {
+                if matches!(action, GamePlayerAction::Muck | GamePlayerAction::Show | GamePlayerAction::RunItTwice(_)) {
+                    match game.advance_game_as(id, action.clone()) {
+                        Ok(events) => apply_game_events(events, lobby, client_channels),
+                        Err(e) => {
+                            warn!("seat {} tried to {:?}: {}", id, action, e);
+                            send_action_error(client, &e, client_channels);
+                        },
+                    }
+                } else if game.current_turn != id {
+                    warn!("seat {} tried to {:?} out of turn", id, action);
+                    send_action_error(client, &ActionError::NotYourTurn, client_channels);
+                } else if let Err(e) = advance_game(action.clone(), lobby, client_channels) {
+                    warn!("seat {} tried an illegal {:?}: {}", id, action, e);
+                    send_action_error(client, &e, client_channels);
+                }
+            }
+        },
+        ServerBound::SitOut(sitting_out) => {
+            // someone sitting out in the middle of a hand still plays it out
+            if let Some(user) = lobby.players.get_mut(&client) {
+                user.sitting_out = sitting_out;
+                send_player_list_update(lobby, client_channels, None);
+                check_for_game_start(client_channels, lobby);
+            }
+        },
+        ServerBound::Rebuy(money) => {
+            let Some(user) = lobby.players.get_mut(&client) else { return };
+            // stacks are only touched between hands, the game has its own copy of them
+            let accepted = lobby.game.is_none() && money > 0 && user.money.checked_add(money).is_some_and(|total| lobby.config.buy_in.allows(total));
+            if accepted {
+                user.money += money;
+            }
+            if let Some(channel) = client_channels.get(&client) {
+                let _ = channel.send(ClientBound::RebuyResult { accepted, money: user.money });
+            }
+            if accepted {
+                send_player_list_update(lobby, client_channels, None);
+                check_for_game_start(client_channels, lobby); // they may have been short of the minimum stack
+            }
+        },
+        ServerBound::Spectate => {
+            if !lobby.players.contains_key(&client) {
+                lobby.spectators.insert(client);
+                send_player_list_update(lobby, client_channels, Some(client));
             }
         },
         ServerBound::GetPlayerList => {
             send_player_list_update(lobby, client_channels, Some(client));
-        }
+        },
+        ServerBound::Chat(message) => {
+            if message.is_empty() || message.len() > MAX_CHAT_LENGTH {
+                return;
+            }
+            // only players in the lobby can talk, everyone else in it hears it
+            if let Some(index) = lobby.player_order.iter().position(|&p| p == client) {
+                for id in lobby.player_order.iter().chain(&lobby.spectators).filter(|&&id| id != client) {
+                    if let Some(channel) = client_channels.get(id) {
+                        let _ = channel.send(ClientBound::ChatBroadcast { player: index as u8, message: message.clone() });
+                    }
+                }
+            }
+        },
+        ServerBound::Admin(command) => {
+            if lobby.game.is_none() && lobby.player_order.first() == Some(&client) {
+                handle_admin_command(&command, client, lobby, client_channels);
+            }
+        },
+        ServerBound::Ping | ServerBound::Pong => {}, // answered by the connection's own thread
+        ServerBound::Hello(_) | ServerBound::JoinRoom(_) | ServerBound::CreateRoom => {} // not about any one room
+    }
+}
+
+// seat 0 runs the lobby, malformed commands are ignored
+fn handle_admin_command(command: &str, client: u64, lobby: &mut Lobby, client_channels: &ClientChannels) {
+    let args: Vec<&str> = command.split_whitespace().collect();
+    match args.as_slice() {
+        ["kick", seat] => {
+            let Some(&id) = seat.parse::<usize>().ok().and_then(|seat| lobby.player_order.get(seat)) else { return };
+            let bot = lobby.players.get(&id).is_some_and(|u| u.bot.is_some());
+            remove_user(id, lobby, client_channels);
+            // a kicked human stays around to watch
+            if !bot && id != client && client_channels.contains_key(&id) {
+                lobby.spectators.insert(id);
+                send_player_list_update(lobby, client_channels, Some(id));
+            }
+        },
+        ["setmoney", seat, amount] => {
+            let Ok(amount) = amount.parse::<u32>() else { return };
+            if amount == 0 {
+                return;
+            }
+            let amount = lobby.config.buy_in.clamp(amount);
+            let Some(user) = seat.parse::<usize>().ok().and_then(|seat| lobby.player_order.get(seat)).and_then(|id| lobby.players.get_mut(id)) else { return };
+            user.money = amount;
+            send_player_list_update(lobby, client_channels, None);
+        },
+        // everyone's stack, and what anyone who joins later sits down with
+        ["startmoney", amount] => {
+            let Ok(amount) = amount.parse::<u32>() else { return };
+            if amount == 0 {
+                return;
+            }
+            let amount = lobby.config.buy_in.clamp(amount);
+            lobby.default_money = amount;
+            for user in lobby.players.values_mut() {
+                user.money = amount;
+            }
+            send_player_list_update(lobby, client_channels, None);
+        },
+        ["blinds", small_blind, big_blind] => {
+            let (Ok(small_blind), Ok(big_blind)) = (small_blind.parse::<u32>(), big_blind.parse::<u32>()) else { return };
+            if big_blind == 0 || small_blind > big_blind {
+                return;
+            }
+            lobby.config.blinds.small_blind = small_blind;
+            lobby.config.blinds.big_blind = big_blind;
+        },
+        _ => {}
     }
 }
 
+fn can_start_game(lobby: &Lobby) -> bool {
+    let dealt_in = || lobby.players.values().filter(|user| !user.sits_out(&lobby.config));
+    lobby.game.is_none() && dealt_in().all(|user| user.ready) && dealt_in().count() >= lobby.config.min_players
+}
+
+// starts the countdown once everyone is ready and calls it off as soon as that stops being true
 fn check_for_game_start(client_channels: &ClientChannels, lobby: &mut Lobby) {
-    if lobby.players.iter().all(|(_, user)| user.ready) && lobby.players.len() >= 3 {
-        let mut list = Vec::new();
-        for (game_id, &network_id) in lobby.player_order.iter().enumerate() {
-            let player = lobby.players.get(&network_id).unwrap();
-            list.push(player.money);
-            lobby.network_to_game.insert(network_id, game_id as u8);
-        }
+    match (can_start_game(lobby), lobby.countdown_started) {
+        (true, None) if lobby.start_countdown.is_zero() => start_game(client_channels, lobby),
+        (true, None) => {
+            lobby.countdown_started = Some(Instant::now());
+            broadcast_event(lobby, client_channels, ClientBound::Countdown(lobby.start_countdown.as_secs() as u8));
+        },
+        (false, Some(_)) => {
+            lobby.countdown_started = None;
+            broadcast_event(lobby, client_channels, ClientBound::Countdown(0));
+        },
+        _ => {}
+    }
+}
+
+fn drive_countdown(lobby: &mut Lobby, client_channels: &ClientChannels) {
+    let Some(started) = lobby.countdown_started else { return };
+    if !can_start_game(lobby) {
+        // not every change to the lobby goes through check_for_game_start
+        check_for_game_start(client_channels, lobby);
+    } else if started.elapsed() >= lobby.start_countdown {
+        lobby.countdown_started = None;
+        start_game(client_channels, lobby);
+    }
+}
 
-        if let Some(game) = make_game(list) {
-            for (id, player) in game.players.iter().enumerate() {
-                let _ = client_channels.get(&lobby.player_order[id]).unwrap().send(ClientBound::GameStarted(player.private_cards));
+fn start_game(client_channels: &ClientChannels, lobby: &mut Lobby) {
+    // the game's seats are the first places in the player list, so whoever sits out goes to the back of it
+    let order_before = lobby.player_order.clone();
+    lobby.player_order.sort_by_key(|id| lobby.players[id].sits_out(&lobby.config));
+
+    let mut list = Vec::new();
+    for (game_id, &network_id) in lobby.player_order.iter().filter(|id| !lobby.players[id].sits_out(&lobby.config)).enumerate() {
+        let player = lobby.players.get(&network_id).unwrap();
+        list.push(player.money);
+        lobby.network_to_game.insert(network_id, game_id as u8);
+    }
+
+    let dealt_count = list.len();
+    let mut game = match make_game(list, lobby.config, lobby.button) {
+        Ok(game) => game,
+        Err(e) => {
+            error!("couldn't start the game: {}", e);
+            lobby.network_to_game.clear();
+            return;
+        }
+    };
+    for seat in 0..dealt_count {
+        game.set_time_bank(seat as u8, lobby.players[&lobby.player_order[seat]].time_bank);
+    }
+    // whoever comes back pays for the blinds they skipped, unless the big blind lands on them right away
+    let big_blind_seat = blind_seats(dealt_count as u8, game.button).1;
+    for (seat, id) in lobby.player_order.iter().enumerate() {
+        let user = lobby.players.get_mut(id).unwrap();
+        if seat >= dealt_count {
+            user.missed_blinds = lobby.config.blinds.big_blind;
+        } else if user.missed_blinds > 0 {
+            if seat as u8 != big_blind_seat {
+                info!("{} posts {} of missed blinds", user.username, user.missed_blinds);
+                game.post_missed_blinds(seat as u8, user.missed_blinds);
             }
-            
-            lobby.game = Some(game);
+            user.missed_blinds = 0;
+        }
+    }
 
-            // big blind and small blind forced
-            advance_game(GamePlayerAction::AddMoney(5), lobby, client_channels);
-            advance_game(GamePlayerAction::AddMoney(10), lobby, client_channels);
+    for (id, player) in game.players.iter().enumerate() {
+        if let Some(channel) = client_channels.get(&lobby.player_order[id]) {
+            let _ = channel.send(ClientBound::GameStarted(player.private_cards));
         }
     }
+
+    let seats: Vec<String> = lobby.player_order.iter().take(dealt_count).map(|id| format!("{} ({})", lobby.players[id].username, lobby.players[id].money)).collect();
+    info!("hand started, seed {:?}, button on seat {}, seats: {}", game.seed, game.button, seats.join(", "));
+
+    lobby.button = game.button + 1;
+    let events = game.start_events();
+    lobby.game = Some(game);
+    if lobby.player_order != order_before {
+        send_player_list_update(lobby, client_channels, None);
+    }
+    apply_game_events(events, lobby, client_channels);
+}
+
+fn advance_game(player_action: GamePlayerAction, lobby: &mut Lobby, client_channels: &ClientChannels) -> Result<(), ActionError> {
+    let Some(game) = lobby.game.as_mut() else { return Err(ActionError::HandOver) };
+    let events = game.advance_game(player_action)?;
+    apply_game_events(events, lobby, client_channels);
+    Ok(())
+}
+
+// only to the player who tried it
+fn send_action_error(client: u64, error: &ActionError, client_channels: &ClientChannels) {
+    if let Some(channel) = client_channels.get(&client) {
+        let _ = channel.send(ClientBound::Error { code: error.code(), message: error.to_string() });
+    }
 }
 
-fn advance_game(player_action: GamePlayerAction, lobby: &mut Lobby, client_channels: &ClientChannels) {
-    if let Some(game) = lobby.game.as_mut() && let Some(events) = game.advance_game(player_action) {
-        for event in &events {
-            broadcast_event(client_channels, ClientBound::GameEvent(event.clone()));
+// sends the events out and wraps up the hand once it's been won
+fn apply_game_events(events: Vec<GameEvent>, lobby: &mut Lobby, client_channels: &ClientChannels) {
+    for event in &events {
+        log_game_event(event);
+        broadcast_event(lobby, client_channels, ClientBound::GameEvent(event.clone()));
+        if matches!(event, GameEvent::UpdatePots(_)) && let Some(game) = &lobby.game {
+            broadcast_event(lobby, client_channels, ClientBound::PotTotal(game.total_pot()));
         }
+    }
+    if let Some(&GameEvent::NextPlayer(seat)) = events.iter().rev().find(|e| matches!(e, GameEvent::NextPlayer(_))) {
+        send_turn_prompt(seat, lobby, client_channels);
+    }
 
-        if events.iter().any(|e| matches!(e, GameEvent::Showdown(_))) {
-            // cleanup
-            for &id in &lobby.queued_for_removal {
-                let newtork_id = lobby.player_order[id as usize];
-                let username = lobby.players.remove(&newtork_id).unwrap().username;
-                broadcast_event(client_channels, ClientBound::PlayerLeft(username));
-                lobby.player_order.retain(|c| *c != newtork_id);
+    if events.iter().any(|e| matches!(e, GameEvent::Showdown(_) | GameEvent::WonUncontested(..))) && let Some(game) = lobby.game.take() {
+        // cleanup, the seats are looked up before anyone is removed since removing shifts everyone after them
+        let seats: Vec<u64> = lobby.player_order.iter().take(game.players.len()).copied().collect();
+        for (&network_id, player) in seats.iter().zip(&game.players) {
+            if let Some(user) = lobby.players.get_mut(&network_id) {
+                user.money = player.money;
+                user.time_bank = game.time_bank(player.id);
             }
-            for (id, &player) in game.players.iter().enumerate() {
-                if let Some(network_id) = lobby.player_order.get(id) && let Some(user) = lobby.players.get_mut(&*network_id) {
-                    user.money = player.money;
-                }
+        }
+        for id in std::mem::take(&mut lobby.queued_for_removal) {
+            let network_id = seats[id as usize];
+            let username = lobby.players.remove(&network_id).unwrap().username;
+            broadcast_event(lobby, client_channels, ClientBound::PlayerLeft(username));
+            lobby.player_order.retain(|c| *c != network_id);
+        }
+        for user in lobby.players.values_mut() {
+            user.ready = user.bot.is_some();
+        }
+        lobby.network_to_game.clear();
+        send_player_list_update(lobby, client_channels, None);
+        update_bots(lobby, client_channels);
+    }
+}
+
+// catches one connection up on the hand in progress, only players in it get their own cards
+fn send_game_state(client: u64, lobby: &Lobby, client_channels: &ClientChannels) {
+    let (Some(game), Some(channel)) = (&lobby.game, client_channels.get(&client)) else { return };
+    let seat = lobby.network_to_game.get(&client).copied();
+    if let Some(seat) = seat {
+        let _ = channel.send(ClientBound::GameStarted(game.player(seat).private_cards));
+    }
+    for event in game.state_events() {
+        let pots_updated = matches!(event, GameEvent::UpdatePots(_));
+        let _ = channel.send(ClientBound::GameEvent(event));
+        if pots_updated {
+            let _ = channel.send(ClientBound::PotTotal(game.total_pot()));
+        }
+    }
+    if seat == Some(game.current_turn) {
+        send_turn_prompt(game.current_turn, lobby, client_channels);
+    }
+}
+
+// spells out what the player whose turn it is can do, so the client doesn't have to work it out
+fn send_turn_prompt(seat: u8, lobby: &Lobby, client_channels: &ClientChannels) {
+    let (Some(game), Some(network_id)) = (&lobby.game, lobby.player_order.get(seat as usize)) else { return };
+    let Some(channel) = client_channels.get(network_id) else { return };
+    let legal = game.legal_actions();
+    let _ = channel.send(ClientBound::YourTurn { to_call: legal.can_call.unwrap_or(0), min_raise: legal.min_raise.unwrap_or(game.min_raise), can_check: legal.can_check });
+}
+
+fn log_game_event(event: &GameEvent) {
+    match event {
+        GameEvent::PlayerAction(seat, action) => info!("seat {}: {:?}", seat, action),
+        GameEvent::Showdown((_, steps, _)) => {
+            for step in steps {
+                info!("showdown: seats {:?} win {} of pots {}..={}, rake {}", step.winners, step.winnings, step.pot_start_index, step.pot_end_index, step.rake_taken);
             }
-            for (_, user) in &mut lobby.players {
-                user.ready = false;
+        },
+        GameEvent::WonUncontested(seat, winnings) => info!("seat {} wins {} uncontested", seat, winnings),
+        _ => {}
+    }
+}
+
+// what used to happen right on disconnect, the player leaves the lobby or gives up their seat at the end of the hand
+fn remove_user(client: u64, lobby: &mut Lobby, client_channels: &ClientChannels) {
+    if let Some(player) = lobby.players.get(&client) {
+        broadcast_event(lobby, client_channels, ClientBound::PlayerLeft(player.username.clone()));
+    }
+
+    if let Some(&id) = lobby.network_to_game.get(&client) && lobby.game.is_some() {
+        lobby.queued_for_removal.insert(id);
+        broadcast_event(lobby, client_channels, ClientBound::GameEvent(GameEvent::InGamePlayerLeave(id)));
+        // an all-in player has nothing left to decide, so their cards still run to the showdown
+        if let Some(game) = lobby.game.as_mut() && id != game.current_turn {
+            let player = game.player_mut(id);
+            if player.can_act() {
+                player.has_folded = true;
             }
-            lobby.game = None;
-            lobby.queued_for_removal.clear();
-            lobby.network_to_game.clear();
-            send_player_list_update(lobby, client_channels, None);
+            if let Some(events) = game.check_hand_over() {
+                apply_game_events(events, lobby, client_channels);
+            }
+        } else {
+            let _ = advance_game(GamePlayerAction::Fold, lobby, client_channels);
+        }
+    } else {
+        lobby.players.remove(&client);
+        lobby.player_order.retain(|&p| p != client);
+        send_player_list_update(lobby, client_channels, None);
+        update_bots(lobby, client_channels);
+        check_for_game_start(client_channels, lobby);
+    }
+}
+
+fn expire_disconnected(lobby: &mut Lobby, client_channels: &ClientChannels) {
+    let expired: Vec<u64> = lobby.players.iter().filter(|(_, u)| u.disconnected_at.is_some_and(|t| t.elapsed() > RECONNECT_GRACE)).map(|(&id, _)| id).collect();
+    for id in expired {
+        if let Some(user) = lobby.players.get_mut(&id) {
+            user.disconnected_at = None;
         }
+        remove_user(id, lobby, client_channels);
+    }
+}
+
+// seats bots while the lobby is short of humans and removes them once enough humans are there, only between games
+fn update_bots(lobby: &mut Lobby, client_channels: &ClientChannels) {
+    if lobby.game.is_some() {
+        return;
+    }
+
+    let humans = lobby.players.values().filter(|u| u.bot.is_none()).count();
+    let bots: Vec<u64> = lobby.player_order.iter().copied().filter(|id| lobby.players.get(id).is_some_and(|u| u.bot.is_some())).collect();
+    let wanted = if lobby.fill_with_bots && humans > 0 { lobby.config.min_players.saturating_sub(humans) } else { 0 };
+    if wanted == bots.len() {
+        return;
+    }
+
+    for &id in bots.iter().skip(wanted) {
+        let username = lobby.players.remove(&id).unwrap().username;
+        lobby.player_order.retain(|&p| p != id);
+        broadcast_event(lobby, client_channels, ClientBound::PlayerLeft(username));
+    }
+
+    let mut number = 1;
+    for _ in bots.len()..wanted {
+        while lobby.players.values().any(|u| u.username.eq_ignore_ascii_case(&format!("Bot{}", number))) {
+            number += 1;
+        }
+        let username = format!("Bot{}", number);
+        let id = lobby.next_bot_id;
+        lobby.next_bot_id -= 1;
+        lobby.players.insert(id, User { money: lobby.default_money, username: username.clone(), ready: true, bot: Some(Box::new(Bot)), session_token: 0, disconnected_at: None, sitting_out: false, time_bank: lobby.config.time_bank, missed_blinds: 0 });
+        lobby.player_order.push(id);
+        broadcast_event(lobby, client_channels, ClientBound::PlayerJoined(username));
+    }
+
+    send_player_list_update(lobby, client_channels, None);
+}
+
+// bots, dropped and leaving players always muck, everyone else does too once MUCK_TIME is up
+fn drive_showdown(lobby: &mut Lobby, client_channels: &ClientChannels) {
+    let Some(game) = lobby.game.as_mut() else {
+        lobby.showdown_started = None;
+        return;
+    };
+    // the same players would rather run it once, and the turn timer covers everyone else
+    for seat in game.undecided_run_it_twice_players() {
+        let user = lobby.player_order.get(seat as usize).and_then(|id| lobby.players.get(id));
+        if lobby.queued_for_removal.contains(&seat) || user.is_none_or(|u| u.bot.is_some() || u.disconnected_at.is_some()) {
+            let events = game.vote_run_it_twice(seat, false).unwrap_or_default();
+            apply_game_events(events, lobby, client_channels);
+            return;
+        }
+    }
+    let undecided = game.undecided_showdown_players();
+    if undecided.is_empty() {
+        lobby.showdown_started = None;
+        return;
+    }
+
+    let started = *lobby.showdown_started.get_or_insert_with(Instant::now);
+    let mut events = Vec::new();
+    if started.elapsed() > MUCK_TIME {
+        events = game.muck_remaining();
+    } else {
+        for seat in undecided {
+            let user = lobby.player_order.get(seat as usize).and_then(|id| lobby.players.get(id));
+            if lobby.queued_for_removal.contains(&seat) || user.is_none_or(|u| u.bot.is_some() || u.disconnected_at.is_some()) {
+                events.extend(game.muck_or_show(seat, false).unwrap_or_default());
+            }
+        }
+    }
+    if !events.is_empty() {
+        apply_game_events(events, lobby, client_channels);
+    }
+}
+
+// plays the current turn if it belongs to a bot, after letting it "think" for a moment
+fn drive_bots(lobby: &mut Lobby, client_channels: &ClientChannels) {
+    let Some(game) = &lobby.game else {
+        lobby.bot_turn_started = None;
+        return;
+    };
+    if !game.undecided_showdown_players().is_empty() || !game.undecided_run_it_twice_players().is_empty() {
+        return;
+    }
+    let Some(user) = lobby.player_order.get(game.current_turn as usize).and_then(|id| lobby.players.get_mut(id)) else { return };
+    if user.disconnected_at.is_some() && lobby.restored_at.is_none_or(|at| at.elapsed() > RECONNECT_GRACE) {
+        if advance_game(GamePlayerAction::Check, lobby, client_channels).is_err() {
+            let _ = advance_game(GamePlayerAction::Fold, lobby, client_channels);
+        }
+        return;
+    }
+    let Some(bot) = user.bot.as_mut() else {
+        lobby.bot_turn_started = None;
+        return;
+    };
+
+    let started = *lobby.bot_turn_started.get_or_insert_with(Instant::now);
+    if started.elapsed() < BOT_THINK_TIME {
+        return;
+    }
+    lobby.bot_turn_started = None;
+
+    let action = bot.decide(&game.player_view(game.current_turn));
+    // bots don't know about betting limits, so a raise that's too big becomes a call
+    if advance_game(action, lobby, client_channels).is_err() && advance_game(GamePlayerAction::Call, lobby, client_channels).is_err() {
+        let _ = advance_game(GamePlayerAction::Fold, lobby, client_channels);
     }
 }
 
 fn send_player_list_update(lobby: &Lobby, client_channels: &ClientChannels, private_id: Option<u64>) {
     let mut list = Vec::new();
+    let public_view = lobby.game.as_ref().map(Game::public_view);
     for network_id in &lobby.player_order {
         let user = lobby.players.get(network_id).unwrap();
-        if let Some(game) = &lobby.game {
-            let player = game.player(*lobby.network_to_game.get(network_id).unwrap());
+        if let Some(public_view) = &public_view && let Some(&seat) = lobby.network_to_game.get(network_id) {
+            let player = public_view[seat as usize];
             list.push((if lobby.queued_for_removal.contains(&player.id) { PlayerState::Left } else if player.has_folded { PlayerState::Folded } else { PlayerState::InGame }, player.money, user.username.clone()));
         } else {
-            list.push((if user.ready { PlayerState::Ready } else { PlayerState::NotReady }, user.money, user.username.clone()));
+            list.push((if user.sits_out(&lobby.config) { PlayerState::SittingOut } else if user.ready { PlayerState::Ready } else { PlayerState::NotReady }, user.money, user.username.clone()));
         }
     }
 
     if let Some(id) = private_id {
         let _ = client_channels.get(&id).unwrap().send(ClientBound::UpdatePlayerList(list));
     } else {
-        broadcast_event(client_channels, ClientBound::UpdatePlayerList(list));
+        broadcast_event(lobby, client_channels, ClientBound::UpdatePlayerList(list));
         for (index, network_id) in lobby.player_order.iter().enumerate() {
             if let Some(channel) = client_channels.get(network_id) {
                 let _ = channel.send(ClientBound::YourIndex(index as u8));
@@ -188,8 +986,1007 @@ fn send_player_list_update(lobby: &Lobby, client_channels: &ClientChannels, priv
     }
 }
 
-fn broadcast_event(client_channels: &ClientChannels, event: ClientBound) {
-    for channel in client_channels.values() {
-        let _ = channel.send(event.clone());
+// goes to everyone in the lobby, players and spectators, but not to connections that haven't joined either way
+fn broadcast_event(lobby: &Lobby, client_channels: &ClientChannels, event: ClientBound) {
+    for id in lobby.player_order.iter().chain(&lobby.spectators) {
+        if let Some(channel) = client_channels.get(id) {
+            let _ = channel.send(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mini_holdem::{cards::Card, networking::{MemoryTransport, client_network_loop, send_event}, protocol::{decode_server_bound, encode_server_bound}};
+
+    fn lobby(fill_with_bots: bool) -> Lobby {
+        Lobby::new(fill_with_bots, 1000, BuyInConfig::default(), Duration::ZERO, false)
+    }
+
+    // a connection that's already through the Hello, with everything sent to it kept in the receiver
+    fn join(client: u64, username: &str, lobby: &mut Lobby, client_channels: &mut ClientChannels) -> Receiver<ClientBound> {
+        let (sender, receiver) = mpsc::channel();
+        client_channels.insert(client, sender);
+        handle_lobby_event(ServerBound::Login { username: username.to_string(), buy_in: None }, client, lobby, client_channels);
+        receiver
+    }
+
+    // seats everyone under ids from 1 up and readies them, a full table deals right away
+    fn table(usernames: &[&str], lobby: &mut Lobby, client_channels: &mut ClientChannels) -> Vec<Receiver<ClientBound>> {
+        let receivers: Vec<_> = usernames.iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, lobby, client_channels)).collect();
+        for client in 1..=usernames.len() as u64 {
+            handle_lobby_event(ServerBound::Ready(true), client, lobby, client_channels);
+        }
+        receivers
+    }
+
+    // one pass of the main loop without waiting on the bots, the humans call everything and muck when beaten
+    fn tick(lobby: &mut Lobby, client_channels: &mut ClientChannels) {
+        drive_countdown(lobby, client_channels);
+        drive_showdown(lobby, client_channels);
+        if let Some(game) = &lobby.game {
+            if let Some(&seat) = game.undecided_showdown_players().first() && lobby.players[&lobby.player_order[seat as usize]].bot.is_none() {
+                handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Muck), lobby.player_order[seat as usize], lobby, client_channels);
+                return;
+            }
+            let client = lobby.player_order[game.current_turn as usize];
+            if lobby.players[&client].bot.is_none() {
+                handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), client, lobby, client_channels);
+                return;
+            }
+        }
+        lobby.bot_turn_started = Some(Instant::now() - BOT_THINK_TIME);
+        drive_bots(lobby, client_channels);
+    }
+
+    #[test]
+    fn one_human_and_two_bots_play_a_full_hand() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(true);
+        let receiver = join(1, "alice", &mut lobby, &mut client_channels);
+        assert_eq!(lobby.players.len(), 3);
+        assert_eq!(lobby.players.values().filter(|u| u.bot.is_some()).count(), 2);
+
+        handle_lobby_event(ServerBound::Ready(true), 1, &mut lobby, &mut client_channels);
+        assert!(lobby.game.is_some());
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+
+        let received: Vec<ClientBound> = receiver.try_iter().collect();
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameStarted(_))));
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::Showdown(_) | GameEvent::WonUncontested(..)))));
+    }
+
+    #[test]
+    fn leaving_mid_hand_tells_the_table() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        assert!(lobby.game.is_some());
+        let seat = lobby.network_to_game[&2];
+
+        handle_lobby_event(ServerBound::Disconnect, 2, &mut lobby, &mut client_channels);
+        lobby.players.get_mut(&2).unwrap().disconnected_at = Some(Instant::now() - RECONNECT_GRACE - Duration::from_secs(1));
+        expire_disconnected(&mut lobby, &client_channels);
+
+        for receiver in [&receivers[0], &receivers[2]] {
+            let received: Vec<ClientBound> = receiver.try_iter().collect();
+            assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::InGamePlayerLeave(s)) if *s == seat)));
+            assert!(received.iter().any(|event| matches!(event, ClientBound::PlayerLeft(name) if name == "bob")));
+        }
+    }
+
+    #[test]
+    fn game_actions_without_a_game_or_a_seat_are_ignored() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        join(1, "alice", &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), 1, &mut lobby, &mut client_channels);
+        assert!(lobby.game.is_none());
+
+        // a connection that never sat down, while a hand is on
+        let mut lobby = self::lobby(false);
+        table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        let before = format!("{:?}", lobby.game.as_ref().unwrap().full_snapshot());
+        for action in [GamePlayerAction::Call, GamePlayerAction::Fold, GamePlayerAction::Muck] {
+            handle_lobby_event(ServerBound::GameAction(action), 99, &mut lobby, &mut client_channels);
+        }
+        assert_eq!(format!("{:?}", lobby.game.as_ref().unwrap().full_snapshot()), before);
+    }
+
+    #[test]
+    fn reconnecting_gets_the_private_cards_again() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        let seat = lobby.network_to_game[&2];
+        let token = lobby.players[&2].session_token;
+        handle_lobby_event(ServerBound::Disconnect, 2, &mut lobby, &mut client_channels);
+        client_channels.remove(&2);
+
+        let (sender, receiver) = mpsc::channel();
+        client_channels.insert(12, sender);
+        handle_lobby_event(ServerBound::Reconnect(token), 12, &mut lobby, &mut client_channels);
+        assert_eq!(lobby.network_to_game.get(&12), Some(&seat));
+        let cards = lobby.game.as_ref().unwrap().player(seat).private_cards;
+        let received: Vec<ClientBound> = receiver.try_iter().collect();
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameStarted(dealt) if dealt[0].full_eq(&cards[0]) && dealt[1].full_eq(&cards[1]))));
+    }
+
+    fn stacks(lobby: &Lobby) -> Vec<u32> {
+        lobby.player_order.iter().map(|id| lobby.players[id].money).collect()
+    }
+
+    #[test]
+    fn buy_ins_are_clamped() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = Lobby::new(false, 1000, BuyInConfig { min: 500, max: 2000, min_stack: 1 }, Duration::ZERO, false);
+        for (client, (username, buy_in)) in [("alice", None), ("bob", Some(100)), ("carol", Some(1500)), ("dave", Some(9000))].into_iter().enumerate() {
+            let (sender, _) = mpsc::channel();
+            client_channels.insert(client as u64 + 1, sender);
+            handle_lobby_event(ServerBound::Login { username: username.to_string(), buy_in }, client as u64 + 1, &mut lobby, &mut client_channels);
+        }
+        assert_eq!(stacks(&lobby), [1000, 500, 1500, 2000]);
+    }
+
+    #[test]
+    fn admin_sets_stacks_per_seat_and_for_everyone() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = Lobby::new(false, 1000, BuyInConfig { min: 500, max: 2000, min_stack: 1 }, Duration::ZERO, false);
+        let receivers: Vec<_> = ["alice", "bob", "carol"].iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, &mut lobby, &mut client_channels)).collect();
+
+        handle_admin_command("setmoney 1 1800", 1, &mut lobby, &client_channels);
+        assert_eq!(stacks(&lobby), [1000, 1800, 1000]);
+        handle_admin_command("setmoney 2 100", 1, &mut lobby, &client_channels);
+        assert_eq!(stacks(&lobby), [1000, 1800, 500]);
+        // the others see the new stacks in the player list
+        let last_list = receivers[0].try_iter().filter_map(|event| match event {
+            ClientBound::UpdatePlayerList(list) => Some(list.into_iter().map(|(_, money, _)| money).collect::<Vec<_>>()),
+            _ => None,
+        }).last();
+        assert_eq!(last_list, Some(vec![1000, 1800, 500]));
+
+        handle_admin_command("startmoney 5000", 1, &mut lobby, &client_channels);
+        assert_eq!(stacks(&lobby), [2000, 2000, 2000]);
+        assert_eq!(lobby.default_money, 2000);
+    }
+
+    #[test]
+    fn illegal_actions_are_only_answered_to_whoever_tried() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        for receiver in &receivers {
+            receiver.try_iter().for_each(drop);
+        }
+        let actor = lobby.player_order[lobby.game.as_ref().unwrap().current_turn as usize];
+
+        // facing the big blind preflop
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Check), actor, &mut lobby, &mut client_channels);
+        for (i, receiver) in receivers.iter().enumerate() {
+            let errors: Vec<u16> = receiver.try_iter().filter_map(|event| match event {
+                ClientBound::Error { code, .. } => Some(code),
+                _ => None,
+            }).collect();
+            if i as u64 + 1 == actor {
+                assert_eq!(errors, [ActionError::CannotCheckFacingBet.code()]);
+            } else {
+                assert!(errors.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn busted_players_arent_dealt_the_next_hand() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol", "dave"], &mut lobby, &mut client_channels);
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+        // as if dave lost everything in that hand
+        lobby.players.get_mut(&4).unwrap().money = 0;
+        for client in 1..=4 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        let game = lobby.game.as_ref().unwrap();
+        assert_eq!(game.players.len(), 3);
+        assert!(!lobby.network_to_game.contains_key(&4));
+        let last_list = receivers[0].try_iter().filter_map(|event| match event {
+            ClientBound::UpdatePlayerList(list) => Some(list),
+            _ => None,
+        }).last().unwrap();
+        assert!(last_list.iter().any(|(state, money, username)| matches!(state, PlayerState::SittingOut) && *money == 0 && username == "dave"));
+    }
+
+    fn server() -> Server {
+        Server { rooms: HashMap::from([(0, lobby(false))]), client_rooms: HashMap::new(), next_room_id: 1, greeted: HashSet::new(), fill_with_bots: false, default_money: 1000, buy_in: BuyInConfig::default(), start_countdown: Duration::ZERO, run_it_twice: false }
+    }
+
+    // a fresh connection, nothing it sent has been handled yet
+    fn connect(client: u64, client_channels: &mut ClientChannels) -> Receiver<ClientBound> {
+        let (sender, receiver) = mpsc::channel();
+        client_channels.insert(client, sender);
+        receiver
+    }
+
+    #[test]
+    fn a_different_protocol_version_is_turned_away() {
+        let mut client_channels = ClientChannels::new();
+        let mut server = server();
+        let old = connect(1, &mut client_channels);
+        handle_event(ServerBound::Hello(PROTOCOL_VERSION - 1), 1, &mut server, &mut client_channels);
+        assert!(matches!(old.try_recv(), Ok(ClientBound::Welcome { version: PROTOCOL_VERSION, accepted: false })));
+        // the channel is gone, so the connection closes, and nothing else it sends is listened to
+        assert!(!client_channels.contains_key(&1));
+        assert!(!server.greeted.contains(&1));
+        handle_event(ServerBound::Login { username: "alice".to_string(), buy_in: None }, 1, &mut server, &mut client_channels);
+        assert!(server.rooms[&0].players.is_empty());
+
+        let current = connect(2, &mut client_channels);
+        handle_event(ServerBound::Hello(PROTOCOL_VERSION), 2, &mut server, &mut client_channels);
+        assert!(matches!(current.try_recv(), Ok(ClientBound::Welcome { version: PROTOCOL_VERSION, accepted: true })));
+        handle_event(ServerBound::Login { username: "bob".to_string(), buy_in: None }, 2, &mut server, &mut client_channels);
+        assert_eq!(server.rooms[&0].players.len(), 1);
+    }
+
+    #[test]
+    fn chat_goes_to_everyone_else_in_the_room() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers: Vec<_> = ["alice", "bob", "carol"].iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, &mut lobby, &mut client_channels)).collect();
+        let (sender, spectator) = mpsc::channel();
+        client_channels.insert(9, sender);
+        handle_lobby_event(ServerBound::Spectate, 9, &mut lobby, &mut client_channels);
+        for receiver in receivers.iter().chain([&spectator]) {
+            receiver.try_iter().for_each(drop);
+        }
+
+        // through the wire format and back, as it comes in from bob's connection
+        let chat = decode_server_bound(&encode_server_bound(ServerBound::Chat("nice hand".to_string()))).unwrap();
+        handle_lobby_event(chat, 2, &mut lobby, &mut client_channels);
+        // too long, and from a spectator, neither goes anywhere
+        handle_lobby_event(ServerBound::Chat("a".repeat(MAX_CHAT_LENGTH + 1)), 1, &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::Chat("hi".to_string()), 9, &mut lobby, &mut client_channels);
+
+        let chats = |receiver: &Receiver<ClientBound>| receiver.try_iter().filter_map(|event| match event {
+            ClientBound::ChatBroadcast { player, message } => Some((player, message)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert!(chats(&receivers[1]).is_empty());
+        for receiver in [&receivers[0], &receivers[2], &spectator] {
+            assert_eq!(chats(receiver), [(1, "nice hand".to_string())]);
+        }
+    }
+
+    #[test]
+    fn spectators_see_the_board_but_no_private_cards() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        for (i, username) in ["alice", "bob", "carol"].iter().enumerate() {
+            join(i as u64 + 1, username, &mut lobby, &mut client_channels);
+        }
+        let (sender, spectator) = mpsc::channel();
+        client_channels.insert(9, sender);
+        handle_lobby_event(ServerBound::Spectate, 9, &mut lobby, &mut client_channels);
+        for client in 1..=3 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        assert_eq!(lobby.game.as_ref().unwrap().players.len(), 3);
+
+        let before = format!("{:?}", lobby.game.as_ref().unwrap().full_snapshot());
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Fold), 9, &mut lobby, &mut client_channels);
+        assert_eq!(format!("{:?}", lobby.game.as_ref().unwrap().full_snapshot()), before);
+
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        let received: Vec<ClientBound> = spectator.try_iter().collect();
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::RevealFlop(_)))));
+        assert!(!received.iter().any(|event| matches!(event, ClientBound::GameStarted(_) | ClientBound::YourTurn { .. } | ClientBound::Error { .. })));
+    }
+
+    #[test]
+    fn a_reconnect_keeps_the_stack_and_the_fold() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        let seat = lobby.network_to_game[&2];
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), 1, &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Fold), 2, &mut lobby, &mut client_channels);
+        let player = lobby.game.as_ref().unwrap().player(seat);
+        assert!(player.has_folded);
+
+        let token = lobby.players[&2].session_token;
+        handle_lobby_event(ServerBound::Disconnect, 2, &mut lobby, &mut client_channels);
+        client_channels.remove(&2);
+        let (sender, receiver) = mpsc::channel();
+        client_channels.insert(12, sender);
+        handle_lobby_event(ServerBound::Reconnect(token), 12, &mut lobby, &mut client_channels);
+
+        let rejoined = lobby.game.as_ref().unwrap().player(lobby.network_to_game[&12]);
+        assert_eq!(lobby.network_to_game[&12], seat);
+        assert_eq!((rejoined.money, rejoined.has_folded), (player.money, true));
+        assert_eq!(lobby.players[&12].money, 1000);
+        // the state they're caught up with says the same
+        let received: Vec<ClientBound> = receiver.try_iter().collect();
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::OwnedMoneyChange(s, money)) if *s == seat && *money == player.money)));
+        assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::PlayerAction(s, GamePlayerAction::Fold)) if *s == seat)));
+    }
+
+    // through the Hello, so everything else it sends is listened to
+    fn greet(client: u64, server: &mut Server, client_channels: &mut ClientChannels) -> Receiver<ClientBound> {
+        let receiver = connect(client, client_channels);
+        handle_event(ServerBound::Hello(PROTOCOL_VERSION), client, server, client_channels);
+        receiver
+    }
+
+    #[test]
+    fn rooms_dont_hear_each_other() {
+        let mut client_channels = ClientChannels::new();
+        let mut server = server();
+        let first_room: Vec<_> = (1..=3).map(|client| greet(client, &mut server, &mut client_channels)).collect();
+        let second_room: Vec<_> = (4..=6).map(|client| greet(client, &mut server, &mut client_channels)).collect();
+
+        handle_event(ServerBound::CreateRoom, 4, &mut server, &mut client_channels);
+        let room = server.room_of(4);
+        assert_ne!(room, 0);
+        for client in 5..=6 {
+            handle_event(ServerBound::JoinRoom(room), client, &mut server, &mut client_channels);
+        }
+        assert_eq!((server.room_of(1), server.room_of(5)), (0, room));
+        for receiver in &second_room {
+            receiver.try_iter().for_each(drop);
+        }
+
+        // a whole hand in the first room, chat included
+        for (client, username) in [(1, "alice"), (2, "bob"), (3, "carol")] {
+            handle_event(ServerBound::Login { username: username.to_string(), buy_in: None }, client, &mut server, &mut client_channels);
+        }
+        handle_event(ServerBound::Chat("good luck".to_string()), 1, &mut server, &mut client_channels);
+        for client in 1..=3 {
+            handle_event(ServerBound::Ready(true), client, &mut server, &mut client_channels);
+        }
+        let lobby = server.rooms.get_mut(&0).unwrap();
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(lobby, &mut client_channels);
+        }
+        assert!(first_room[1].try_iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::RevealFlop(_)))));
+        for receiver in &second_room {
+            assert_eq!(receiver.try_iter().count(), 0);
+        }
+        assert!(server.rooms[&room].game.is_none() && server.rooms[&room].players.is_empty());
+    }
+
+    #[test]
+    fn only_seat_zero_runs_the_table_and_only_between_hands() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers: Vec<_> = ["alice", "bob", "carol", "dave"].iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, &mut lobby, &mut client_channels)).collect();
+
+        // bob isn't seat 0
+        handle_lobby_event(ServerBound::Admin("blinds 25 50".to_string()), 2, &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::Admin("kick 3".to_string()), 2, &mut lobby, &mut client_channels);
+        assert_eq!(lobby.config.blinds.big_blind, 10);
+        assert_eq!(lobby.player_order.len(), 4);
+
+        handle_lobby_event(ServerBound::Admin("blinds 25 50".to_string()), 1, &mut lobby, &mut client_channels);
+        assert_eq!((lobby.config.blinds.small_blind, lobby.config.blinds.big_blind), (25, 50));
+        // a small blind over the big blind makes no sense
+        handle_lobby_event(ServerBound::Admin("blinds 60 50".to_string()), 1, &mut lobby, &mut client_channels);
+        assert_eq!(lobby.config.blinds.small_blind, 25);
+
+        handle_lobby_event(ServerBound::Admin("kick 3".to_string()), 1, &mut lobby, &mut client_channels);
+        assert_eq!(lobby.player_order, [1, 2, 3]);
+        // dave is still connected and watches from now on
+        assert!(lobby.spectators.contains(&4));
+        assert!(receivers[3].try_iter().any(|event| matches!(event, ClientBound::PlayerLeft(name) if name == "dave")));
+
+        for client in 1..=3 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        assert_eq!(lobby.game.as_ref().unwrap().current_bet, 50);
+        // nothing changes while a hand is on
+        handle_lobby_event(ServerBound::Admin("blinds 50 100".to_string()), 1, &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::Admin("kick 2".to_string()), 1, &mut lobby, &mut client_channels);
+        assert_eq!(lobby.config.blinds.big_blind, 50);
+        assert_eq!(lobby.player_order.len(), 3);
+        assert!(lobby.queued_for_removal.is_empty());
+    }
+
+    fn args(args: &str) -> Result<Args, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn command_line_flags() {
+        let parsed = args("--bind 127.0.0.1:7000 --default-money 2000 --min-buy-in 500 --max-buy-in 5000 --min-stack 20 --countdown 3 --bots --run-it-twice --max-connections 8").unwrap();
+        assert_eq!(parsed.bind, SocketAddr::from(([127, 0, 0, 1], 7000)));
+        assert_eq!((parsed.default_money, parsed.min_buy_in, parsed.max_buy_in, parsed.min_stack), (2000, Some(500), Some(5000), 20));
+        assert_eq!(parsed.start_countdown, Duration::from_secs(3));
+        assert!(parsed.fill_with_bots && parsed.run_it_twice);
+        assert_eq!(parsed.max_connections, 8);
+
+        let defaults = args("").unwrap();
+        assert_eq!((defaults.default_money, defaults.start_countdown, defaults.max_connections), (1000, START_COUNTDOWN, MAX_CONNECTIONS));
+        assert!(!defaults.fill_with_bots);
+    }
+
+    #[test]
+    fn bad_command_line_flags() {
+        assert_eq!(args("--bind nowhere").err().unwrap(), "invalid bind address: nowhere");
+        assert_eq!(args("--default-money 0").err().unwrap(), "invalid default money: 0");
+        assert_eq!(args("--countdown 300").err().unwrap(), "invalid countdown: 300");
+        assert_eq!(args("--max-connections").err().unwrap(), "--max-connections needs a number");
+        assert_eq!(args("--min-buy-in 2000").err().unwrap(), "the min buy-in can't be above the default money");
+        assert_eq!(args("--max-buy-in 500").err().unwrap(), "the max buy-in can't be below the default money");
+        assert_eq!(args("--min-stack 1001").err().unwrap(), "the min stack can't be above the default money");
+        assert_eq!(args("--bots --fast").err().unwrap(), "unknown argument: --fast");
+    }
+
+    #[test]
+    fn bad_usernames_are_rejected() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        join(1, "alice", &mut lobby, &mut client_channels);
+        for (client, username, reason) in [(2, "", "at least 3"), (3, "seventeen_letters", "more than 16"), (4, "bob smith", "spaces"), (5, "bøb", "ASCII"), (6, "ALICE", "already taken")] {
+            let receiver = join(client, username, &mut lobby, &mut client_channels);
+            let received: Vec<ClientBound> = receiver.try_iter().collect();
+            assert!(matches!(received.as_slice(), [ClientBound::LoginRejected(message)] if message.contains(reason)), "{:?}: {:?}", username, received);
+            assert!(!lobby.players.contains_key(&client));
+        }
+        assert_eq!(lobby.player_order, [1]);
+    }
+
+    #[test]
+    fn shutting_down_tells_everyone_and_cuts_off_stragglers() {
+        let mut client_channels = ClientChannels::new();
+        let receivers: Vec<_> = (1..=3).map(|client| connect(client, &mut client_channels)).collect();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        // the first two leave on their own, the third never answers
+        for client in [1, 2] {
+            server_bound_sender.send((client, ServerBound::Disconnect)).unwrap();
+        }
+
+        let started = Instant::now();
+        shut_down(&mut client_channels, &server_bound_receiver, Duration::from_millis(50));
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(client_channels.is_empty());
+        for receiver in &receivers {
+            assert!(matches!(receiver.try_recv(), Ok(ClientBound::ServerShutdown)));
+            assert!(matches!(receiver.try_recv(), Err(mpsc::TryRecvError::Disconnected)));
+        }
+    }
+
+    #[test]
+    fn shutting_down_ends_as_soon_as_everyone_left() {
+        let mut client_channels = ClientChannels::new();
+        let _receiver = connect(1, &mut client_channels);
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        server_bound_sender.send((1, ServerBound::Disconnect)).unwrap();
+
+        let started = Instant::now();
+        shut_down(&mut client_channels, &server_bound_receiver, Duration::from_secs(10));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(client_channels.is_empty());
+    }
+
+    // every test runs on its own thread, so each one only sees what it logged itself
+    struct TestLogger;
+    thread_local! {
+        static LOG_RECORDS: std::cell::RefCell<Vec<(log::Level, String)>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+    impl log::Log for TestLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &log::Record) {
+            LOG_RECORDS.with(|records| records.borrow_mut().push((record.level(), record.args().to_string())));
+        }
+        fn flush(&self) {}
+    }
+    static TEST_LOGGER: TestLogger = TestLogger;
+
+    fn take_log_records() -> Vec<(log::Level, String)> {
+        LOG_RECORDS.with(|records| std::mem::take(&mut *records.borrow_mut()))
+    }
+
+    #[test]
+    fn a_hand_is_logged_from_the_deal_to_the_showdown() {
+        let _ = log::set_logger(&TEST_LOGGER);
+        log::set_max_level(log::LevelFilter::Info);
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        // bob tries to go before alice
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), 2, &mut lobby, &mut client_channels);
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        handle_lobby_event(ServerBound::Disconnect, 2, &mut lobby, &mut client_channels);
+
+        let records = take_log_records();
+        assert_eq!(records[0].0, log::Level::Info);
+        assert!(records[0].1.starts_with("hand started, seed Some(") && records[0].1.ends_with("button on seat 0, seats: alice (1000), bob (1000), carol (1000)"), "{}", records[0].1);
+        // everyone calls and checks down, so only who mucks at the end depends on the cards
+        let mut expected = vec![(log::Level::Info, "seat 1: AddMoney(5)"), (log::Level::Info, "seat 2: AddMoney(10)"), (log::Level::Warn, "seat 1 tried to Call out of turn"), (log::Level::Info, "seat 0: AddMoney(10)"), (log::Level::Info, "seat 1: AddMoney(5)"), (log::Level::Info, "seat 2: Check")];
+        for _ in 0..3 {
+            expected.extend([(log::Level::Info, "seat 1: Check"), (log::Level::Info, "seat 2: Check"), (log::Level::Info, "seat 0: Check")]);
+        }
+        let actions: Vec<(log::Level, &str)> = records[1..=expected.len()].iter().map(|(level, message)| (*level, message.as_str())).collect();
+        assert_eq!(actions, expected);
+        let rest = &records[expected.len() + 1..];
+        let (mucks, rest) = rest.split_at(rest.iter().position(|(_, message)| message.starts_with("showdown: ")).unwrap());
+        assert!(mucks.iter().all(|(level, message)| *level == log::Level::Info && message.ends_with(": Muck")));
+        let (showdown, rest) = rest.split_at(rest.len() - 1);
+        assert!(showdown.iter().all(|(level, message)| *level == log::Level::Info && message.starts_with("showdown: seats ") && message.ends_with("rake 0")));
+        assert_eq!(rest, [(log::Level::Warn, "bob disconnected".to_string())]);
+    }
+
+    #[test]
+    fn rebuys_are_capped_and_only_between_hands() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = Lobby::new(false, 1000, BuyInConfig { min: 500, max: 2000, min_stack: 1 }, Duration::ZERO, false);
+        let receivers: Vec<_> = ["alice", "bob", "carol"].iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, &mut lobby, &mut client_channels)).collect();
+        let rebuy = |client: u64, money: u32, lobby: &mut Lobby, client_channels: &mut ClientChannels| {
+            handle_lobby_event(ServerBound::Rebuy(money), client, lobby, client_channels);
+            receivers[client as usize - 1].try_iter().find_map(|event| match event {
+                ClientBound::RebuyResult { accepted, money } => Some((accepted, money)),
+                _ => None,
+            })
+        };
+
+        assert_eq!(rebuy(1, 500, &mut lobby, &mut client_channels), Some((true, 1500)));
+        // 2100 would be over the maximum
+        assert_eq!(rebuy(1, 600, &mut lobby, &mut client_channels), Some((false, 1500)));
+        assert_eq!(rebuy(1, 500, &mut lobby, &mut client_channels), Some((true, 2000)));
+        assert_eq!(rebuy(1, 1, &mut lobby, &mut client_channels), Some((false, 2000)));
+
+        for client in 1..=3 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_some());
+        assert_eq!(rebuy(2, 100, &mut lobby, &mut client_channels), Some((false, 1000)));
+        assert_eq!(stacks(&lobby), [2000, 1000, 1000]);
+    }
+
+    #[test]
+    fn sitting_out_players_arent_dealt_in() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers: Vec<_> = ["alice", "bob", "carol", "dave"].iter().enumerate().map(|(i, username)| join(i as u64 + 1, username, &mut lobby, &mut client_channels)).collect();
+        handle_lobby_event(ServerBound::SitOut(true), 1, &mut lobby, &mut client_channels);
+        for client in 2..=4 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+
+        let game = lobby.game.as_ref().expect("the three others are enough for a hand");
+        assert_eq!(game.players.len(), 3);
+        assert!(!lobby.network_to_game.contains_key(&1));
+        // alice moved behind the seats that were dealt
+        assert_eq!(lobby.player_order, [2, 3, 4, 1]);
+        assert_eq!(lobby.network_to_game, HashMap::from([(2, 0), (3, 1), (4, 2)]));
+        assert!(!receivers[0].try_iter().any(|event| matches!(event, ClientBound::GameStarted(_))));
+        for receiver in &receivers[1..] {
+            assert!(receiver.try_iter().any(|event| matches!(event, ClientBound::GameStarted(_))));
+        }
+    }
+
+    #[test]
+    fn leaving_from_two_seats_apart_removes_just_those_two() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol", "dave"], &mut lobby, &mut client_channels);
+        // bob (seat 1) and dave (seat 3) leave, dave when it's his turn
+        remove_user(2, &mut lobby, &client_channels);
+        remove_user(4, &mut lobby, &client_channels);
+        assert_eq!(lobby.queued_for_removal, HashSet::from([1, 3]));
+        assert_eq!(lobby.player_order, [1, 2, 3, 4]);
+
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+        assert!(lobby.queued_for_removal.is_empty());
+        assert_eq!(lobby.player_order, [1, 3]);
+        assert_eq!(lobby.players.keys().copied().collect::<HashSet<u64>>(), HashSet::from([1, 3]));
+        let left: HashSet<String> = receivers[0].try_iter().filter_map(|event| match event { ClientBound::PlayerLeft(username) => Some(username), _ => None }).collect();
+        assert_eq!(left, HashSet::from(["bob".to_string(), "dave".to_string()]));
+    }
+
+    #[test]
+    fn the_turn_prompt_says_what_the_player_owes() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        let prompt = |client: u64, lobby: &Lobby| {
+            let prompts: Vec<(u32, u32, bool)> = receivers[client as usize - 1].try_iter().filter_map(|event| match event {
+                ClientBound::YourTurn { to_call, min_raise, can_check } => Some((to_call, min_raise, can_check)),
+                _ => None,
+            }).collect();
+            let game = lobby.game.as_ref().unwrap();
+            let view = game.player_view(lobby.network_to_game[&client]);
+            assert_eq!(game.current_turn, view.seat);
+            assert_eq!(prompts.last().unwrap().0, view.current_bet - view.contribution);
+            prompts
+        };
+
+        // alice is first to act with the button, facing the big blind
+        assert_eq!(prompt(1, &lobby), [(10, 10, false)]);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Raise(30)), 1, &mut lobby, &mut client_channels);
+        // bob has the small blind in, a raise has to go at least another 30
+        assert_eq!(prompt(2, &lobby), [(35, 30, false)]);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), 2, &mut lobby, &mut client_channels);
+        assert_eq!(prompt(3, &lobby), [(30, 30, false)]);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Call), 3, &mut lobby, &mut client_channels);
+        // on the flop nobody owes anything
+        assert_eq!(prompt(2, &lobby), [(0, 10, true)]);
+        assert!(receivers.iter().all(|receiver| !receiver.try_iter().any(|event| matches!(event, ClientBound::YourTurn { .. }))));
+    }
+
+    #[test]
+    fn unreadying_calls_off_the_countdown() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = Lobby::new(false, 1000, BuyInConfig::default(), Duration::from_secs(5), false);
+        let receivers = table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        let countdowns = |receiver: &Receiver<ClientBound>| -> Vec<u8> { receiver.try_iter().filter_map(|event| match event { ClientBound::Countdown(secs) => Some(secs), _ => None }).collect() };
+        assert!(lobby.game.is_none());
+        assert!(lobby.countdown_started.is_some());
+        assert_eq!(countdowns(&receivers[0]), [5]);
+
+        handle_lobby_event(ServerBound::Ready(false), 3, &mut lobby, &mut client_channels);
+        assert!(lobby.countdown_started.is_none());
+        assert_eq!(countdowns(&receivers[0]), [0]);
+        drive_countdown(&mut lobby, &client_channels);
+        assert!(lobby.game.is_none());
+
+        // readying again starts it over, and the hand is dealt once it runs out
+        handle_lobby_event(ServerBound::Ready(true), 3, &mut lobby, &mut client_channels);
+        assert_eq!(countdowns(&receivers[0]), [5]);
+        drive_countdown(&mut lobby, &client_channels);
+        assert!(lobby.game.is_none());
+        lobby.countdown_started = Some(Instant::now() - Duration::from_secs(5));
+        drive_countdown(&mut lobby, &client_channels);
+        assert!(lobby.game.is_some());
+    }
+
+    // the variant without what's in it, the cards and the session tokens differ every time
+    fn kind(event: &ClientBound) -> String {
+        let debug = match event {
+            ClientBound::GameEvent(event) => format!("{:?}", event),
+            event => format!("{:?}", event),
+        };
+        debug.split(['(', ' ']).next().unwrap().to_string()
+    }
+
+    #[test]
+    fn two_clients_play_a_hand_over_the_whole_protocol() {
+        let mut server = server();
+        server.rooms.get_mut(&0).unwrap().config.min_players = 2;
+        let mut client_channels = ClientChannels::new();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        let mut clients = Vec::new();
+        for (id, username) in [(1, "alice"), (2, "bob")] {
+            let (mut client, connection) = MemoryTransport::pair();
+            let (client_bound_sender, client_bound_receiver) = mpsc::channel();
+            client_channels.insert(id, client_bound_sender);
+            let server_bound_sender = server_bound_sender.clone();
+            thread::spawn(move || handle_client(id, connection, client_bound_receiver, server_bound_sender, CLIENT_TIMEOUT).is_ok());
+            let (sender, receiver) = mpsc::channel();
+            let mut reading = client.clone();
+            thread::spawn(move || client_network_loop(&mut reading, sender).is_ok());
+            for event in [ServerBound::Hello(PROTOCOL_VERSION), ServerBound::Login { username: username.to_string(), buy_in: None }, ServerBound::Ready(true)] {
+                send_event(&mut client, event).unwrap();
+            }
+            clients.push((client, receiver, Vec::new()));
+            // bob only connects once alice is sitting there ready, so what each of them gets comes in a set order
+            let started = Instant::now();
+            while !server.rooms[&0].players.get(&id).is_some_and(|user| user.ready) {
+                assert!(started.elapsed() < Duration::from_secs(10), "{} never got to be ready", username);
+                run_rooms(&mut server, &mut client_channels, &server_bound_receiver);
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        // both call whatever they're asked to and muck when beaten
+        let started = Instant::now();
+        let mut dealt = false;
+        while !dealt || server.rooms[&0].game.is_some() {
+            assert!(started.elapsed() < Duration::from_secs(10), "the hand never finished");
+            run_rooms(&mut server, &mut client_channels, &server_bound_receiver);
+            dealt |= server.rooms[&0].game.is_some();
+            for (seat, (client, receiver, received)) in clients.iter_mut().enumerate() {
+                for event in receiver.try_iter() {
+                    match &event {
+                        ClientBound::YourTurn { .. } => send_event(client, ServerBound::GameAction(GamePlayerAction::Call)).unwrap(),
+                        ClientBound::GameEvent(GameEvent::MuckOrShow(seats)) if seats.contains(&(seat as u8)) => send_event(client, ServerBound::GameAction(GamePlayerAction::Muck)).unwrap(),
+                        _ => {},
+                    }
+                    received.push(event);
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        // letting go of the connections closes them, which ends both client loops
+        client_channels.clear();
+        for (_, receiver, received) in &mut clients {
+            received.extend(receiver.iter());
+        }
+        // a tie has nobody to muck, otherwise the beaten one is asked and mucks
+        let kinds = |received: &[ClientBound]| -> Vec<String> { received.iter().filter(|event| !matches!(event, ClientBound::GameEvent(GameEvent::MuckOrShow(_) | GameEvent::PlayerAction(_, GamePlayerAction::Muck)))).map(kind).collect() };
+        let logged_in = ["Welcome", "SessionToken", "UpdatePlayerList", "YourIndex", "PlayerJoined"];
+        let joined = ["UpdatePlayerList", "YourIndex", "PlayerJoined"];
+        let readied = ["UpdatePlayerList", "YourIndex"];
+        let blinds = ["GameStarted", "OwnedMoneyChange", "PlayerAction", "OwnedMoneyChange", "PlayerAction", "UpdateCurrentBet", "UpdatePots", "PotTotal", "NextPlayer"];
+        let completed = ["OwnedMoneyChange", "PlayerAction", "UpdatePots", "PotTotal", "NextPlayer"];
+        let street = |reveal: &'static str, alice: bool| {
+            // bob is first after the flop, whoever's turn it is is prompted right after the NextPlayer
+            let mut street = vec!["PlayerAction", reveal, "UpdateCurrentBet", "NextPlayer"];
+            street.extend(if alice { ["PlayerAction", "NextPlayer", "YourTurn"].as_slice() } else { ["YourTurn", "PlayerAction", "NextPlayer"].as_slice() });
+            street
+        };
+        let end = ["PlayerAction", "Showdown", "UpdatePlayerList", "YourIndex"];
+
+        let mut alice = [logged_in.as_slice(), &readied, &joined, &readied, &blinds, &["YourTurn"], &completed].concat();
+        let mut bob = [logged_in.as_slice(), &readied, &blinds, &completed, &["YourTurn"]].concat();
+        for reveal in ["RevealFlop", "RevealTurn", "RevealRiver"] {
+            alice.extend(street(reveal, true));
+            bob.extend(street(reveal, false));
+        }
+        alice.extend(end);
+        bob.extend(end);
+        assert_eq!(kinds(&clients[0].2), alice);
+        assert_eq!(kinds(&clients[1].2), bob);
+
+        // everything about the hand itself reaches both the same
+        let game_events = |received: &[ClientBound]| -> Vec<String> { received.iter().filter(|event| matches!(event, ClientBound::GameEvent(_))).map(|event| format!("{:?}", event)).collect() };
+        assert_eq!(game_events(&clients[0].2), game_events(&clients[1].2));
+        assert_eq!(stacks(&server.rooms[&0]).iter().sum::<u32>(), 2000);
+    }
+
+    #[test]
+    fn the_game_state_catches_up_on_a_hand_in_progress() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        // alice folds, bob completes and carol checks, so bob is first on the flop
+        for (client, action) in [(1, GamePlayerAction::Fold), (2, GamePlayerAction::Call), (3, GamePlayerAction::Check)] {
+            handle_lobby_event(ServerBound::GameAction(action), client, &mut lobby, &mut client_channels);
+        }
+        let spectator = connect(9, &mut client_channels);
+        handle_lobby_event(ServerBound::Spectate, 9, &mut lobby, &mut client_channels);
+        for receiver in receivers.iter().chain([&spectator]) {
+            receiver.try_iter().count();
+        }
+
+        let state = ["OwnedMoneyChange", "PlayerAction", "OwnedMoneyChange", "OwnedMoneyChange", "RevealFlop", "UpdateCurrentBet", "UpdatePots", "PotTotal", "NextPlayer"];
+        for (client, receiver) in [(1, &receivers[0]), (2, &receivers[1]), (9, &spectator)] {
+            handle_lobby_event(ServerBound::RequestGameState, client, &mut lobby, &mut client_channels);
+            let received: Vec<ClientBound> = receiver.try_iter().collect();
+            let mut expected = if client == 9 { Vec::new() } else { vec!["GameStarted"] };
+            expected.extend(state);
+            // only whoever's turn it is gets asked
+            if client == 2 {
+                expected.push("YourTurn");
+            }
+            assert_eq!(received.iter().map(kind).collect::<Vec<_>>(), expected);
+            assert!(client != 2 || matches!(received.last(), Some(ClientBound::YourTurn { to_call: 0, can_check: true, .. })));
+            assert!(received.iter().any(|event| matches!(event, ClientBound::PotTotal(20))));
+            assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::PlayerAction(0, GamePlayerAction::Fold)))));
+            assert!(received.iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::NextPlayer(1)))));
+            if let Some(&seat) = lobby.network_to_game.get(&client) {
+                let cards = lobby.game.as_ref().unwrap().player(seat).private_cards;
+                assert!(matches!(&received[0], ClientBound::GameStarted(dealt) if dealt[0].full_eq(&cards[0]) && dealt[1].full_eq(&cards[1])));
+            }
+        }
+    }
+
+    // alice has aces and the shortest stack, and pushes it all in first thing
+    fn aces_all_in(lobby: &mut Lobby, client_channels: &mut ClientChannels) -> Vec<Receiver<ClientBound>> {
+        let receivers = table(&["alice", "bob", "carol"], lobby, client_channels);
+        let cards = |notation: &str| -> Vec<Card> { notation.split_whitespace().map(|card| card.parse().unwrap()).collect() };
+        let private = |notation: &str| -> [Card; 2] { cards(notation).try_into().unwrap() };
+        lobby.game = Some(Game::new_with_cards(vec![(0, 100, private("As Ah")), (1, 1000, private("Ks Kh")), (2, 1000, private("Qs Qh"))], cards("2c 7d 9h Js 3s").try_into().unwrap(), lobby.config.blinds).unwrap());
+        lobby.players.get_mut(&1).unwrap().money = 100;
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::AddMoney(100)), 1, lobby, client_channels);
+        receivers
+    }
+
+    #[test]
+    fn an_all_in_player_who_drops_still_wins() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = aces_all_in(&mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::Disconnect, 1, &mut lobby, &mut client_channels);
+        client_channels.remove(&1);
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+        // the seat is kept for her to come back to, with the main pot in it
+        assert_eq!(stacks(&lobby), [300, 900, 900]);
+        assert!(receivers[1].try_iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::Showdown((_, steps, _))) if steps[0].winners == [0] && steps[0].winnings == 300)));
+    }
+
+    #[test]
+    fn an_all_in_player_whose_seat_runs_out_still_wins() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = aces_all_in(&mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::Disconnect, 1, &mut lobby, &mut client_channels);
+        lobby.players.get_mut(&1).unwrap().disconnected_at = Some(Instant::now() - RECONNECT_GRACE - Duration::from_secs(1));
+        expire_disconnected(&mut lobby, &client_channels);
+        assert!(!lobby.game.as_ref().unwrap().player(0).has_folded);
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+        assert!(receivers[1].try_iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::Showdown((_, steps, _))) if steps[0].winners == [0] && steps[0].winnings == 300)));
+        // she's gone once it's paid out
+        assert_eq!(lobby.player_order, [2, 3]);
+    }
+
+    #[test]
+    fn a_second_login_doesnt_take_a_second_seat() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receiver = join(1, "alice", &mut lobby, &mut client_channels);
+        receiver.try_iter().count();
+        for username in ["alice", "alice2"] {
+            handle_lobby_event(ServerBound::Login { username: username.to_string(), buy_in: None }, 1, &mut lobby, &mut client_channels);
+            let received: Vec<ClientBound> = receiver.try_iter().collect();
+            assert!(matches!(received.as_slice(), [ClientBound::LoginRejected(message)] if message.contains("already seated")), "{:?}", received);
+        }
+        assert_eq!(lobby.player_order, [1]);
+        assert_eq!(lobby.players.len(), 1);
+        assert_eq!(lobby.players[&1].username, "alice");
+    }
+
+    #[test]
+    fn everyone_else_leaving_ends_the_hand() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let receivers = table(&["alice", "bob", "carol", "dave"], &mut lobby, &mut client_channels);
+        assert_eq!(lobby.game.as_ref().unwrap().current_turn, 3);
+        // none of them leave on their turn, dave is to act the whole time
+        for client in [2, 1] {
+            remove_user(client, &mut lobby, &client_channels);
+            assert!(lobby.game.is_some());
+        }
+        remove_user(3, &mut lobby, &client_channels);
+        assert!(lobby.game.is_none());
+        assert!(receivers[3].try_iter().any(|event| matches!(event, ClientBound::GameEvent(GameEvent::WonUncontested(3, 15)))));
+        assert_eq!(lobby.player_order, [4]);
+        assert_eq!(lobby.players[&4].money, 1015);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn a_saved_lobby_loads_back_the_same() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        lobby.config.blinds.small_blind = 10;
+        lobby.config.blinds.big_blind = 20;
+        join(4, "dave", &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::SitOut(true), 4, &mut lobby, &mut client_channels);
+        lobby.players.get_mut(&4).unwrap().money = 700;
+        table(&["alice", "bob", "carol"], &mut lobby, &mut client_channels);
+        handle_lobby_event(ServerBound::GameAction(GamePlayerAction::Raise(40)), lobby.player_order[0], &mut lobby, &mut client_channels);
+
+        let path = std::env::temp_dir().join(format!("mini-holdem-lobby-{}.json", std::process::id()));
+        lobby.save(&path).unwrap();
+        let loaded = Lobby::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+
+        let seats = |lobby: &Lobby| -> Vec<(String, u32, bool, bool)> { lobby.player_order.iter().map(|id| &lobby.players[id]).map(|user| (user.username.clone(), user.money, user.ready, user.sitting_out)).collect() };
+        assert_eq!(seats(&loaded), seats(&lobby));
+        assert_eq!(loaded.config, lobby.config);
+        assert_eq!((loaded.default_money, loaded.button), (lobby.default_money, lobby.button));
+        // the hand goes on from where it was, everyone has a new id until they reconnect
+        let (game, saved) = (loaded.game.as_ref().unwrap(), lobby.game.as_ref().unwrap());
+        assert_eq!(format!("{:?}", game.full_snapshot()), format!("{:?}", saved.full_snapshot()));
+        assert_eq!(game.total_pot(), 90);
+        assert_eq!(loaded.network_to_game.len(), 3);
+        assert!(loaded.players.values().all(|user| user.disconnected_at.is_some()));
+    }
+
+    #[test]
+    fn coming_back_from_sitting_out_posts_the_missed_blinds() {
+        let mut client_channels = ClientChannels::new();
+        let mut lobby = lobby(false);
+        let usernames = ["alice", "bob", "carol", "dave", "erin"];
+        for (i, username) in usernames.iter().enumerate() {
+            join(i as u64 + 1, username, &mut lobby, &mut client_channels);
+        }
+        handle_lobby_event(ServerBound::SitOut(true), 1, &mut lobby, &mut client_channels);
+        for client in 2..=5 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        assert_eq!(lobby.game.as_ref().unwrap().players.len(), 4);
+        assert_eq!(lobby.players[&1].missed_blinds, 10);
+        for _ in 0..100 {
+            if lobby.game.is_none() {
+                break;
+            }
+            tick(&mut lobby, &mut client_channels);
+        }
+        assert!(lobby.game.is_none());
+
+        // alice is back at the end of the table, under the gun, so she owes them
+        handle_lobby_event(ServerBound::SitOut(false), 1, &mut lobby, &mut client_channels);
+        for client in 1..=5 {
+            handle_lobby_event(ServerBound::Ready(true), client, &mut lobby, &mut client_channels);
+        }
+        let game = lobby.game.as_ref().unwrap();
+        let seat = lobby.network_to_game[&1];
+        assert_eq!((seat, game.current_turn), (4, 4));
+        assert_eq!(game.player(seat).money, 990);
+        assert_eq!(game.total_pot(), 25);
+        assert_eq!(game.current_bet, 10);
+        assert_eq!(lobby.players[&1].missed_blinds, 0);
+    }
+
+    #[test]
+    fn connections_past_the_limit_are_turned_away_until_one_closes() {
+        let mut client_channels = ClientChannels::new();
+        let (server_bound_sender, _server_bound_receiver) = mpsc::channel();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let mut next_id = 0;
+        let mut accept = |client_channels: &mut ClientChannels| {
+            let (client, server) = MemoryTransport::pair();
+            (client, accept_connection(server, &mut next_id, &connections, 2, client_channels, &server_bound_sender))
+        };
+        let (first, accepted) = accept(&mut client_channels);
+        assert!(accepted);
+        let (_second, accepted) = accept(&mut client_channels);
+        assert!(accepted);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+        let (mut third, accepted) = accept(&mut client_channels);
+        assert!(!accepted);
+        assert_eq!(client_channels.len(), 2);
+        let (sender, receiver) = mpsc::channel();
+        client_network_loop(&mut third, sender).unwrap();
+        assert_eq!(receiver.try_iter().map(|event| kind(&event)).collect::<Vec<_>>(), vec!["ServerFull"]);
+
+        // the first handler sees the connection close and gives its place back
+        drop(first);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while connections.load(Ordering::SeqCst) == 2 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        let (_fourth, accepted) = accept(&mut client_channels);
+        assert!(accepted);
+        assert_eq!(client_channels.keys().max(), Some(&2));
     }
 }
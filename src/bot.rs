@@ -0,0 +1,119 @@
+use crate::{cards::{Card, HandCategory, best_hand_rank_from, detect_draws}, events::GamePlayerAction, game::GameSnapshot, strategy::{PlayerView, Strategy}};
+
+// cheap bot without any simulation: rates its hand from 0 to 1 by simple rules and plays it against the pot odds
+#[derive(Debug, Clone, Default)]
+pub struct Bot;
+impl Bot {
+    // the snapshot is meant to be the seat's own, a seat whose cards were left out of it folds
+    pub fn decide(&self, snapshot: &GameSnapshot, my_seat: u8) -> GamePlayerAction {
+        let Some(me) = snapshot.players.get(my_seat as usize) else { return GamePlayerAction::Fold };
+        let Some(private_cards) = me.private_cards else { return GamePlayerAction::Fold };
+        let view = PlayerView {
+            seat: my_seat,
+            private_cards,
+            public_cards: snapshot.public_cards.iter().flatten().copied().collect(),
+            pot: snapshot.players.iter().map(|p| p.total_contribution).sum(),
+            current_bet: snapshot.current_bet,
+            min_raise: snapshot.min_raise,
+            contribution: me.round_contribution,
+            money: me.money,
+            opponents: snapshot.players.iter().filter(|p| p.id != my_seat && !p.has_folded).count(),
+        };
+        Bot::play(&view)
+    }
+
+    fn play(view: &PlayerView) -> GamePlayerAction {
+        let strength = Bot::hand_strength(view);
+        let to_call = view.to_call();
+
+        if strength >= 0.75 && view.money > to_call {
+            let raise = to_call + (view.pot / 2).max(view.min_raise);
+            return GamePlayerAction::AddMoney(raise.min(view.money));
+        }
+
+        if to_call == 0 {
+            return GamePlayerAction::Check;
+        }
+
+        let pot_odds = to_call as f64 / (view.pot + to_call) as f64;
+        if strength < pot_odds + 0.1 {
+            GamePlayerAction::Fold
+        } else {
+            GamePlayerAction::AddMoney(to_call.min(view.money))
+        }
+    }
+
+    fn hand_strength(view: &PlayerView) -> f64 {
+        let [first, second] = view.private_cards;
+        let cards: Vec<Card> = view.private_cards.iter().chain(&view.public_cards).copied().collect();
+        // preflop, pairs and high cards, a bit more for suited and connected ones
+        let Some(hand_rank) = best_hand_rank_from(&cards) else {
+            if first.rank == second.rank {
+                return 0.5 + first.rank as f64 / 24.0;
+            }
+            let connected = first.rank.abs_diff(second.rank) == 1;
+            return (first.rank + second.rank) as f64 / 40.0 + if first.suit == second.suit { 0.05 } else { 0.0 } + if connected { 0.05 } else { 0.0 };
+        };
+        // a hand the board makes on its own is everyone's
+        if best_hand_rank_from(&view.public_cards).is_some_and(|board| board.category >= hand_rank.category) {
+            return 0.1;
+        }
+        let draws = detect_draws(&cards);
+        let draw = if cards.len() < 7 && (draws.flush_draw || draws.open_ended_straight_draw) { 0.15 } else { 0.0 };
+        let made = match hand_rank.category {
+            HandCategory::HighCard => 0.1,
+            HandCategory::OnePair => 0.45,
+            HandCategory::TwoPair => 0.65,
+            HandCategory::ThreeKind => 0.75,
+            HandCategory::Straight => 0.85,
+            HandCategory::Flush => 0.88,
+            HandCategory::FullHouse => 0.93,
+            _ => 0.98,
+        };
+        f64::max(made, 0.1 + draw)
+    }
+}
+// so the server can seat it like any other strategy
+impl Strategy for Bot {
+    fn decide(&mut self, view: &PlayerView) -> GamePlayerAction {
+        Bot::play(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameConfig, make_game_with_deck};
+
+    // heads-up with seat 0 holding the given cards, checked down to the river where seat 1 bets 400 into it
+    fn facing_a_big_bet(private_cards: &str, board: &str) -> GameSnapshot {
+        let parse = |notation: &str| -> Vec<Card> { notation.split_whitespace().map(|card| card.parse().unwrap()).collect() };
+        let mut deck = parse(private_cards);
+        deck.extend(parse("2h 3h"));
+        deck.extend(parse(board));
+        deck.reverse();
+        let mut game = make_game_with_deck(vec![1000, 1000], GameConfig::default(), 1, deck).unwrap();
+        while game.full_snapshot().current_phase < 3 || game.current_turn != 1 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        game.advance_game(GamePlayerAction::Bet(400)).unwrap();
+        game.snapshot(Some(0))
+    }
+
+    #[test]
+    fn folds_a_hopeless_hand_facing_a_big_bet() {
+        let action = Bot.decide(&facing_a_big_bet("7d 2c", "As Kh Qc Jd 9s"), 0);
+        assert!(matches!(action, GamePlayerAction::Fold), "{:?}", action);
+    }
+
+    #[test]
+    fn plays_on_with_a_strong_made_hand() {
+        let action = Bot.decide(&facing_a_big_bet("Ah As", "Ac Ad Kh 8s 4c"), 0);
+        assert!(matches!(action, GamePlayerAction::AddMoney(money) if money >= 400), "{:?}", action);
+    }
+
+    #[test]
+    fn cant_play_without_its_cards() {
+        assert!(matches!(Bot.decide(&facing_a_big_bet("Ah As", "Ac Ad Kh 8s 4c"), 1), GamePlayerAction::Fold));
+    }
+}
@@ -1,9 +1,14 @@
-use std::{cmp::Ordering, fmt::{Display, Error}};
+use std::{cmp::Ordering, collections::HashSet, fmt::{Display, Error}, hash::{Hash, Hasher}, str::FromStr};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// serialized in the same notation Display uses, like "As"
 #[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(into = "String", try_from = "String"))]
 pub struct Card {
     pub rank: u8, // 0 to 8 is 2 to 10, then 9 - J, 10 - Q, 11 - K, 12 - A
-    pub suit: u8, // who cares which is which until we make them display
+    pub suit: u8, // 0 - clubs, 1 - diamonds, 2 - hearts, 3 - spades
 }
 impl Ord for Card {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -21,48 +26,112 @@ impl PartialEq for Card {
     }
 }
 impl Eq for Card {}
+const RANK_CHARS: [char; 13] = ['2', '3', '4', '5', '6', '7', '8', '9', 'T', 'J', 'Q', 'K', 'A'];
+const SUIT_CHARS: [char; 4] = ['c', 'd', 'h', 's'];
+const SUIT_SYMBOLS: [char; 4] = ['♣', '♦', '♥', '♠'];
+
+// standard notation like As, Td or 9c
 impl Display for Card {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}\x1b[0m",
-            match self.rank {
-                0..9 => (self.rank+2).to_string(),
-                9 => String::from("J"),
-                10 => String::from("Q"),
-                11 => String::from("K"),
-                12 => String::from("A"),
-                _ => return Err(Error)
-            },
-            match self.suit {
-                0 => "\x1b[31m♥",
-                1 => "\x1b[31m♦",
-                2 => "\x1b[30m♠",
-                3 => "\x1b[30m♣",
-                _ => return Err(Error)
-            }
-        )
+        let rank = RANK_CHARS.get(self.rank as usize).ok_or(Error)?;
+        let suit = SUIT_CHARS.get(self.suit as usize).ok_or(Error)?;
+        write!(f, "{}{}", rank, suit)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCardError;
+impl Display for ParseCardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected a card like As, Td or 9c")
+    }
+}
+
+// the ten is always T, so "10s" doesn't parse
+impl FromStr for Card {
+    type Err = ParseCardError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let (Some(rank), Some(suit), None) = (chars.next(), chars.next(), chars.next()) else {
+            return Err(ParseCardError);
+        };
+        let rank = RANK_CHARS.iter().position(|&c| c == rank.to_ascii_uppercase()).ok_or(ParseCardError)?;
+        let suit = SUIT_CHARS.iter().position(|&c| c == suit.to_ascii_lowercase()).ok_or(ParseCardError)?;
+        Ok(Card { rank: rank as u8, suit: suit as u8 })
+    }
+}
+
+impl From<Card> for String {
+    fn from(card: Card) -> Self {
+        card.to_string()
+    }
+}
+impl TryFrom<String> for Card {
+    type Error = ParseCardError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
     }
 }
 
 impl Card {
+    // == only looks at the rank, which is what hand ranking wants, this tells apart two cards of the same rank
+    pub fn full_eq(&self, other: &Self) -> bool {
+        self.rank == other.rank && self.suit == other.suit
+    }
+
+    // ? for a suit that doesn't exist
+    pub fn suit_symbol(&self) -> char {
+        SUIT_SYMBOLS.get(self.suit as usize).copied().unwrap_or('?')
+    }
+
+    // diamonds and hearts
+    pub fn suit_is_red(&self) -> bool {
+        self.suit == 1 || self.suit == 2
+    }
+
     pub fn to_byte(&self) -> u8 {
         // 00ssrrrr
         self.suit << 4 | self.rank
     }
 
     pub fn from_byte(byte: u8) -> Option<Self> {
-        let rank = byte & 0x0F;
-        if rank > 12 {
+        let (rank, suit) = (byte & 0x0F, byte >> 4);
+        if rank > 12 || suit > 3 {
             return None;
         }
-        Some(Card { rank, suit: byte >> 4 })
+        Some(Card { rank, suit })
+    }
+}
+
+// compares and hashes by rank and suit, so cards can go in sets and maps as the physical cards they are
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalCard(pub Card);
+impl PartialEq for PhysicalCard {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.full_eq(&other.0)
+    }
+}
+impl Eq for PhysicalCard {}
+impl Hash for PhysicalCard {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.0.rank, self.0.suit).hash(state);
     }
 }
 
+// true if no card appears twice
+pub fn all_distinct(cards: &[Card]) -> bool {
+    let mut seen = HashSet::new();
+    cards.iter().all(|&card| seen.insert(PhysicalCard(card)))
+}
+
 pub fn format_cards(cards: &[Card]) -> String {
     cards.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" ")
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum HandCategory {
     HighCard,
@@ -94,12 +163,41 @@ impl HandCategory {
     }
 }
 
+// short deck takes out the twos to fives, which makes flushes rarer than full houses and A 6 7 8 9 the lowest straight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeckKind {
+    #[default]
+    Standard,
+    ShortDeck,
+}
+impl DeckKind {
+    pub fn lowest_rank(&self) -> u8 {
+        match self {
+            DeckKind::Standard => 0,
+            DeckKind::ShortDeck => 4,
+        }
+    }
+
+    // how categories are ordered under these rules, only flushes and full houses trade places
+    pub fn category_strength(&self, category: &HandCategory) -> u8 {
+        match (self, category) {
+            (DeckKind::ShortDeck, HandCategory::Flush) => HandCategory::FullHouse as u8,
+            (DeckKind::ShortDeck, HandCategory::FullHouse) => HandCategory::Flush as u8,
+            (_, category) => category.clone() as u8,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct HandRank {
     pub category: HandCategory,
     pub primary: Vec<Card>,
     pub secondary: Vec<Card>,
     pub kickers: Vec<Card>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deck: DeckKind, // the rules it was ranked under, only the category order depends on it
 }
 impl Ord for HandRank {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -114,30 +212,44 @@ impl PartialOrd for HandRank {
 impl PartialEq for HandRank {
     fn eq(&self, other: &Self) -> bool {
         self.category == other.category
+            && self.deck == other.deck
             && self.primary == other.primary
             && self.secondary == other.secondary
             && self.kickers == other.kickers
     }
 }
 impl Eq for HandRank {}
+const RANK_NAMES: [&str; 13] = ["Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Nine", "Ten", "Jack", "Queen", "King", "Ace"];
+const RANK_PLURALS: [&str; 13] = ["Twos", "Threes", "Fours", "Fives", "Sixes", "Sevens", "Eights", "Nines", "Tens", "Jacks", "Queens", "Kings", "Aces"];
+
+fn rank_name(cards: &[Card]) -> &'static str {
+    cards.first().and_then(|c| RANK_NAMES.get(c.rank as usize)).unwrap_or(&"?")
+}
+
+fn rank_plural(cards: &[Card]) -> &'static str {
+    cards.first().and_then(|c| RANK_PLURALS.get(c.rank as usize)).unwrap_or(&"?")
+}
+
+// how you'd say it at the table, like "Full house, Kings over Tens" or "Ace-high flush"
 impl Display for HandRank {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.category {
-            HandCategory::HighCard => write!(f, "High card with kickers {}", format_cards(&self.kickers)),
-            HandCategory::OnePair => write!(f, "Pair with cards {} and kickers {}", format_cards(&self.primary), format_cards(&self.kickers)),
-            HandCategory::TwoPair => write!(f, "Two pairs {} and {} with kicker {}", format_cards(&self.primary), format_cards(&self.secondary), self.kickers[0]),
-            HandCategory::ThreeKind => write!(f, "Three of a kind with cards {} with kickers {}", format_cards(&self.primary), format_cards(&self.kickers)),
-            HandCategory::Straight => write!(f, "Straight with cards {}", format_cards(&self.kickers)),
-            HandCategory::Flush => write!(f, "Flush with cards {}", format_cards(&self.kickers)),
-            HandCategory::FullHouse => write!(f, "Full house with card triple {} and pair {}", format_cards(&self.primary), format_cards(&self.secondary)),
-            HandCategory::FourKind => write!(f, "Four of a kind with cards {} and kicker {}", format_cards(&self.primary), self.kickers[0]),
-            HandCategory::StraightFlush => write!(f, "Straight flush with cards {}", format_cards(&self.kickers)),
-            HandCategory::RoyalFlush => write!(f, "Royal flush with cards {}", format_cards(&self.kickers))
+            HandCategory::HighCard => write!(f, "{}-high", rank_name(&self.kickers)),
+            HandCategory::OnePair => write!(f, "Pair of {}", rank_plural(&self.primary)),
+            HandCategory::TwoPair => write!(f, "Two pair, {} and {}", rank_plural(&self.primary), rank_plural(&self.secondary)),
+            HandCategory::ThreeKind => write!(f, "Three of a kind, {}", rank_plural(&self.primary)),
+            HandCategory::Straight => write!(f, "{}-high straight", rank_name(&self.kickers)),
+            HandCategory::Flush => write!(f, "{}-high flush", rank_name(&self.kickers)),
+            HandCategory::FullHouse => write!(f, "Full house, {} over {}", rank_plural(&self.primary), rank_plural(&self.secondary)),
+            HandCategory::FourKind => write!(f, "Four of a kind, {}", rank_plural(&self.primary)),
+            HandCategory::StraightFlush => write!(f, "{}-high straight flush", rank_name(&self.kickers)),
+            HandCategory::RoyalFlush => write!(f, "Royal flush")
         }
     }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ShowdownDecidingFactor {
     Category,
     Primary(Vec<Card>, Vec<Card>),
@@ -146,15 +258,17 @@ pub enum ShowdownDecidingFactor {
     Tie,
 }
 
-fn get_all_combinations(cards: &[Card; 7]) -> [[Card; 5]; 21] {
+// only the first of the returned count are filled in, that's 1 for five cards, 6 for six and 21 for seven
+fn get_all_combinations(cards: &[Card]) -> ([[Card; 5]; 21], usize) {
+    let len = cards.len();
     let mut out = [[cards[0]; 5]; 21];
     let mut n = 0;
 
-    for a in 0..3 {
-        for b in (a + 1)..4 {
-            for c in (b + 1)..5 {
-                for d in (c + 1)..6 {
-                    for e in (d + 1)..7 {
+    for a in 0..(len - 4) {
+        for b in (a + 1)..(len - 3) {
+            for c in (b + 1)..(len - 2) {
+                for d in (c + 1)..(len - 1) {
+                    for e in (d + 1)..len {
                         out[n] = [
                             cards[a],
                             cards[b],
@@ -169,16 +283,17 @@ fn get_all_combinations(cards: &[Card; 7]) -> [[Card; 5]; 21] {
         }
     }
 
-    out
+    (out, n)
 }
 
-fn rank_hand(cards: &[Card; 5]) -> HandRank {
+fn rank_hand(cards: &[Card; 5], deck: DeckKind) -> HandRank {
     let mut hand = *cards;
-    hand.sort_by(|a, b| a.rank.cmp(&b.rank));
+    hand.sort_by_key(|c| c.rank);
 
     let is_flush = hand.into_iter().map(|c| c.suit).all(|c| c == hand[0].suit);
 
-    let is_low_ace = hand[0].rank == 0 && hand[1].rank == 1 && hand[2].rank == 2 && hand[3].rank == 3 && hand[4].rank == 12;
+    let low = deck.lowest_rank();
+    let is_low_ace = hand[0].rank == low && hand[1].rank == low + 1 && hand[2].rank == low + 2 && hand[3].rank == low + 3 && hand[4].rank == 12;
     let is_straight = is_low_ace || hand.windows(2).all(|w| w[0].rank + 1 == w[1].rank);
 
     let mut groups: [Vec<Card>; 13] = Default::default();
@@ -204,11 +319,13 @@ fn rank_hand(cards: &[Card; 5]) -> HandRank {
     }
 
     kickers.sort_by(|a, b| b.cmp(a));
+    // the ace plays low in the wheel, so it goes last and the five leads: 5 4 3 2 A (9 8 7 6 A in short deck)
+    if is_low_ace && is_straight {
+        kickers.rotate_left(1);
+    }
 
     if primary.len() == secondary.len() && let Some(primary_card) = primary.first() && let Some(secondary_card) = secondary.first() && secondary_card.rank > primary_card.rank {
-        let temp = primary;
-        primary = secondary;
-        secondary = temp;
+        std::mem::swap(&mut primary, &mut secondary);
     }
 
     let counts = [groups[0].len(), groups[1].len(), groups[2].len(), groups[3].len(), groups[4].len()];
@@ -230,30 +347,108 @@ fn rank_hand(cards: &[Card; 5]) -> HandRank {
         _ => HandCategory::HighCard
     };
 
-    HandRank { category, primary, secondary, kickers }
+    HandRank { category, primary, secondary, kickers, deck }
+}
+
+// orders hands exactly like compare_hand_ranks without allocating:
+// the category, then the ranks of the primary, secondary and kickers, one per nibble
+fn hand_score(cards: &[Card; 5], deck: DeckKind) -> u32 {
+    let mut counts = [0u8; 13];
+    for card in cards {
+        counts[card.rank as usize] += 1;
+    }
+
+    let is_flush = cards.iter().all(|c| c.suit == cards[0].suit);
+    let lowest = counts.iter().position(|&c| c > 0).unwrap_or(0);
+    let low = deck.lowest_rank() as usize;
+    let is_low_ace = counts[low..low + 4].iter().all(|&c| c == 1) && counts[12] == 1;
+    let is_straight = is_low_ace || (lowest + 5 <= 13 && counts[lowest..lowest + 5].iter().all(|&c| c == 1));
+
+    // bigger groups first, higher ranks first within the same group size
+    let mut score = 0;
+    let mut group_sizes = [0u8; 5];
+    let mut groups = 0;
+    for size in (1..=4).rev() {
+        for rank in (0..13).rev() {
+            if counts[rank] == size {
+                score = score << 4 | rank as u32;
+                group_sizes[groups] = size;
+                groups += 1;
+            }
+        }
+    }
+    score <<= 4 * (5 - groups as u32);
+    if is_low_ace {
+        score = (low as u32 + 3) << 16 | (low as u32 + 2) << 12 | (low as u32 + 1) << 8 | (low as u32) << 4 | 12; // 5 4 3 2 A, same as rank_hand
+    }
+
+    let category = match (group_sizes, is_straight, is_flush) {
+        ([1, 1, 1, 1, 1], true, true) => {
+            if lowest == 8 {
+                HandCategory::RoyalFlush
+            } else {
+                HandCategory::StraightFlush
+            }
+        },
+        ([4, 1, 0, 0, 0], _, _) => HandCategory::FourKind,
+        ([3, 2, 0, 0, 0], _, _) => HandCategory::FullHouse,
+        ([3, 1, 1, 0, 0], _, _) => HandCategory::ThreeKind,
+        ([2, 2, 1, 0, 0], _, _) => HandCategory::TwoPair,
+        ([2, 1, 1, 1, 0], _, _) => HandCategory::OnePair,
+        ([1, 1, 1, 1, 1], false, true) => HandCategory::Flush,
+        ([1, 1, 1, 1, 1], true, false) => HandCategory::Straight,
+        _ => HandCategory::HighCard
+    };
+
+    (deck.category_strength(&category) as u32) << 20 | score
 }
 
+// scores all 21 combinations and only builds the HandRank of the best one, the first one wins ties
 pub fn get_best_hand_rank(cards: &[Card; 7]) -> ([Card; 5], HandRank) {
-    let mut hand_ranks = get_all_combinations(cards).map(|c| (c, rank_hand(&c)));
-    hand_ranks.sort_by(|a, b| b.1.cmp(&a.1));
-    hand_ranks[0].clone()
+    get_best_hand_rank_for(cards, DeckKind::Standard)
+}
+
+pub fn get_best_hand_rank_for(cards: &[Card; 7], deck: DeckKind) -> ([Card; 5], HandRank) {
+    best_hand_from(cards, deck).unwrap()
+}
+
+// the same for any 5 to 7 cards, like a board that isn't all out yet, none for fewer or more
+pub fn best_hand_rank_from(cards: &[Card]) -> Option<HandRank> {
+    best_hand_from(cards, DeckKind::Standard).map(|(_, hand_rank)| hand_rank)
+}
+
+fn best_hand_from(cards: &[Card], deck: DeckKind) -> Option<([Card; 5], HandRank)> {
+    if !(5..=7).contains(&cards.len()) {
+        return None;
+    }
+    let (combinations, count) = get_all_combinations(cards);
+    let mut best = combinations[0];
+    let mut best_score = hand_score(&best, deck);
+    for combination in &combinations[1..count] {
+        let score = hand_score(combination, deck);
+        if score > best_score {
+            best = *combination;
+            best_score = score;
+        }
+    }
+    Some((best, rank_hand(&best, deck)))
 }
 
 pub fn compare_hand_ranks(hand1: &HandRank, hand2: &HandRank) -> (Ordering, ShowdownDecidingFactor) {
-    let category_comparison = hand1.category.cmp(&hand2.category);
+    let category_comparison = hand1.deck.category_strength(&hand1.category).cmp(&hand2.deck.category_strength(&hand2.category));
     if category_comparison != Ordering::Equal {
         return (category_comparison, ShowdownDecidingFactor::Category);
     }
 
     if let Some(a) = hand1.primary.first() && let Some(b) = hand2.primary.first() {
-        let comparison = a.cmp(&b);
+        let comparison = a.cmp(b);
         if comparison != Ordering::Equal {
             return (comparison, ShowdownDecidingFactor::Primary(hand1.primary.clone(), hand2.primary.clone()));
         }
     }
 
     if let Some(a) = hand1.secondary.first() && let Some(b) = hand2.secondary.first() {
-        let comparison = a.cmp(&b);
+        let comparison = a.cmp(b);
         if comparison != Ordering::Equal {
             return (comparison, ShowdownDecidingFactor::Secondary(hand1.secondary.clone(), hand2.secondary.clone()));
         }
@@ -269,3 +464,258 @@ pub fn compare_hand_ranks(hand1: &HandRank, hand2: &HandRank) -> (Ordering, Show
 
     (Ordering::Equal, ShowdownDecidingFactor::Tie)
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Draws {
+    pub flush_draw: bool, // four cards of one suit
+    pub open_ended_straight_draw: bool, // four in a row that either end completes
+    pub gutshot: bool, // four to a straight that only one rank completes
+}
+
+// meant for 4 to 6 cards, like the private cards and a partial board
+pub fn detect_draws(cards: &[Card]) -> Draws {
+    let mut suit_counts = [0u8; 4];
+    // bit 0 is the ace played low, bit 1 the two and so on up to the ace at bit 13
+    let mut ranks = 0u16;
+    for card in cards {
+        if let Some(count) = suit_counts.get_mut(card.suit as usize) {
+            *count += 1;
+        }
+        ranks |= 1 << (card.rank + 1);
+        if card.rank == 12 {
+            ranks |= 1;
+        }
+    }
+
+    let mut draws = Draws { flush_draw: suit_counts.contains(&4), ..Default::default() };
+    for start in 0..=9 {
+        let window = (ranks >> start) & 0b11111;
+        if window.count_ones() != 4 {
+            continue;
+        }
+        let missing = (!window & 0b11111).trailing_zeros();
+        if missing != 0 && missing != 4 {
+            draws.gutshot = true;
+            continue;
+        }
+        // four in a row, open-ended unless it runs into the end of the deck like A234 or JQKA
+        let low = if missing == 0 { start + 1 } else { start };
+        if low >= 1 && low + 3 <= 12 {
+            draws.open_ended_straight_draw = true;
+        } else {
+            draws.gutshot = true;
+        }
+    }
+    draws
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::get_shuffled_deck;
+    use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+    #[test]
+    fn parses_standard_notation() {
+        let card: Card = "Ah".parse().unwrap();
+        assert!(card.full_eq(&Card { rank: 12, suit: 2 }));
+        assert_eq!("Td".parse::<Card>().unwrap().to_string(), "Td");
+        // ten is always T, anything longer than two characters isn't a card
+        assert_eq!("10s".parse::<Card>(), Err(ParseCardError));
+    }
+
+    #[test]
+    fn scores_order_hands_like_compare_hand_ranks() {
+        let mut rng = StdRng::seed_from_u64(261);
+        for deck_kind in [DeckKind::Standard, DeckKind::ShortDeck] {
+            let full_deck = get_shuffled_deck(deck_kind, 0);
+            // with only tens and up most hands make something, so every category comes up
+            let high_cards: Vec<Card> = full_deck.iter().copied().filter(|c| c.rank >= 8).collect();
+            for deck in [&full_deck, &high_cards] {
+                for _ in 0..5000 {
+                    let mut cards: Vec<Card> = deck.choose_multiple(&mut rng, 10).copied().collect();
+                    let a: [Card; 5] = cards.split_off(5).try_into().unwrap();
+                    let b: [Card; 5] = cards.try_into().unwrap();
+                    let (rank_a, rank_b) = (rank_hand(&a, deck_kind), rank_hand(&b, deck_kind));
+                    assert_eq!(hand_score(&a, deck_kind).cmp(&hand_score(&b, deck_kind)), compare_hand_ranks(&rank_a, &rank_b).0, "{} vs {}", format_cards(&a), format_cards(&b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn best_hand_is_the_best_of_all_combinations() {
+        let mut rng = StdRng::seed_from_u64(262);
+        let deck = get_shuffled_deck(DeckKind::Standard, 0);
+        for _ in 0..2000 {
+            let cards: [Card; 7] = deck.choose_multiple(&mut rng, 7).copied().collect::<Vec<_>>().try_into().unwrap();
+            let (_, best) = get_best_hand_rank(&cards);
+            let (combinations, count) = get_all_combinations(&cards);
+            for combination in &combinations[..count] {
+                assert_ne!(compare_hand_ranks(&rank_hand(combination, DeckKind::Standard), &best).0, Ordering::Greater, "{}", format_cards(&cards));
+            }
+        }
+    }
+
+    fn hand(notation: &str) -> HandRank {
+        let cards: Vec<Card> = notation.split_whitespace().map(|card| card.parse().unwrap()).collect();
+        rank_hand(&cards.try_into().unwrap(), DeckKind::Standard)
+    }
+
+    #[test]
+    fn the_wheel_is_the_lowest_straight() {
+        let wheel = hand("Ah 2c 3d 4s 5h");
+        assert_eq!(wheel.category, HandCategory::Straight);
+        assert_eq!(compare_hand_ranks(&wheel, &hand("2d 3h 4c 5s 6d")).0, Ordering::Less);
+        assert_eq!(compare_hand_ranks(&hand("As 2h 3h 4h 5h"), &hand("2s 3s 4s 5s 6s")).0, Ordering::Less);
+    }
+
+    #[test]
+    fn two_wheels_tie() {
+        let (ordering, factor) = compare_hand_ranks(&hand("Ah 2c 3d 4s 5h"), &hand("As 2d 3c 4h 5c"));
+        assert_eq!(ordering, Ordering::Equal);
+        assert!(matches!(factor, ShowdownDecidingFactor::Tie));
+    }
+
+    #[test]
+    fn flushes_go_down_to_the_fifth_card() {
+        let (higher, lower) = (hand("Ah Jh 9h 6h 4h"), hand("As Js 9s 6s 3s"));
+        assert_eq!(higher.category, HandCategory::Flush);
+        assert_eq!(higher.kickers.iter().map(|card| card.rank).collect::<Vec<_>>(), [12, 9, 7, 4, 2]);
+        let (ordering, factor) = compare_hand_ranks(&higher, &lower);
+        assert_eq!(ordering, Ordering::Greater);
+        assert!(matches!(factor, ShowdownDecidingFactor::Kicker(..)));
+        assert_eq!(compare_hand_ranks(&lower, &higher).0, Ordering::Less);
+    }
+
+    #[test]
+    fn best_five_cards_make_the_category() {
+        let seven = |notation: &str| -> [Card; 7] { notation.split_whitespace().map(|card| card.parse().unwrap()).collect::<Vec<Card>>().try_into().unwrap() };
+
+        let (best, rank) = get_best_hand_rank(&seven("Kh 2c 9h 3h Ks Jh 5h"));
+        assert_eq!(rank.category, HandCategory::Flush);
+        assert!(best.iter().all(|card| card.suit == 2));
+        assert!(!best.iter().any(|card| card.full_eq(&"Ks".parse().unwrap())));
+
+        let (best, rank) = get_best_hand_rank(&seven("7c 7d Qs 7h Qd 2s 2c"));
+        assert_eq!(rank.category, HandCategory::FullHouse);
+        assert_eq!(best.iter().filter(|card| card.rank == 5).count(), 3);
+        assert_eq!(best.iter().filter(|card| card.rank == 10).count(), 2);
+    }
+
+    #[test]
+    fn two_cards_of_a_rank_are_only_the_same_card_with_the_suit() {
+        let (five_of_spades, five_of_hearts): (Card, Card) = ("5s".parse().unwrap(), "5h".parse().unwrap());
+        assert!(five_of_spades == five_of_hearts);
+        assert!(!five_of_spades.full_eq(&five_of_hearts));
+        assert!(five_of_spades.full_eq(&"5s".parse().unwrap()));
+        // a second five of spades can't be part of a deal, a five of hearts can
+        let deal: Vec<Card> = "5s Kd 5s 9c 2h".split_whitespace().map(|card| card.parse().unwrap()).collect();
+        assert!(!all_distinct(&deal));
+        let deal: Vec<Card> = "5s Kd 5h 9c 2h".split_whitespace().map(|card| card.parse().unwrap()).collect();
+        assert!(all_distinct(&deal));
+    }
+
+    #[test]
+    fn a_flush_needs_five_of_one_suit() {
+        assert_eq!(hand("2h 6h 9h Jh Kh").category, HandCategory::Flush);
+        // the same ranks with one card off suit are only high card
+        assert_eq!(hand("2h 6h 9h Jh Ks").category, HandCategory::HighCard);
+    }
+
+    #[test]
+    fn every_category_reads_like_at_the_table() {
+        let cases = [
+            ("Ah Jc 9d 6s 4h", "Ace-high"),
+            ("Kh Kc 9d 6s 4h", "Pair of Kings"),
+            ("Kh Kc Td Ts 4h", "Two pair, Kings and Tens"),
+            ("7h 7c 7d Qs 4h", "Three of a kind, Sevens"),
+            ("9h 8c 7d 6s 5h", "Nine-high straight"),
+            ("Ah 2c 3d 4s 5h", "Five-high straight"),
+            ("Ah Jh 9h 6h 4h", "Ace-high flush"),
+            ("Kh Kc Kd Ts Th", "Full house, Kings over Tens"),
+            ("2h 2c 2d 2s Ah", "Four of a kind, Twos"),
+            ("9h 8h 7h 6h 5h", "Nine-high straight flush"),
+            ("Ah Kh Qh Jh Th", "Royal flush"),
+        ];
+        for (notation, description) in cases {
+            assert_eq!(hand(notation).to_string(), description, "{notation}");
+        }
+    }
+
+    fn draws(notation: &str) -> Draws {
+        let cards: Vec<Card> = notation.split_whitespace().map(|card| card.parse().unwrap()).collect();
+        detect_draws(&cards)
+    }
+
+    #[test]
+    fn draws_are_told_apart() {
+        let nothing = Draws::default();
+        assert_eq!(draws("5h 6c 7d 8s Kh"), Draws { open_ended_straight_draw: true, ..nothing });
+        assert_eq!(draws("5h 6c 8d 9s Kh"), Draws { gutshot: true, ..nothing });
+        assert_eq!(draws("2h 6h 9h Kh 4c"), Draws { flush_draw: true, ..nothing });
+        // only one rank completes a straight that runs into either end of the deck
+        assert_eq!(draws("Ah 2c 3d 4s 9h"), Draws { gutshot: true, ..nothing });
+        assert_eq!(draws("Jh Qc Kd As 4h"), Draws { gutshot: true, ..nothing });
+        assert_eq!(draws("2h 7c 9d Js Kh"), nothing);
+    }
+
+    #[test]
+    fn draws_can_come_together() {
+        assert_eq!(draws("5h 6h 7h 8h Kc"), Draws { flush_draw: true, open_ended_straight_draw: true, gutshot: false });
+        // a four or an eight makes a straight, two gutshots but nothing open-ended
+        assert_eq!(draws("5h 6h 7h 9h Kc 3d"), Draws { flush_draw: true, open_ended_straight_draw: false, gutshot: true });
+    }
+
+    #[test]
+    fn a_shuffled_deal_has_no_duplicates() {
+        for seed in 0..200 {
+            let deck = get_shuffled_deck(DeckKind::Standard, seed);
+            assert_eq!(deck.len(), 52);
+            assert!(all_distinct(&deck));
+        }
+        let mut deal = get_shuffled_deck(DeckKind::Standard, 294)[..9].to_vec();
+        assert!(all_distinct(&deal));
+        deal[8] = deal[0];
+        assert!(!all_distinct(&deal));
+    }
+
+    #[test]
+    fn the_short_deck_starts_at_six_and_ranks_flushes_over_full_houses() {
+        let deck = get_shuffled_deck(DeckKind::ShortDeck, 319);
+        assert_eq!(deck.len(), 36);
+        assert!(all_distinct(&deck));
+        assert!(!deck.iter().any(|card| card.to_string().starts_with(['2', '3', '4', '5'])));
+        assert_eq!(deck.iter().map(|card| card.rank).min(), Some(DeckKind::ShortDeck.lowest_rank()));
+
+        let (flush, full_house) = ("Ah Jh 9h 7h 6h", "Tc Td Ts 7s 7d");
+        let short = |notation: &str| rank_hand(&notation.split_whitespace().map(|card| card.parse().unwrap()).collect::<Vec<Card>>().try_into().unwrap(), DeckKind::ShortDeck);
+        assert_eq!(compare_hand_ranks(&short(flush), &short(full_house)).0, Ordering::Greater);
+        assert_eq!(compare_hand_ranks(&hand(flush), &hand(full_house)).0, Ordering::Less);
+    }
+
+    #[test]
+    fn the_best_hand_of_five_or_six_cards() {
+        let cards = |notation: &str| -> Vec<Card> { notation.split_whitespace().map(|card| card.parse().unwrap()).collect() };
+        // on the flop there's only the one hand
+        let flop = best_hand_rank_from(&cards("Kh Kd 7s 7c 2h")).unwrap();
+        assert_eq!(flop.category, HandCategory::TwoPair);
+        assert_eq!(compare_hand_ranks(&flop, &hand("Kh Kd 7s 7c 2h")).0, Ordering::Equal);
+        // the turn makes a full house out of six
+        let turn = best_hand_rank_from(&cards("Kh Kd 7s 7c 2h Ks")).unwrap();
+        assert_eq!(turn.category, HandCategory::FullHouse);
+        assert_eq!(compare_hand_ranks(&turn, &hand("Kh Kd Ks 7s 7c")).0, Ordering::Equal);
+        // a sixth card that makes nothing only plays if it beats the kicker
+        assert_eq!(compare_hand_ranks(&best_hand_rank_from(&cards("Kh Kd 7s 7c 2h 3d")).unwrap(), &hand("Kh Kd 7s 7c 3d")).0, Ordering::Equal);
+        assert!(best_hand_rank_from(&cards("Kh Kd 7s 7c")).is_none());
+    }
+
+    #[test]
+    fn each_suit_has_its_symbol_and_colour() {
+        let suits: Vec<(char, bool)> = ["2c", "2d", "2h", "2s"].iter().map(|s| {
+            let card: Card = s.parse().unwrap();
+            (card.suit_symbol(), card.suit_is_red())
+        }).collect();
+        assert_eq!(suits, vec![('♣', false), ('♦', true), ('♥', true), ('♠', false)]);
+    }
+}
@@ -0,0 +1,189 @@
+use std::{cmp::Ordering, collections::HashMap};
+use rand::{SeedableRng, rngs::StdRng, seq::SliceRandom, thread_rng};
+
+use crate::{cards::{Card, HandCategory, HandRank, get_best_hand_rank}, game::get_deck};
+
+// deals the rest of the board `iterations` times and hands over everyone's rank on each of them
+// false if there's nothing to deal from: a board over 5 cards or not enough cards left
+fn run_boards(hole_cards: &[[Card; 2]], board: &[Card], iterations: u32, seed: u64, mut on_board: impl FnMut(&[HandRank])) -> bool {
+    if hole_cards.is_empty() || board.len() > 5 || iterations == 0 {
+        return false;
+    }
+
+    let mut deck: Vec<Card> = get_deck().into_iter().filter(|c| !hole_cards.iter().flatten().chain(board).any(|k| k.full_eq(c))).collect();
+    let missing = 5 - board.len();
+    if missing > deck.len() {
+        return false;
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut ranks = Vec::with_capacity(hole_cards.len());
+    for _ in 0..iterations {
+        let (drawn, _) = deck.partial_shuffle(&mut rng, missing);
+
+        let mut full_board = [Card { rank: 0, suit: 0 }; 5];
+        full_board[..board.len()].copy_from_slice(board);
+        full_board[board.len()..].copy_from_slice(drawn);
+
+        ranks.clear();
+        ranks.extend(hole_cards.iter().map(|h| get_best_hand_rank(&[full_board[0], full_board[1], full_board[2], full_board[3], full_board[4], h[0], h[1]]).1));
+        on_board(&ranks);
+    }
+    true
+}
+
+// share of the pot each of these hands wins against the others, ties split evenly
+// none of the cards may repeat, and the board can have anything from 0 to 5 cards
+pub fn equity(hole_cards: Vec<[Card; 2]>, board: Vec<Card>, iterations: u32, seed: u64) -> Vec<f64> {
+    let mut shares = vec![0.0; hole_cards.len()];
+    let dealt = run_boards(&hole_cards, &board, iterations, seed, |ranks| {
+        let best = ranks.iter().max().unwrap();
+        let winners = ranks.iter().filter(|r| *r == best).count();
+        for (share, rank) in shares.iter_mut().zip(ranks) {
+            if rank == best {
+                *share += 1.0 / winners as f64;
+            }
+        }
+    });
+    if !dealt {
+        return shares;
+    }
+
+    shares.iter().map(|s| s / iterations as f64).collect()
+}
+
+// how often the first hand wins, ties and loses against the second, same rules as equity
+pub fn head_to_head(first: [Card; 2], second: [Card; 2], board: Vec<Card>, iterations: u32, seed: u64) -> (f64, f64, f64) {
+    let (mut wins, mut ties, mut losses) = (0, 0, 0);
+    let dealt = run_boards(&[first, second], &board, iterations, seed, |ranks| {
+        match ranks[0].cmp(&ranks[1]) {
+            Ordering::Greater => wins += 1,
+            Ordering::Equal => ties += 1,
+            Ordering::Less => losses += 1,
+        }
+    });
+    if !dealt {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let iterations = iterations as f64;
+    (wins as f64 / iterations, ties as f64 / iterations, losses as f64 / iterations)
+}
+
+// how likely these private cards end up as each category once the whole board is out, categories that never came up are left out
+pub fn category_distribution(hole: [Card; 2], iterations: u32, seed: u64) -> HashMap<HandCategory, f64> {
+    let mut counts = HashMap::new();
+    run_boards(&[hole], &[], iterations, seed, |ranks| *counts.entry(ranks[0].category.clone()).or_insert(0) += 1);
+    counts.into_iter().map(|(category, count)| (category, count as f64 / iterations as f64)).collect()
+}
+
+// the pot against what it costs to call as a ratio (3.0 is 3 to 1), and the equity needed to break even in percent
+// nothing to call is a free look, infinitely good odds that need no equity at all
+pub fn pot_odds(to_call: u32, pot: u32) -> (f64, f64) {
+    if to_call == 0 {
+        return (f64::INFINITY, 0.0);
+    }
+    (pot as f64 / to_call as f64, to_call as f64 / (pot as f64 + to_call as f64) * 100.0)
+}
+
+// share of the pot these private cards win against `opponents` random hands, ties split evenly
+pub fn estimate_win_probability(private_cards: &[Card; 2], public_cards: &[Card], opponents: usize, iterations: u32) -> f64 {
+    if opponents == 0 || iterations == 0 {
+        return 1.0;
+    }
+
+    let mut deck: Vec<Card> = get_deck().into_iter().filter(|c| !private_cards.iter().chain(public_cards).any(|k| k.full_eq(c))).collect();
+    let missing_public = 5 - public_cards.len();
+    let needed = missing_public + opponents * 2;
+    if needed > deck.len() {
+        return 0.0;
+    }
+
+    let mut rng = thread_rng();
+    let mut total = 0.0;
+    for _ in 0..iterations {
+        let (drawn, _) = deck.partial_shuffle(&mut rng, needed);
+
+        let mut board = [drawn[0]; 5];
+        board[..public_cards.len()].copy_from_slice(public_cards);
+        board[public_cards.len()..].copy_from_slice(&drawn[..missing_public]);
+
+        let own = get_best_hand_rank(&[board[0], board[1], board[2], board[3], board[4], private_cards[0], private_cards[1]]).1;
+        let mut tied = 1;
+        let mut lost = false;
+        for opponent in drawn[missing_public..].chunks(2) {
+            let other = get_best_hand_rank(&[board[0], board[1], board[2], board[3], board[4], opponent[0], opponent[1]]).1;
+            match other.cmp(&own) {
+                Ordering::Greater => { lost = true; break },
+                Ordering::Equal => tied += 1,
+                Ordering::Less => {}
+            }
+        }
+        if !lost {
+            total += 1.0 / tied as f64;
+        }
+    }
+
+    total / iterations as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cards(notation: &str) -> [Card; 2] {
+        let cards: Vec<Card> = notation.split_whitespace().map(|card| card.parse().unwrap()).collect();
+        cards.try_into().unwrap()
+    }
+
+    #[test]
+    fn aces_crush_seven_deuce() {
+        let (aces, seven_deuce) = (cards("As Ah"), cards("7c 2d"));
+        let (wins, ties, losses) = head_to_head(aces, seven_deuce, Vec::new(), 20000, 264);
+        assert!(wins >= 0.85, "{wins}");
+        assert!((wins + ties + losses - 1.0).abs() < 1e-9);
+        let shares = equity(vec![aces, seven_deuce], Vec::new(), 20000, 264);
+        assert!(shares[0] >= 0.85, "{shares:?}");
+        assert!((shares[0] + shares[1] - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_same_hand_in_other_suits_ties() {
+        let (first, second) = (cards("As Kd"), cards("Ah Kc"));
+        // only a flush in one of the two suits tells them apart
+        let (wins, ties, losses) = head_to_head(first, second, Vec::new(), 20000, 291);
+        assert!(ties > 0.94, "{ties}");
+        assert!((wins - losses).abs() < 0.01, "{wins} against {losses}");
+        // a board with no flush left on it ties every time
+        let board: Vec<Card> = "2c 2d 7h 8s".split_whitespace().map(|card| card.parse().unwrap()).collect();
+        assert_eq!(head_to_head(first, second, board.clone(), 1000, 291), (0.0, 1.0, 0.0));
+        assert_eq!(equity(vec![first, second], board, 1000, 291), [0.5, 0.5]);
+    }
+
+    #[test]
+    fn pocket_pairs_fill_up_more_often() {
+        let rate = |distribution: &HashMap<HandCategory, f64>| [HandCategory::FullHouse, HandCategory::FourKind].iter().map(|category| distribution.get(category).copied().unwrap_or(0.0)).sum::<f64>();
+        let pair = category_distribution(cards("7s 7h"), 20000, 311);
+        let unpaired = category_distribution(cards("7c 2d"), 20000, 311);
+        // roughly 9% against 3%
+        assert!(rate(&pair) > 2.0 * rate(&unpaired), "{} against {}", rate(&pair), rate(&unpaired));
+        // a pair in hand can't end up with less than a pair
+        assert!(!pair.contains_key(&HandCategory::HighCard));
+        for distribution in [&pair, &unpaired] {
+            assert!((distribution.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+        assert_eq!(category_distribution(cards("7s 7h"), 20000, 311), pair);
+    }
+
+    #[test]
+    fn pot_odds_and_the_equity_to_call() {
+        // 30 to win 90 is 3 to 1, calling needs a quarter of the final pot
+        assert_eq!(pot_odds(30, 90), (3.0, 25.0));
+        assert_eq!(pot_odds(50, 50), (1.0, 50.0));
+        let (ratio, needed) = pot_odds(20, 50);
+        assert!((ratio - 2.5).abs() < 1e-9 && (needed - 20.0 / 70.0 * 100.0).abs() < 1e-9);
+        // a free look
+        assert_eq!(pot_odds(0, 90), (f64::INFINITY, 0.0));
+        assert_eq!(pot_odds(0, 0), (f64::INFINITY, 0.0));
+    }
+}
@@ -1,37 +1,79 @@
 use crate::{cards::{Card, HandRank}, game::{Pot, ShowdownStep}};
 
-pub type ShowdownInfo = (Vec<([Card; 2], [Card; 5], HandRank)>, Vec<ShowdownStep>);
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub type ShowdownHand = ([Card; 2], [Card; 5], HandRank); // private cards, best five, rank
+pub type ShowdownInfo = (Vec<Option<ShowdownHand>>, Vec<ShowdownStep>, Vec<u8>); // none for mucked hands, then the seats in the order they show
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ServerBound {
-    Login(String),
+    Login { username: String, buy_in: Option<u32> }, // the stack to sit down with, clamped to the table's buy-in, the table's default without one
     Disconnect,
     Ready(bool),
     GetPlayerList,
-    GameAction(GamePlayerAction)
+    GameAction(GamePlayerAction),
+    Hello(u16), // protocol version, the first thing a client sends
+    Ping,
+    Pong,
+    Chat(String),
+    Spectate, // watch the lobby and its games without a seat, logging in ends it
+    Reconnect(u64), // session token from an earlier connection, takes its seat back
+    JoinRoom(u32), // everyone starts out in room 0
+    CreateRoom,
+    Admin(String), // e.g. "kick 2", only listened to from seat 0 between games
+    SitOut(bool), // false sits back in, takes effect from the next hand
+    RequestGameState, // answered with the events that rebuild the hand in progress, nothing between hands
+    Rebuy(u32), // how much to add, only between hands and up to the table's max buy-in
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ClientBound {
     UpdatePlayerList(Vec<(PlayerState, u32, String)>), // state, money, username
     YourIndex(u8),
     PlayerLeft(String),
     PlayerJoined(String),
     GameStarted([Card; 2]), // player id and private cards
-    GameEvent(GameEvent)
+    GameEvent(GameEvent),
+    Welcome { version: u16, accepted: bool }, // the server's protocol version, clients that don't match get disconnected
+    Ping,
+    Pong,
+    ChatBroadcast { player: u8, message: String }, // player is the sender's index in the player list
+    SessionToken(u64), // sent on login, lets a dropped connection reclaim its seat
+    RoomCreated(u32), // the creator is moved into it right away
+    LoginRejected(String), // why the name or the seat wasn't accepted, to show to the player
+    ServerShutdown, // the connection closes right after
+    RebuyResult { accepted: bool, money: u32 }, // money is the stack afterwards either way
+    Countdown(u8), // seconds until the hand is dealt, 0 means it was called off
+    YourTurn { to_call: u32, min_raise: u32, can_check: bool }, // only to the player whose turn it is, to_call can be more than they have
+    TimeBankUsed { player: u8, remaining: u32 }, // their turn time ran out, remaining is how many seconds of time bank they're on now
+    PotTotal(u32), // follows every UpdatePots, all of the pots added up
+    Error { code: u16, message: String }, // a rejected action or packet, only to whoever sent it, the message is for showing to the player
+    ServerFull, // sent instead of anything else when the server is at its connection limit, the connection closes right after
 }
 
 // the client is able to tell when something is a check, call, bet, raise or an all-in
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum GamePlayerAction {
     Check,
     AddMoney(u32), // can be anything: call, bet, raise, all-in
     Fold,
+    Call, // the server works out the amount, calling for less is an all-in
+    Bet(u32), // opening the betting on a street, only while there's no bet yet
+    Raise(u32), // how much over the current bet, on top of calling it
+    Muck, // only for beaten players once the betting is over
+    Show,
+    RunItTwice(bool), // only once offered, one player saying no is enough to run it once
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum GameEvent {
     PlayerAction(u8, GamePlayerAction),
+    PlayerAllIn(u8), // right after whatever put the player's last chip in
     OwnedMoneyChange(u8, u32),
     NextPlayer(u8),
     UpdateCurrentBet(u32),
@@ -39,17 +81,23 @@ pub enum GameEvent {
     RevealFlop([Card; 3]),
     RevealTurn(Card),
     RevealRiver(Card),
-    Showdown(ShowdownInfo),
-    InGamePlayerLeave(u8)
+    Showdown(ShowdownInfo), // a hand that was run twice has one for each run, each paying out half of every pot
+    InGamePlayerLeave(u8),
+    MuckOrShow(Vec<u8>), // the betting is over, these beaten players get to decide before the showdown goes out
+    WonUncontested(u8, u32), // everyone else folded, the winnings are after the rake and nothing gets shown
+    RunItTwiceOffer(Vec<u8>), // everyone in the hand is all-in with cards to come, these seats get to vote on running it twice
+    RevealSecondBoard(Vec<Card>), // the cards the second run deals, the first run's board comes out before it
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PlayerState {
     NotReady,
     Ready,
     InGame,
     Folded,
-    Left
+    Left,
+    SittingOut, // seated but not dealt in until they sit back in
 }
 impl PlayerState {
     pub fn from_byte(byte: u8) -> Option<Self> {
@@ -59,7 +107,102 @@ impl PlayerState {
             2 => Self::InGame,
             3 => Self::Folded,
             4 => Self::Left,
+            5 => Self::SittingOut,
             _ => return None
         })
     }
 }
+
+// one of every message, the game events come from a real hand so the showdown is one that can happen
+#[cfg(test)]
+pub(crate) mod samples {
+    use super::*;
+    use crate::game::{GameConfig, make_game_seeded};
+
+    pub(crate) fn game_events() -> Vec<GameEvent> {
+        let mut game = make_game_seeded(vec![1000, 500, 1500], GameConfig::default(), 0, 5).unwrap();
+        let mut events = game.start_events();
+        events.extend(game.advance_game(GamePlayerAction::Raise(20)).unwrap());
+        let all_in = game.players[game.current_turn as usize].money;
+        events.extend(game.advance_game(GamePlayerAction::AddMoney(all_in)).unwrap());
+        while let Ok(new_events) = game.advance_game(GamePlayerAction::Call) {
+            events.extend(new_events);
+        }
+        events.extend(game.muck_remaining());
+        assert!(events.iter().any(|event| matches!(event, GameEvent::Showdown(_))));
+        let card = "As".parse().unwrap();
+        events.extend([
+            GameEvent::PlayerAction(0, GamePlayerAction::Check),
+            GameEvent::PlayerAction(1, GamePlayerAction::Fold),
+            GameEvent::PlayerAction(2, GamePlayerAction::Bet(40)),
+            GameEvent::PlayerAction(0, GamePlayerAction::Muck),
+            GameEvent::PlayerAction(1, GamePlayerAction::Show),
+            GameEvent::PlayerAction(2, GamePlayerAction::RunItTwice(true)),
+            GameEvent::InGamePlayerLeave(1),
+            GameEvent::MuckOrShow(vec![0, 2]),
+            GameEvent::WonUncontested(2, 150),
+            GameEvent::RunItTwiceOffer(vec![0, 1]),
+            GameEvent::RevealSecondBoard(vec![card]),
+        ]);
+        events
+    }
+
+    pub(crate) fn client_bound() -> Vec<ClientBound> {
+        let card = "Td".parse().unwrap();
+        let mut samples = vec![
+            ClientBound::UpdatePlayerList(vec![(PlayerState::NotReady, 1000, "alice".to_string()), (PlayerState::Ready, 0, "bob".to_string()), (PlayerState::InGame, 5, "carol".to_string()), (PlayerState::Folded, 6, "dave".to_string()), (PlayerState::Left, 7, "erin".to_string()), (PlayerState::SittingOut, 8, "frank".to_string())]),
+            ClientBound::YourIndex(3),
+            ClientBound::PlayerLeft("bob".to_string()),
+            ClientBound::PlayerJoined("carol".to_string()),
+            ClientBound::GameStarted([card, "2c".parse().unwrap()]),
+            ClientBound::Welcome { version: 7, accepted: false },
+            ClientBound::Ping,
+            ClientBound::Pong,
+            ClientBound::ChatBroadcast { player: 2, message: "nice hand".to_string() },
+            ClientBound::SessionToken(u64::MAX - 1),
+            ClientBound::RoomCreated(300),
+            ClientBound::LoginRejected("This username is already taken!".to_string()),
+            ClientBound::ServerShutdown,
+            ClientBound::RebuyResult { accepted: true, money: 2000 },
+            ClientBound::Countdown(5),
+            ClientBound::YourTurn { to_call: 20, min_raise: 40, can_check: false },
+            ClientBound::TimeBankUsed { player: 1, remaining: 30 },
+            ClientBound::PotTotal(450),
+            ClientBound::Error { code: 100, message: "you can't check while facing a bet".to_string() },
+            ClientBound::ServerFull,
+        ];
+        samples.extend(game_events().into_iter().map(ClientBound::GameEvent));
+        samples
+    }
+
+    pub(crate) fn server_bound() -> Vec<ServerBound> {
+        vec![
+            ServerBound::Login { username: "alice".to_string(), buy_in: None },
+            ServerBound::Login { username: "bob".to_string(), buy_in: Some(500) },
+            ServerBound::Disconnect,
+            ServerBound::Ready(true),
+            ServerBound::GetPlayerList,
+            ServerBound::GameAction(GamePlayerAction::Check),
+            ServerBound::GameAction(GamePlayerAction::AddMoney(30)),
+            ServerBound::GameAction(GamePlayerAction::Fold),
+            ServerBound::GameAction(GamePlayerAction::Call),
+            ServerBound::GameAction(GamePlayerAction::Bet(20)),
+            ServerBound::GameAction(GamePlayerAction::Raise(40)),
+            ServerBound::GameAction(GamePlayerAction::Muck),
+            ServerBound::GameAction(GamePlayerAction::Show),
+            ServerBound::GameAction(GamePlayerAction::RunItTwice(false)),
+            ServerBound::Hello(26),
+            ServerBound::Ping,
+            ServerBound::Pong,
+            ServerBound::Chat("hi".to_string()),
+            ServerBound::Spectate,
+            ServerBound::Reconnect(12345),
+            ServerBound::JoinRoom(2),
+            ServerBound::CreateRoom,
+            ServerBound::Admin("kick 2".to_string()),
+            ServerBound::SitOut(true),
+            ServerBound::RequestGameState,
+            ServerBound::Rebuy(250),
+        ]
+    }
+}
@@ -1,9 +1,13 @@
-use std::cmp::{Ordering, max};
-use rand::{seq::SliceRandom, thread_rng};
+use std::{cmp::Ordering, fmt::Display, sync::atomic::{self, AtomicU64, AtomicU8}, time::{Duration, Instant}};
+use rand::{RngCore, SeedableRng, rngs::StdRng, seq::SliceRandom, thread_rng};
 
-use crate::{cards::{Card, HandRank, ShowdownDecidingFactor, compare_hand_ranks, get_best_hand_rank}, events::{GameEvent, GamePlayerAction, ShowdownInfo}};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{cards::{Card, DeckKind, HandRank, ShowdownDecidingFactor, all_distinct, compare_hand_ranks, get_best_hand_rank_for}, events::{GameEvent, GamePlayerAction, ShowdownHand, ShowdownInfo}, history::{HandHistory, HistoryEvent}, strategy::PlayerView};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Pot {
     pub money: u32,
     pub eligible_players: Vec<u8>,
@@ -13,119 +17,667 @@ pub struct Pot {
 pub struct Player {
     pub id: u8,
     pub money: u32,
-    total_contribution: u32,
+    pub(crate) total_contribution: u32,
+    round_contribution: u32, // only what was added in the current betting round
+    street_contributions: [u32; 4], // preflop, flop, turn and river, antes and missed blinds count for the street they were paid on
     pub private_cards: [Card; 2],
     pub has_folded: bool,
 }
+// all-in players are still in the hand, they just can't act anymore
+impl Player {
+    pub fn can_act(&self) -> bool {
+        self.in_hand() && self.money > 0
+    }
+
+    pub fn in_hand(&self) -> bool {
+        !self.has_folded
+    }
+
+    pub fn redacted(&self) -> RedactedPlayer {
+        RedactedPlayer { id: self.id, money: self.money, total_contribution: self.total_contribution, has_folded: self.has_folded }
+    }
+}
 
+// everything about a seat the whole table may know, without the hole cards so they can't end up broadcast by accident
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RedactedPlayer {
+    pub id: u8,
+    pub money: u32,
+    pub total_contribution: u32,
+    pub has_folded: bool,
+}
+
+#[derive(Clone)]
 pub struct Game {
     pub players: Vec<Player>,
-    pub current_bet: u32,
+    pub current_bet: u32, // highest contribution in the current betting round
     current_phase: u8, // 0 - 4, preflop, flop, turn, river, showdown
     pub current_turn: u8,
-    last_bettor: u8,
-    public_cards: [Card; 5],
+    last_bettor: u8, // the betting round ends when the action comes back to this player
+    pub min_raise: u32, // size of the last legal raise in this round, a raise has to be at least this much over the current bet
+    acted_since_full_raise: Vec<u8>, // seats that can only call or fold until someone raises in full, an all-in for less doesn't reopen the betting
+    pub(crate) config: GameConfig,
+    pub button: u8,
+    pub seed: Option<u64>, // none if the deck didn't come from a seed
+    pub(crate) public_cards: [Card; 5],
+    history: HandHistory,
+    pending_showdown: Option<PendingShowdown>,
+    pub turn_deadline: Option<Instant>, // none without a turn time in the config
+    time_banks: Vec<Duration>, // by seat, extra time for when the turn time runs out
+    time_bank_started: Option<(u8, Instant)>, // the seat dipping into their time bank right now
+    spare_cards: Vec<Card>, // what's left of the deck after the deal, the second run comes from here
+    run_it_twice_vote: Option<Vec<u8>>, // seats yet to vote while the offer is open
+    second_board: Option<[Card; 5]>, // once everyone agreed to run it twice
+}
+
+// the pots are already paid out, only what gets shown is still open
+#[derive(Clone)]
+struct PendingShowdown {
+    info: Vec<ShowdownInfo>, // one for each run
+    undecided: Vec<u8>,
+    shown: Vec<bool>, // by seat
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ShowdownStep {
-    pub winners: Vec<u8>,
-    pub winnings: u32,
+    pub winners: Vec<u8>, // starting left of the button, the first ones got the odd chips of a split
+    pub winnings: u32, // what the winners got, after the rake
+    pub rake_taken: u32,
     pub pot_start_index: u8, // players can win multiple pots next to each other at once, both of those are inclusive
     pub pot_end_index: u8,
     pub eligible_players: Vec<u8>,
     pub win_reason: Option<(ShowdownDecidingFactor, u8)>, // only used if there's one winner
 }
 
+// what the player whose turn it is may do, raises count from the current bet like GamePlayerAction::Raise
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LegalActions {
+    pub can_check: bool,
+    pub can_call: Option<u32>, // how much calling adds, less than the bet for a short stack
+    pub min_raise: Option<u32>, // none if the player can't raise, all-ins for less than a full raise bring it down to max_raise
+    pub max_raise: u32,
+    pub can_fold: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ActionError {
+    CannotCheckFacingBet,
+    RaiseBelowMinimum, // not enough money added to call or raise
+    InsufficientFunds,
+    NotYourTurn,
+    HandOver,
+    NotAtShowdown,
+    RaiseAboveMaximum, // over the pot in pot-limit, or not the fixed size in fixed-limit
+    RaiseCapReached, // fixed-limit streets only take so many bets
+    NoRunItTwiceOffer,
+    AlreadyABet, // a Bet while facing one, that has to be a Raise
+    NothingToRaise, // a Raise with no bet yet, that has to be a Bet
+    BettingNotReopened, // a raise after only an all-in for less than a full raise came back around
+}
+impl Display for ActionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionError::CannotCheckFacingBet => write!(f, "you can't check while facing a bet"),
+            ActionError::RaiseBelowMinimum => write!(f, "that's not enough money to call or raise"),
+            ActionError::InsufficientFunds => write!(f, "you don't have that much money"),
+            ActionError::NotYourTurn => write!(f, "it's not your turn"),
+            ActionError::HandOver => write!(f, "the hand is already over"),
+            ActionError::NotAtShowdown => write!(f, "you can only muck or show after losing at the showdown"),
+            ActionError::RaiseAboveMaximum => write!(f, "that's more than the betting limit allows"),
+            ActionError::RaiseCapReached => write!(f, "there can't be any more raises this round"),
+            ActionError::NoRunItTwiceOffer => write!(f, "there's no offer to run it twice to vote on"),
+            ActionError::AlreadyABet => write!(f, "there's already a bet, raise it instead"),
+            ActionError::NothingToRaise => write!(f, "there's no bet to raise, bet instead"),
+            ActionError::BettingNotReopened => write!(f, "an all-in for less than a raise doesn't let you raise again"),
+        }
+    }
+}
+impl std::error::Error for ActionError {}
+impl ActionError {
+    // what ClientBound::Error carries, these stay the same between versions
+    pub fn code(&self) -> u16 {
+        match self {
+            ActionError::CannotCheckFacingBet => 100,
+            ActionError::RaiseBelowMinimum => 101,
+            ActionError::InsufficientFunds => 102,
+            ActionError::NotYourTurn => 103,
+            ActionError::HandOver => 104,
+            ActionError::NotAtShowdown => 105,
+            ActionError::RaiseAboveMaximum => 106,
+            ActionError::RaiseCapReached => 107,
+            ActionError::NoRunItTwiceOffer => 108,
+            ActionError::AlreadyABet => 109,
+            ActionError::NothingToRaise => 110,
+            ActionError::BettingNotReopened => 111,
+        }
+    }
+}
+
 impl Game {
-    pub fn advance_game(&mut self, action: GamePlayerAction) -> Option<Vec<GameEvent>> { // none means illegal action
-        if self.current_phase == 4 { return None }
-        let player = self.players.get_mut(self.current_turn as usize).unwrap();
+    // an error leaves the game exactly as it was
+    pub fn advance_game(&mut self, action: GamePlayerAction) -> Result<Vec<GameEvent>, ActionError> {
+        let events = self.play_action(action)?;
+        for event in &events {
+            self.history.record(event);
+        }
+        self.reset_turn_deadline();
+        Ok(events)
+    }
+
+    fn reset_turn_deadline(&mut self) {
+        // whatever part of the time bank wasn't needed is kept for later
+        if let Some((seat, started)) = self.time_bank_started.take() {
+            let bank = &mut self.time_banks[seat as usize];
+            *bank = bank.saturating_sub(started.elapsed());
+        }
+        self.turn_deadline = self.config.turn_time.filter(|_| self.current_phase < 4).map(|time| Instant::now() + time);
+    }
+
+    // acts for the current player once their time is up: checks if nothing is owed, folds otherwise
+    // a player with time bank left gets all of it on top first, using_time_bank tells when that happened
+    pub fn check_timeout(&mut self, now: Instant) -> Vec<GameEvent> {
+        let Some(deadline) = self.turn_deadline.filter(|&deadline| now >= deadline) else {
+            return Vec::new();
+        };
+        if self.run_it_twice_vote.is_some() {
+            return self.decline_run_it_twice_remaining();
+        }
+        let bank = self.time_banks[self.current_turn as usize];
+        match self.time_bank_started {
+            None if !bank.is_zero() => {
+                self.time_bank_started = Some((self.current_turn, deadline));
+                self.turn_deadline = Some(deadline + bank);
+                return Vec::new();
+            },
+            Some((seat, _)) => {
+                self.time_bank_started = None;
+                self.time_banks[seat as usize] = Duration::ZERO;
+            },
+            None => {},
+        }
+        let action = if self.legal_actions().can_check { GamePlayerAction::Check } else { GamePlayerAction::Fold };
+        self.advance_game(action).unwrap_or_default()
+    }
+
+    // seats that can still act, which leaves out all-in players as well as folded ones
+    pub fn active_players(&self) -> Vec<u8> {
+        self.players.iter().filter(|p| p.can_act()).map(|p| p.id).collect()
+    }
+
+    // everyone who hasn't folded, all-in or not
+    pub fn players_in_hand(&self) -> usize {
+        self.players.iter().filter(|p| p.in_hand()).count()
+    }
+
+    // nothing is legal once the hand is over or while the run it twice vote is open
+    pub fn legal_actions(&self) -> LegalActions {
+        if self.current_phase >= 4 || self.run_it_twice_vote.is_some() {
+            return LegalActions::default();
+        }
+        let player = self.players[self.current_turn as usize];
+        let to_call = self.current_bet.saturating_sub(player.round_contribution);
+        let stack_raise = player.money.saturating_sub(to_call);
+        let (min_raise, max_raise) = self.config.betting.raise_limits(self.current_phase, self.current_bet, self.min_raise, self.total_pot() + to_call)
+            .filter(|_| !self.acted_since_full_raise.contains(&self.current_turn))
+            .map_or((0, 0), |(min, max)| (min.min(max).min(stack_raise), max.min(stack_raise)));
+        LegalActions {
+            can_check: to_call == 0 || player.money == 0,
+            can_call: Some(to_call.min(player.money)).filter(|&call| call > 0),
+            min_raise: Some(min_raise).filter(|_| max_raise > 0),
+            max_raise,
+            can_fold: true,
+        }
+    }
+
+    pub fn using_time_bank(&self) -> bool {
+        self.time_bank_started.is_some()
+    }
+
+    // what's left of a seat's time bank, not counting what the current player has used up so far
+    pub fn time_bank(&self, seat: u8) -> Duration {
+        self.time_banks[seat as usize]
+    }
+
+    // time banks last across hands, so whoever runs the table carries them over from the last one
+    pub fn set_time_bank(&mut self, seat: u8, bank: Duration) {
+        self.time_banks[seat as usize] = bank;
+    }
+
+    fn play_action(&mut self, action: GamePlayerAction) -> Result<Vec<GameEvent>, ActionError> {
+        if self.current_phase == 4 { return Err(ActionError::HandOver) }
+        if self.run_it_twice_vote.is_some() { return Err(ActionError::NotYourTurn) } // nobody bets while the vote is open
+        let seat = self.current_turn;
+        let pot = self.total_pot();
+        let legal = self.legal_actions();
+        let player = self.players.get_mut(seat as usize).unwrap();
+        let action = match action {
+            GamePlayerAction::Call => match self.current_bet.saturating_sub(player.round_contribution).min(player.money) {
+                0 => GamePlayerAction::Check,
+                to_call => GamePlayerAction::AddMoney(to_call),
+            },
+            GamePlayerAction::Bet(_) if self.current_bet > 0 => return Err(ActionError::AlreadyABet),
+            GamePlayerAction::Bet(money) => GamePlayerAction::AddMoney(money),
+            GamePlayerAction::Raise(_) if self.current_bet == 0 => return Err(ActionError::NothingToRaise),
+            GamePlayerAction::Raise(0) => return Err(ActionError::RaiseBelowMinimum),
+            GamePlayerAction::Raise(raise) => GamePlayerAction::AddMoney((self.current_bet - player.round_contribution).saturating_add(raise)),
+            action => action,
+        };
         let mut events = Vec::<GameEvent>::new();
         match action {
             GamePlayerAction::AddMoney(money) => {
+                if money > player.money {
+                    return Err(ActionError::InsufficientFunds)
+                }
                 if money == 0 {
-                    return None
+                    return Err(ActionError::RaiseBelowMinimum)
                 }
-                if player.total_contribution + money < self.current_bet && money != player.money { // all-ins are only recognized if the bet money is exactly equal to the player's money
-                    return None
+                if player.round_contribution + money < self.current_bet && money != player.money { // all-ins are only recognized if the bet money is exactly equal to the player's money
+                    return Err(ActionError::RaiseBelowMinimum)
                 }
-                if money > player.money {
-                    return None
+                let raise = (player.round_contribution + money).saturating_sub(self.current_bet);
+                if raise > 0 && raise < self.min_raise && money != player.money { // all-ins for less are still allowed
+                    return Err(ActionError::RaiseBelowMinimum)
+                }
+                if raise > 0 && self.acted_since_full_raise.contains(&seat) {
+                    return Err(ActionError::BettingNotReopened)
+                }
+                if raise > 0 {
+                    let to_call = self.current_bet.saturating_sub(player.round_contribution);
+                    self.config.betting.check_raise(self.current_phase, self.current_bet, raise, pot + to_call, money == player.money)?;
+                }
+                if raise >= self.min_raise {
+                    self.min_raise = raise;
+                    self.acted_since_full_raise.clear();
                 }
-                
-                self.current_bet = max(self.current_bet, player.total_contribution + money); // has to be done so that all-ins dont lower the bet
-                events.push(GameEvent::UpdateCurrentBet(self.current_bet));
-
-                self.last_bettor = self.current_turn;
 
                 player.money -= money;
+                player.round_contribution += money;
                 player.total_contribution += money;
-                events.push(GameEvent::OwnedMoneyChange(self.current_turn, player.money));
+                player.street_contributions[self.current_phase as usize] += money;
 
-                events.push(GameEvent::PlayerAction(self.current_turn, GamePlayerAction::AddMoney(money)));
+                if player.round_contribution > self.current_bet { // all-ins for less than the bet don't lower it
+                    self.current_bet = player.round_contribution;
+                    self.last_bettor = seat;
+                    events.push(GameEvent::UpdateCurrentBet(self.current_bet));
+                }
 
+                events.push(GameEvent::OwnedMoneyChange(seat, player.money));
+                events.push(GameEvent::PlayerAction(seat, GamePlayerAction::AddMoney(money)));
+                if player.money == 0 {
+                    events.push(GameEvent::PlayerAllIn(seat));
+                }
                 events.push(GameEvent::UpdatePots(self.compute_pots()));
             },
             GamePlayerAction::Fold => {
                 player.has_folded = true;
-                events.push(GameEvent::PlayerAction(self.current_turn, GamePlayerAction::Fold))
+                events.push(GameEvent::PlayerAction(seat, GamePlayerAction::Fold))
             },
             GamePlayerAction::Check => {
-                if self.current_bet > player.total_contribution && player.money != 0 {
-                    return None;
+                if !legal.can_check {
+                    return Err(ActionError::CannotCheckFacingBet);
                 }
-                events.push(GameEvent::PlayerAction(self.current_turn, GamePlayerAction::Check))
-            }
+                events.push(GameEvent::PlayerAction(seat, GamePlayerAction::Check))
+            },
+            GamePlayerAction::Muck | GamePlayerAction::Show => return Err(ActionError::NotAtShowdown),
+            GamePlayerAction::RunItTwice(_) => return Err(ActionError::NoRunItTwiceOffer),
+            GamePlayerAction::Call | GamePlayerAction::Bet(_) | GamePlayerAction::Raise(_) => unreachable!(),
         }
-        
-        if self.players.iter().filter(|&&p| p.money > 0 && !p.has_folded).count() == 1 {
-            events.push(GameEvent::Showdown(self.evaluate_showdown()));
-            return Some(events);
+
+        self.acted_since_full_raise.push(seat);
+
+        if self.players_in_hand() == 1 {
+            events.push(self.award_uncontested());
+            return Ok(events);
         }
-        
+
+        // the betting round is over once the action gets back to whoever bet last (or opened the round),
+        // even if they can't act anymore because they folded or went all-in
         let player_count = self.players.len() as u8;
-        let mut next_turn = (self.current_turn + 1) % player_count;
-        while let Some(&p) = self.players.get(next_turn as usize) {
-            if !p.has_folded && p.money > 0 {
+        let mut round_over = false;
+        let mut next_turn = None;
+        for offset in 1..=player_count {
+            let candidate = (seat + offset) % player_count;
+            if candidate == self.last_bettor {
+                round_over = true;
+            }
+            if self.players[candidate as usize].can_act() {
+                next_turn = Some(candidate);
                 break;
             }
-            next_turn = (next_turn + 1) % player_count;
-        } 
-
-        if self.current_turn == self.last_bettor && matches!(action, GamePlayerAction::Check) {
-            match self.current_phase {
-                0 => events.push(GameEvent::RevealFlop(self.public_cards[0..3].try_into().unwrap())),
-                1 => events.push(GameEvent::RevealTurn(self.public_cards[3])),
-                2 => events.push(GameEvent::RevealRiver(self.public_cards[4])),
-                3 => events.push(GameEvent::Showdown(self.evaluate_showdown())),
-                _ => {} // should never happen
+        }
+
+        if !round_over && let Some(next_turn) = next_turn {
+            self.current_turn = next_turn;
+            events.push(GameEvent::NextPlayer(next_turn));
+            return Ok(events);
+        }
+
+        if self.active_players().len() < 2 { // nobody left to bet against, so the rest of the board comes right away
+            if self.can_run_it_twice() {
+                let seats: Vec<u8> = self.players.iter().filter(|p| p.in_hand()).map(|p| p.id).collect();
+                self.run_it_twice_vote = Some(seats.clone());
+                events.push(GameEvent::RunItTwiceOffer(seats));
+            } else {
+                events.extend(self.run_out());
+            }
+            return Ok(events);
+        }
+
+        match self.reveal_event(self.current_phase) {
+            Some(reveal) => events.push(reveal),
+            None => {
+                events.extend(self.start_showdown());
+                return Ok(events);
+            }
+        }
+        self.current_phase += 1;
+
+        // every street starts from scratch, with the first player after the button opening the betting
+        self.current_bet = 0;
+        self.min_raise = self.config.blinds.big_blind;
+        self.acted_since_full_raise.clear();
+        for player in self.players.iter_mut() {
+            player.round_contribution = 0;
+        }
+        events.push(GameEvent::UpdateCurrentBet(0));
+
+        let first = self.next_actor_after(self.button);
+        self.current_turn = first;
+        self.last_bettor = first;
+        events.push(GameEvent::NextPlayer(first));
+
+        Ok(events)
+    }
+
+    // describes the hand as it stands to someone who missed how it got there
+    pub fn state_events(&self) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        for player in self.public_view() {
+            events.push(GameEvent::OwnedMoneyChange(player.id, player.money));
+            if player.has_folded {
+                events.push(GameEvent::PlayerAction(player.id, GamePlayerAction::Fold));
+            } else if player.money == 0 {
+                events.push(GameEvent::PlayerAllIn(player.id));
+            }
+        }
+        events.extend((0..self.current_phase.min(3)).filter_map(|phase| self.reveal_event(phase)));
+        events.push(GameEvent::UpdateCurrentBet(self.current_bet));
+        events.push(GameEvent::UpdatePots(self.compute_pots()));
+        events.push(GameEvent::NextPlayer(self.current_turn));
+        events
+    }
+
+    // the events that tell clients about the antes and blinds posted in make_game
+    pub fn start_events(&self) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        for player in self.players.iter().filter(|p| p.total_contribution > 0) {
+            events.push(GameEvent::OwnedMoneyChange(player.id, player.money));
+            if player.round_contribution > 0 {
+                events.push(GameEvent::PlayerAction(player.id, GamePlayerAction::AddMoney(player.round_contribution)));
+            }
+            if player.money == 0 {
+                events.push(GameEvent::PlayerAllIn(player.id));
             }
-            self.current_phase += 1;
         }
+        events.push(GameEvent::UpdateCurrentBet(self.current_bet));
+        events.push(GameEvent::UpdatePots(self.compute_pots()));
+        events.push(GameEvent::NextPlayer(self.current_turn));
+        events
+    }
+
+    // the cards that get revealed when leaving the given phase, none after the river
+    fn reveal_event(&self, phase: u8) -> Option<GameEvent> {
+        match phase {
+            0 => Some(GameEvent::RevealFlop(self.public_cards[0..3].try_into().unwrap())),
+            1 => Some(GameEvent::RevealTurn(self.public_cards[3])),
+            2 => Some(GameEvent::RevealRiver(self.public_cards[4])),
+            _ => None
+        }
+    }
+
+    // before the first action only, like antes it's in the pot without counting towards calling
+    pub fn post_missed_blinds(&mut self, seat: u8, amount: u32) {
+        let player = &mut self.players[seat as usize];
+        let amount = amount.min(player.money);
+        player.money -= amount;
+        player.total_contribution += amount;
+        player.street_contributions[self.current_phase.min(3) as usize] += amount;
+        self.history.push(HistoryEvent::MissedBlindsPosted(seat, amount));
+        // going all-in on it means the action starts with the next player
+        if self.current_turn == seat && !self.players[seat as usize].can_act() {
+            self.current_turn = self.next_actor_after(seat);
+            self.last_bettor = self.current_turn;
+        }
+    }
+
+    fn next_actor_after(&self, seat: u8) -> u8 {
+        let player_count = self.players.len() as u8;
+        (1..=player_count).map(|offset| (seat + offset) % player_count).find(|&s| self.players[s as usize].can_act()).unwrap_or(seat)
+    }
+
+    // deals the following hand to everyone who still has enough money, at the blinds the schedule has for that hand, time banks carry over
+    // the button moves on to the next player left, and seats close up around eliminated players
+    pub fn next_hand(&self, schedule: &BlindSchedule, hand_number: u32) -> Result<Game, GameSetupError> {
+        let survivors: Vec<&Player> = self.players.iter().filter(|p| self.config.buy_in.can_be_dealt(p.money)).collect();
+        let player_count = self.players.len() as u8;
+        let button = (1..=player_count).map(|offset| (self.button + offset) % player_count)
+            .find_map(|seat| survivors.iter().position(|p| p.id == seat))
+            .unwrap_or(0);
+
+        let config = GameConfig { blinds: schedule.level(hand_number).unwrap_or(self.config.blinds), ..self.config };
+        let mut game = make_game(survivors.iter().map(|p| p.money).collect(), config, button as u8)?;
+        for (seat, player) in survivors.iter().enumerate() {
+            game.set_time_bank(seat as u8, self.time_bank(player.id));
+        }
+        Ok(game)
+    }
+
+    // deals exactly these cards instead of shuffling, with the button on seat 0, so specific hands can be set up
+    // every seat from 0 up has to be given once and no card can show up twice
+    pub fn new_with_cards(mut players: Vec<(u8, u32, [Card; 2])>, board: [Card; 5], blinds: BlindConfig) -> Result<Game, GameSetupError> {
+        players.sort_by_key(|&(seat, _, _)| seat);
+        if players.iter().enumerate().any(|(i, &(seat, _, _))| seat as usize != i) {
+            return Err(GameSetupError::InvalidDeal);
+        }
+        let mut deck: Vec<Card> = players.iter().flat_map(|&(_, _, cards)| cards).chain(board).collect();
+        if !all_distinct(&deck) {
+            return Err(GameSetupError::InvalidDeal);
+        }
+        deck.reverse();
+        make_game_with_deck(players.iter().map(|&(_, money, _)| money).collect(), GameConfig { blinds, ..Default::default() }, 0, deck)
+    }
 
-        self.current_turn = next_turn;
+    // hands the history over, meant to be called once the hand is over
+    pub fn take_history(&mut self) -> HandHistory {
+        std::mem::take(&mut self.history)
+    }
 
-        events.push(GameEvent::NextPlayer(next_turn));
+    // same as advance_game, but for a specific seat that may not be the one to act
+    pub fn advance_game_as(&mut self, seat: u8, action: GamePlayerAction) -> Result<Vec<GameEvent>, ActionError> {
+        if matches!(action, GamePlayerAction::Muck | GamePlayerAction::Show) {
+            return self.muck_or_show(seat, matches!(action, GamePlayerAction::Show));
+        }
+        if let GamePlayerAction::RunItTwice(agree) = action {
+            return self.vote_run_it_twice(seat, agree);
+        }
+        if seat != self.current_turn {
+            return Err(ActionError::NotYourTurn);
+        }
+        self.advance_game(action)
+    }
 
+    // the last player left takes everything without the board being dealt or any hands being compared
+    // no flop, no drop: a hand won before the flop isn't raked, later only the called chips are
+    fn award_uncontested(&mut self) -> GameEvent {
+        let total = self.total_pot();
+        let rake = if self.current_phase == 0 { 0 } else { self.config.rake.cut(total - self.uncalled_bet(), self.config.rake.cap) };
+        let winner = self.players.iter_mut().find(|p| !p.has_folded).unwrap();
+        winner.money += total - rake;
+        self.current_phase = 4;
+        GameEvent::WonUncontested(winner.id, total - rake)
+    }
+
+    // for players folding out of turn, like when they leave, the last one still in wins right away whoever's turn it is
+    pub fn check_hand_over(&mut self) -> Option<Vec<GameEvent>> {
+        if self.current_phase == 4 || self.players_in_hand() != 1 {
+            return None;
+        }
+        let events = vec![self.award_uncontested()];
+        for event in &events {
+            self.history.record(event);
+        }
         Some(events)
     }
 
-    fn evaluate_showdown(&mut self) -> ShowdownInfo {
-        let mut steps = Vec::<ShowdownStep>::new();
-        let info = self.get_showdown_info();
+    // only with the table's say-so, cards left to come and enough of the deck left over for a second board
+    fn can_run_it_twice(&self) -> bool {
+        self.config.run_it_twice && self.current_phase < 3 && self.spare_cards.len() >= 5 - self.revealed_cards()
+    }
+
+    // the rest of the board, then the second run's cards if there is one, then the showdown
+    fn run_out(&mut self) -> Vec<GameEvent> {
+        let mut events: Vec<GameEvent> = (self.current_phase..3).filter_map(|phase| self.reveal_event(phase)).collect();
+        if let Some(board) = self.second_board {
+            events.push(GameEvent::RevealSecondBoard(board[self.revealed_cards()..].to_vec()));
+        }
+        events.extend(self.start_showdown());
+        events
+    }
+
+    // the hand runs out as soon as anyone says no or the last vote is in
+    pub fn vote_run_it_twice(&mut self, seat: u8, agree: bool) -> Result<Vec<GameEvent>, ActionError> {
+        let Some(undecided) = self.run_it_twice_vote.as_mut() else { return Err(ActionError::NoRunItTwiceOffer) };
+        let Some(index) = undecided.iter().position(|&s| s == seat) else { return Err(ActionError::NotYourTurn) };
+        undecided.remove(index);
+
+        let mut events = vec![GameEvent::PlayerAction(seat, GamePlayerAction::RunItTwice(agree))];
+        if !agree || undecided.is_empty() {
+            self.run_it_twice_vote = None;
+            if agree {
+                let mut board = self.public_cards;
+                for card in board.iter_mut().skip(self.revealed_cards()) {
+                    *card = self.spare_cards.pop().unwrap();
+                }
+                self.second_board = Some(board);
+            }
+            events.extend(self.run_out());
+        }
+        for event in &events {
+            self.history.record(event);
+        }
+        Ok(events)
+    }
+
+    // for when the time to vote runs out, which counts as saying no
+    pub fn decline_run_it_twice_remaining(&mut self) -> Vec<GameEvent> {
+        let Some(&seat) = self.undecided_run_it_twice_players().first() else { return Vec::new() };
+        self.vote_run_it_twice(seat, false).unwrap_or_default()
+    }
+
+    pub fn undecided_run_it_twice_players(&self) -> Vec<u8> {
+        self.run_it_twice_vote.clone().unwrap_or_default()
+    }
+
+    pub fn second_board(&self) -> Option<[Card; 5]> {
+        self.second_board
+    }
+
+    // pays out the pots, then holds the showdown back until every beaten player decided to muck or show
+    // winners always show, and players who never got to the showdown have nothing to decide
+    fn start_showdown(&mut self) -> Vec<GameEvent> {
+        let info = self.evaluate_showdown();
+        self.current_phase = 4;
+        let winners: Vec<u8> = info.iter().flat_map(|run| &run.1).flat_map(|step| step.winners.iter().copied()).collect();
+        let undecided: Vec<u8> = self.players.iter().filter(|p| !p.has_folded && !winners.contains(&p.id)).map(|p| p.id).collect();
+        // folded hands never get shown, not even to spectators
+        let shown = self.players.iter().map(|p| !p.has_folded && !undecided.contains(&p.id)).collect();
+        self.pending_showdown = Some(PendingShowdown { info, undecided: undecided.clone(), shown });
+
+        if undecided.is_empty() {
+            self.finish_showdown()
+        } else {
+            vec![GameEvent::MuckOrShow(undecided)]
+        }
+    }
+
+    fn finish_showdown(&mut self) -> Vec<GameEvent> {
+        let pending = self.pending_showdown.take().unwrap();
+        pending.info.into_iter().map(|(hands, steps, reveal_order)| {
+            let hands = hands.into_iter().zip(&pending.shown).map(|(hand, &shown)| hand.filter(|_| shown)).collect();
+            GameEvent::Showdown((hands, steps, reveal_order))
+        }).collect()
+    }
+
+    // the showdown goes out with the last decision
+    pub fn muck_or_show(&mut self, seat: u8, show: bool) -> Result<Vec<GameEvent>, ActionError> {
+        let Some(pending) = self.pending_showdown.as_mut() else { return Err(ActionError::NotAtShowdown) };
+        let Some(index) = pending.undecided.iter().position(|&s| s == seat) else { return Err(ActionError::NotYourTurn) };
+        pending.undecided.remove(index);
+        pending.shown[seat as usize] |= show;
+
+        let mut events = vec![GameEvent::PlayerAction(seat, if show { GamePlayerAction::Show } else { GamePlayerAction::Muck })];
+        if pending.undecided.is_empty() {
+            events.extend(self.finish_showdown());
+        }
+        for event in &events {
+            self.history.record(event);
+        }
+        Ok(events)
+    }
+
+    // for when the time to decide runs out, everyone who hasn't decided yet mucks
+    pub fn muck_remaining(&mut self) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        for seat in self.undecided_showdown_players() {
+            events.extend(self.muck_or_show(seat, false).unwrap_or_default());
+        }
+        events
+    }
+
+    pub fn undecided_showdown_players(&self) -> Vec<u8> {
+        self.pending_showdown.as_ref().map(|pending| pending.undecided.clone()).unwrap_or_default()
+    }
+
+    // one for each run, a hand run twice pays out half of every pot on each board
+    fn evaluate_showdown(&mut self) -> Vec<ShowdownInfo> {
         let pots = self.compute_pots();
+        let uncalled = self.uncalled_bet();
+        let mut rake_left = self.config.rake.cap; // the cap is for the whole hand, not each pot or run
+        let Some(second_board) = self.second_board else {
+            return vec![self.evaluate_run(self.public_cards, pots, uncalled, &mut rake_left)];
+        };
+        // the odd chip of a pot goes to the first run
+        let first_halves = pots.iter().map(|pot| Pot { money: pot.money - pot.money / 2, eligible_players: pot.eligible_players.clone() }).collect();
+        let second_halves = pots.into_iter().map(|pot| Pot { money: pot.money / 2, ..pot }).collect();
+        vec![self.evaluate_run(self.public_cards, first_halves, uncalled - uncalled / 2, &mut rake_left), self.evaluate_run(second_board, second_halves, uncalled / 2, &mut rake_left)]
+    }
+
+    // the uncalled part of the last bet sits in the last pot, it goes back without being raked
+    fn evaluate_run(&mut self, board: [Card; 5], pots: Vec<Pot>, uncalled: u32, rake_left: &mut u32) -> ShowdownInfo {
+        let mut steps = Vec::<ShowdownStep>::new();
+        let info = self.get_showdown_info(board);
+        let rake = self.config.rake;
+        let last_pot = pots.len().saturating_sub(1);
+        let mut take_rake = |index: usize, money: u32| {
+            let called = if index == last_pot { money.saturating_sub(uncalled) } else { money };
+            let taken = rake.cut(called, *rake_left);
+            *rake_left -= taken;
+            taken
+        };
 
         let mut i = 0;
         while i < pots.len() {
             let pot = &pots[i];
             let pot_start_index = i;
 
-            let mut eligible_players: Vec<(u8, HandRank)> = info.iter().enumerate().filter(|(id, _)| pot.eligible_players.contains(&(*id as u8))).map(|(id, (_, _, hand_rank))| (id as u8, hand_rank.clone())).collect();
+            let mut eligible_players: Vec<(u8, HandRank)> = info.iter().enumerate().filter(|(id, _)| pot.eligible_players.contains(&(*id as u8))).filter_map(|(id, hand)| Some((id as u8, hand.as_ref()?.2.clone()))).collect();
             if eligible_players.is_empty() {
+                i += 1;
                 continue;
             }
-            eligible_players.sort_by(|(id1, hand_rank1), (id2, hand_rank2)| hand_rank2.cmp(&hand_rank1).then(id1.cmp(id2)));
+            eligible_players.sort_by(|(id1, hand_rank1), (id2, hand_rank2)| hand_rank2.cmp(hand_rank1).then(id1.cmp(id2)));
 
             let mut winners = Vec::new();
             let mut players_iter = eligible_players.iter();
@@ -138,12 +690,19 @@ impl Game {
                 }
             }
 
-            let mut winnings = pot.money;
+            let mut rake_taken = take_rake(i, pot.money);
+            let mut winnings = pot.money - rake_taken;
             while let Some(pot) = pots.get(i + 1) && winners.iter().all(|(id, _)| pot.eligible_players.contains(id)) {
-                winnings += pot.money;
+                let pot_rake = take_rake(i + 1, pot.money);
+                rake_taken += pot_rake;
+                winnings += pot.money - pot_rake;
                 i += 1;
             }
 
+            // the chips that don't split evenly go one each to the winners closest to the left of the button
+            let player_count = self.players.len() as u8;
+            let button = self.button;
+            winners.sort_by_key(|(id, _)| (id + player_count - button - 1) % player_count);
             let player_winnings = winnings / winners.len() as u32;
             let mut remainder = winnings % winners.len() as u32;
             for (winner, _) in winners.iter() {
@@ -161,6 +720,7 @@ impl Game {
             steps.push(ShowdownStep {
                 winners: winners.iter().map(|(id, _)| *id).collect(),
                 winnings,
+                rake_taken,
                 pot_start_index: pot_start_index.try_into().unwrap(),
                 pot_end_index: i.try_into().unwrap(),
                 eligible_players: eligible_players.iter().map(|(id, _)| *id).collect(),
@@ -169,22 +729,35 @@ impl Game {
 
             i += 1;
         }
-        
-        (info, steps)
+
+        // whoever bet last shows first, or the first player after the button if nobody bet on the river, then around the table
+        let player_count = self.players.len() as u8;
+        let reveal_order = (0..player_count).map(|offset| (self.last_bettor + offset) % player_count).filter(|&seat| !self.players[seat as usize].has_folded).collect();
+
+        (info, steps, reveal_order)
     }
 
     pub fn compute_pots(&self) -> Vec<Pot> {
         let mut contributions: Vec<(u8, Player)> = self.players.iter().enumerate().filter(|(_, p)| p.total_contribution > 0).map(|(id, p)| (id as u8, *p)).collect();
         contributions.sort_by_key(|(_, p)| p.total_contribution);
 
-        let mut pots = Vec::new();
+        let mut pots: Vec<Pot> = Vec::new();
+        let mut unclaimed = 0; // from levels where everyone folded before there was a pot below to take it
 
         while !contributions.is_empty() {
             let level = contributions[0].1.total_contribution;
             let portion = level * contributions.len() as u32;
+            let eligible_players: Vec<u8> = contributions.iter().filter(|(_, p)| p.in_hand()).map(|(id, _)| *id).collect();
 
-            if portion > 0 {
-                pots.push(Pot { money: portion, eligible_players: contributions.iter().filter(|(_, p)| !p.has_folded).map(|(id, _)| *id).collect() });
+            // nobody can win a level where everyone folded, so it goes to the pot below it
+            if eligible_players.is_empty() {
+                match pots.last_mut() {
+                    Some(pot) => pot.money += portion,
+                    None => unclaimed += portion,
+                }
+            } else if portion > 0 {
+                pots.push(Pot { money: portion + unclaimed, eligible_players });
+                unclaimed = 0;
             }
 
             for (_, player) in contributions.iter_mut() {
@@ -196,36 +769,374 @@ impl Game {
         pots
     }
 
-    fn get_showdown_info(&self) -> Vec<([Card; 2], [Card; 5], HandRank)> {
+    // none for everyone who folded, they aren't part of the showdown
+    fn get_showdown_info(&self, board: [Card; 5]) -> Vec<Option<ShowdownHand>> {
         let mut showdown_info = Vec::new();
         for p in self.players.iter() {
+            if p.has_folded {
+                showdown_info.push(None);
+                continue;
+            }
             let mut all_cards = Vec::new();
-            all_cards.extend_from_slice(&self.public_cards);
+            all_cards.extend_from_slice(&board);
             all_cards.extend_from_slice(&p.private_cards);
-            let (hand, hand_rank) = get_best_hand_rank(all_cards.as_slice().try_into().unwrap());
-            showdown_info.push((p.private_cards, hand, hand_rank));
+            let (hand, hand_rank) = get_best_hand_rank_for(all_cards.as_slice().try_into().unwrap(), self.config.deck);
+            showdown_info.push(Some((p.private_cards, hand, hand_rank)));
         }
         showdown_info
     }
 
+    // for AI rollouts, the copy shares nothing with the original and the same actions always give the same events
+    pub fn clone_for_simulation(&self) -> Game {
+        self.clone()
+    }
+
+    fn revealed_cards(&self) -> usize {
+        match self.current_phase {
+            0 => 0,
+            1 => 3,
+            2 => 4,
+            _ => 5,
+        }
+    }
+
+    pub fn player_view(&self, seat: u8) -> PlayerView {
+        let player = self.players[seat as usize];
+        PlayerView {
+            seat,
+            private_cards: player.private_cards,
+            public_cards: self.public_cards[..self.revealed_cards()].to_vec(),
+            pot: self.total_pot(),
+            current_bet: self.current_bet,
+            min_raise: self.min_raise,
+            contribution: player.round_contribution,
+            money: player.money,
+            opponents: self.players.iter().filter(|p| p.id != seat && !p.has_folded).count(),
+        }
+    }
+
+    // what the given seat may know, none for a spectator: everyone else's private cards and the board to come are left out
+    pub fn snapshot(&self, for_player: Option<u8>) -> GameSnapshot {
+        let mut snapshot = self.full_snapshot();
+        for player in snapshot.players.iter_mut().filter(|p| Some(p.id) != for_player) {
+            player.private_cards = None;
+        }
+        for card in snapshot.public_cards.iter_mut().skip(self.revealed_cards()) {
+            *card = None;
+        }
+        snapshot
+    }
+
+    // everything, only meant for the server itself
+    pub fn full_snapshot(&self) -> GameSnapshot {
+        GameSnapshot {
+            players: self.players.iter().map(|p| PlayerSnapshot { id: p.id, money: p.money, total_contribution: p.total_contribution, round_contribution: p.round_contribution, street_contributions: p.street_contributions, private_cards: Some(p.private_cards), has_folded: p.has_folded }).collect(),
+            pots: self.compute_pots(),
+            current_bet: self.current_bet,
+            current_phase: self.current_phase,
+            current_turn: self.current_turn,
+            last_bettor: self.last_bettor,
+            min_raise: self.min_raise,
+            acted_since_full_raise: self.acted_since_full_raise.clone(),
+            config: self.config,
+            button: self.button,
+            seed: self.seed,
+            public_cards: self.public_cards.map(Some),
+        }
+    }
+
+    // none if the snapshot had cards left out, the history, any muck decisions still open and used time banks don't come back
+    // and since the rest of the deck isn't in it either, the hand can't be run twice
+    pub fn from_snapshot(snapshot: &GameSnapshot) -> Option<Game> {
+        let mut players = Vec::new();
+        for p in &snapshot.players {
+            players.push(Player { id: p.id, money: p.money, total_contribution: p.total_contribution, round_contribution: p.round_contribution, street_contributions: p.street_contributions, private_cards: p.private_cards?, has_folded: p.has_folded });
+        }
+        let mut public_cards = [Card { rank: 0, suit: 0 }; 5];
+        for (card, snapshot_card) in public_cards.iter_mut().zip(snapshot.public_cards) {
+            *card = snapshot_card?;
+        }
+
+        let mut game = Game { players, current_bet: snapshot.current_bet, current_phase: snapshot.current_phase, current_turn: snapshot.current_turn, last_bettor: snapshot.last_bettor, min_raise: snapshot.min_raise, acted_since_full_raise: snapshot.acted_since_full_raise.clone(), config: snapshot.config, button: snapshot.button, seed: snapshot.seed, public_cards, history: HandHistory::default(), pending_showdown: None, turn_deadline: None, time_banks: vec![snapshot.config.time_bank; snapshot.players.len()], time_bank_started: None, spare_cards: Vec::new(), run_it_twice_vote: None, second_board: None };
+        game.reset_turn_deadline();
+        Some(game)
+    }
+
     pub fn player(&self, id: u8) -> Player {
         self.players[id as usize]
     }
 
+    // the same as adding up compute_pots, without splitting anything into side pots
+    pub fn total_pot(&self) -> u32 {
+        self.players.iter().map(|p| p.total_contribution).sum()
+    }
+
+    // how much of the biggest contribution nobody matched
+    fn uncalled_bet(&self) -> u32 {
+        let mut contributions: Vec<u32> = self.players.iter().map(|p| p.total_contribution).collect();
+        contributions.sort_unstable_by(|a, b| b.cmp(a));
+        contributions[0] - contributions.get(1).copied().unwrap_or(0)
+    }
+
+    // what goes out about the players outside the showdown
+    pub fn public_view(&self) -> Vec<RedactedPlayer> {
+        self.players.iter().map(Player::redacted).collect()
+    }
+
     pub fn player_mut(&mut self, id: u8) -> &mut Player {
         self.players.get_mut(id as usize).unwrap()
     }
 }
 
-pub fn make_game(lobby_players: Vec<u32> /* array of money amounts */) -> Option<Game> { // none means cant create game
-    if lobby_players.len() < 3 {
-        return None
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlayerSnapshot {
+    pub id: u8,
+    pub money: u32,
+    pub total_contribution: u32,
+    pub round_contribution: u32,
+    #[cfg_attr(feature = "serde", serde(default))] // all zeros in snapshots saved before it was tracked
+    pub street_contributions: [u32; 4],
+    pub private_cards: Option<[Card; 2]>,
+    pub has_folded: bool,
+}
+
+// the whole hand at one point in time, with some cards possibly left out depending on who it's for
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameSnapshot {
+    pub players: Vec<PlayerSnapshot>,
+    pub pots: Vec<Pot>,
+    pub current_bet: u32,
+    pub current_phase: u8,
+    pub current_turn: u8,
+    pub last_bettor: u8,
+    pub min_raise: u32,
+    #[cfg_attr(feature = "serde", serde(default))] // empty in snapshots saved before it was tracked
+    pub acted_since_full_raise: Vec<u8>,
+    pub config: GameConfig,
+    pub button: u8,
+    pub seed: Option<u64>,
+    pub public_cards: [Option<Card>; 5],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameSetupError {
+    TooFewPlayers,
+    TooManyPlayers,
+    PlayerTooPoor(u8), // seat of a player below the minimum stack, anyone else can at least go all-in
+    InvalidDeal, // seats missing or given twice, or the same card dealt twice
+}
+impl Display for GameSetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameSetupError::TooFewPlayers => write!(f, "there aren't enough players to start a game"),
+            GameSetupError::TooManyPlayers => write!(f, "at most {} players fit at a table", MAX_PLAYERS),
+            GameSetupError::PlayerTooPoor(seat) => write!(f, "the player in seat {} doesn't have enough money left to be dealt in", seat),
+            GameSetupError::InvalidDeal => write!(f, "the cards given aren't a deal that could happen"),
+        }
+    }
+}
+impl std::error::Error for GameSetupError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BlindConfig {
+    pub small_blind: u32,
+    pub big_blind: u32,
+    pub ante: u32, // paid by everyone before the blinds, doesn't count towards calling
+}
+impl Default for BlindConfig {
+    fn default() -> Self {
+        BlindConfig { small_blind: 5, big_blind: 10, ante: 0 }
+    }
+}
+
+// 22 players take 44 private cards, which leaves just enough for the board
+pub const MAX_PLAYERS: usize = 22;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RakeConfig {
+    pub percent: f32, // of every pot, 5.0 is 5%
+    pub cap: u32, // most the house takes from one hand, 0 means no rake at all
+}
+impl RakeConfig {
+    // the house's cut of these chips, never more than what's left of the cap for the hand
+    fn cut(&self, money: u32, cap_left: u32) -> u32 {
+        ((money as f64 * self.percent as f64 / 100.0) as u32).min(cap_left)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct GameConfig {
+    pub blinds: BlindConfig,
+    pub min_players: usize, // never less than 2
+    pub rake: RakeConfig,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub turn_time: Option<Duration>, // how long each player gets to act, none waits forever
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub betting: BettingMode,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deck: DeckKind,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub buy_in: BuyInConfig,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_bank: Duration, // every player's extra time once the turn time runs out, for the whole game rather than each turn
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub run_it_twice: bool, // whether all-in players get offered a second board
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub straddle: Option<u32>, // a blind raise from the player after the big blind, only with three players or more
+}
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig { blinds: BlindConfig::default(), min_players: 2, rake: RakeConfig::default(), turn_time: None, betting: BettingMode::default(), deck: DeckKind::default(), buy_in: BuyInConfig::default(), time_bank: Duration::ZERO, run_it_twice: false, straddle: None }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BuyInConfig {
+    pub min: u32, // a stack someone buys or rebuys into has to be between these
+    pub max: u32,
+    pub min_stack: u32, // players below this aren't dealt in, anyone between it and the big blind just goes all-in
+}
+impl Default for BuyInConfig {
+    fn default() -> Self {
+        BuyInConfig { min: 1, max: u32::MAX, min_stack: 1 }
+    }
+}
+impl BuyInConfig {
+    pub fn allows(&self, stack: u32) -> bool {
+        (self.min..=self.max).contains(&stack)
+    }
+
+    // not u32::clamp, that panics if a bad config has min over max
+    pub fn clamp(&self, stack: u32) -> u32 {
+        stack.max(self.min).min(self.max)
+    }
+
+    // never below a single chip, nobody can play with nothing
+    pub fn can_be_dealt(&self, stack: u32) -> bool {
+        stack >= self.min_stack.max(1)
+    }
+}
+
+// fixed-limit streets are capped at this many bets, the big blind counts as the first one preflop
+pub const MAX_LIMIT_BETS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BettingMode {
+    #[default]
+    NoLimit,
+    PotLimit, // a raise can be at most what's in the pot once the raiser has called
+    FixedLimit { small: u32, big: u32 }, // every bet and raise is exactly small preflop and on the flop, big on the turn and river
+}
+impl BettingMode {
+    // raise is how far over the current bet it goes, pot_after_call counts the raiser's call, all-ins for less than a fixed bet are fine
+    fn check_raise(&self, phase: u8, current_bet: u32, raise: u32, pot_after_call: u32, all_in: bool) -> Result<(), ActionError> {
+        match *self {
+            BettingMode::NoLimit => Ok(()),
+            BettingMode::PotLimit if raise > pot_after_call => Err(ActionError::RaiseAboveMaximum),
+            BettingMode::PotLimit => Ok(()),
+            BettingMode::FixedLimit { small, big } => {
+                let size = if phase < 2 { small } else { big };
+                if current_bet + size > size * MAX_LIMIT_BETS {
+                    Err(ActionError::RaiseCapReached)
+                } else if raise > size {
+                    Err(ActionError::RaiseAboveMaximum)
+                } else if raise < size && !all_in {
+                    Err(ActionError::RaiseBelowMinimum)
+                } else {
+                    Ok(())
+                }
+            },
+        }
     }
-    if !lobby_players.iter().all(|&p| p > 10) {
-        return None
+
+    // smallest and largest raise over the current bet before the player's stack is taken into account, none once a fixed-limit round is capped
+    fn raise_limits(&self, phase: u8, current_bet: u32, min_raise: u32, pot_after_call: u32) -> Option<(u32, u32)> {
+        match *self {
+            BettingMode::NoLimit => Some((min_raise, u32::MAX)),
+            BettingMode::PotLimit => Some((min_raise, pot_after_call)),
+            BettingMode::FixedLimit { small, big } => {
+                let size = if phase < 2 { small } else { big };
+                (current_bet + size <= size * MAX_LIMIT_BETS).then_some((size, size))
+            },
+        }
     }
+}
 
-    let mut deck = get_shuffled_deck();
+// tournament blinds, going up a level every few hands and staying at the last level once it's reached
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BlindSchedule {
+    pub levels: Vec<(u32, u32, u32)>, // small blind, big blind, ante
+    pub level_every_hands: u32,
+}
+impl BlindSchedule {
+    // hands count from 0, none if there are no levels
+    pub fn level(&self, hand_number: u32) -> Option<BlindConfig> {
+        let index = (hand_number / self.level_every_hands.max(1)) as usize;
+        let &(small_blind, big_blind, ante) = self.levels.get(index).or(self.levels.last())?;
+        Some(BlindConfig { small_blind, big_blind, ante })
+    }
+}
+
+static HANDS_DEALT: AtomicU64 = AtomicU64::new(0); // by make_game in this process
+static LAST_BOTTOM_CARD: AtomicU8 = AtomicU8::new(0); // of the deck make_game dealt last
+
+// every hand gets a fresh seed from thread_rng, which reseeds itself from the OS
+// mixed into it are how many hands came before and the bottom card of the last deck, so no two hands of a process get the same seed
+// game.seed is the mixed seed, make_game_seeded deals the same hand from it
+pub fn make_game(lobby_players: Vec<u32> /* array of money amounts */, config: GameConfig, button: u8) -> Result<Game, GameSetupError> {
+    let seed = mix_seed(thread_rng().next_u64(), HANDS_DEALT.fetch_add(1, atomic::Ordering::Relaxed), LAST_BOTTOM_CARD.load(atomic::Ordering::Relaxed));
+    let deck = get_shuffled_deck(config.deck, seed);
+    let bottom_card = deck[0].to_byte();
+    let mut game = make_game_with_deck(lobby_players, config, button, deck)?;
+    game.seed = Some(seed);
+    LAST_BOTTOM_CARD.store(bottom_card, atomic::Ordering::Relaxed);
+    Ok(game)
+}
+
+// splitmix64's finalizer, it never maps two inputs to the same output, so different hand counts always give different seeds
+fn mix_seed(seed: u64, hand: u64, bottom_card: u8) -> u64 {
+    let mut z = seed ^ hand.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ ((bottom_card as u64) << 56);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// the same seed always deals the same cards, so hands can be reproduced
+pub fn make_game_seeded(lobby_players: Vec<u32>, config: GameConfig, button: u8, seed: u64) -> Result<Game, GameSetupError> {
+    let mut game = make_game_with_deck(lobby_players, config, button, get_shuffled_deck(config.deck, seed))?;
+    game.seed = Some(seed);
+    Ok(game)
+}
+
+// small and big blind seats, heads-up the button posts the small blind
+pub fn blind_seats(player_count: u8, button: u8) -> (u8, u8) {
+    if player_count == 2 {
+        (button % 2, (button + 1) % 2)
+    } else {
+        ((button + 1) % player_count, (button + 2) % player_count)
+    }
+}
+
+// cards are dealt by popping from the end of the deck, first both private cards of every player, then the public cards
+pub(crate) fn make_game_with_deck(lobby_players: Vec<u32>, config: GameConfig, button: u8, mut deck: Vec<Card>) -> Result<Game, GameSetupError> {
+    if lobby_players.len() < config.min_players.max(2) {
+        return Err(GameSetupError::TooFewPlayers)
+    }
+    if lobby_players.len() > MAX_PLAYERS || lobby_players.len() * 2 + 5 > deck.len() { // a short deck seats fewer
+        return Err(GameSetupError::TooManyPlayers)
+    }
+    let blinds = config.blinds;
+    if let Some(seat) = lobby_players.iter().position(|&p| !config.buy_in.can_be_dealt(p)) {
+        return Err(GameSetupError::PlayerTooPoor(seat as u8))
+    }
 
     let mut players = Vec::new();
     for (id, &money) in lobby_players.iter().enumerate() {
@@ -233,26 +1144,953 @@ pub fn make_game(lobby_players: Vec<u32> /* array of money amounts */) -> Option
             id: id as u8,
             money,
             total_contribution: 0,
+            round_contribution: 0,
+            street_contributions: [0; 4],
             private_cards: [deck.pop().unwrap(), deck.pop().unwrap()],
             has_folded: false,
         });
     }
 
     let public_cards = [deck.pop().unwrap(), deck.pop().unwrap(), deck.pop().unwrap(), deck.pop().unwrap(), deck.pop().unwrap()];
+    debug_assert!(all_distinct(&players.iter().flat_map(|p| p.private_cards).chain(public_cards).collect::<Vec<_>>()), "the same card was dealt twice");
+
+    let mut history = HandHistory::default();
+
+    // players who can't cover the ante go all-in for what they have
+    for player in players.iter_mut() {
+        let ante = blinds.ante.min(player.money);
+        player.money -= ante;
+        player.total_contribution = ante;
+        player.street_contributions[0] = ante;
+        if ante > 0 {
+            history.push(HistoryEvent::AntePosted(player.id, ante));
+        }
+    }
+
+    // the blinds go all-in if they can't cover them, and so does the straddle
+    let button = button % players.len() as u8;
+    let (small_blind_seat, big_blind_seat) = blind_seats(players.len() as u8, button);
+    let mut forced_bets = vec![(small_blind_seat, blinds.small_blind), (big_blind_seat, blinds.big_blind)];
+    // heads-up the player after the big blind is the small blind, who already posted
+    if let Some(straddle) = config.straddle && players.len() > 2 {
+        forced_bets.push(((big_blind_seat + 1) % players.len() as u8, straddle));
+    }
+    let last_blind_seat = forced_bets.last().unwrap().0;
+    for (seat, blind) in forced_bets {
+        let player = &mut players[seat as usize];
+        let blind = blind.min(player.money);
+        player.money -= blind;
+        player.round_contribution = blind;
+        player.total_contribution += blind;
+        player.street_contributions[0] += blind;
+        history.push(HistoryEvent::BlindPosted(seat, blind));
+    }
 
-    let current_turn = 1;
-    Some(Game { players, current_bet: 0, current_phase: 0, current_turn, last_bettor: 0, public_cards })
+    let current_bet = players.iter().map(|p| p.round_contribution).max().unwrap_or(0);
+    let time_banks = vec![config.time_bank; players.len()];
+    let mut game = Game { players, current_bet, current_phase: 0, current_turn: 0, last_bettor: 0, min_raise: current_bet.max(blinds.big_blind), acted_since_full_raise: Vec::new(), config, button, seed: None, public_cards, history, pending_showdown: None, turn_deadline: None, time_banks, time_bank_started: None, spare_cards: deck, run_it_twice_vote: None, second_board: None };
+    // the big blind (or the straddle) still gets to act if nobody raises, so the round closes once the action gets back to the player after them
+    game.current_turn = game.next_actor_after(last_blind_seat);
+    game.last_bettor = game.current_turn;
+    game.reset_turn_deadline();
+    Ok(game)
 }
 
-pub fn get_shuffled_deck() -> Vec<Card> {
+pub fn get_deck() -> Vec<Card> {
     let mut deck = Vec::<Card>::new();
     for suit in 0..4 {
         for rank in 0..13 {
             deck.push(Card { rank, suit });
         }
     }
+    deck
+}
 
-    deck.shuffle(&mut thread_rng());
-
+// only as unpredictable as the seed, StdRng isn't promised to stay a cryptographic generator
+pub fn get_shuffled_deck(kind: DeckKind, seed: u64) -> Vec<Card> {
+    let mut deck: Vec<Card> = get_deck().into_iter().filter(|c| c.rank >= kind.lowest_rank()).collect();
+    deck.shuffle(&mut StdRng::seed_from_u64(seed));
     deck
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards::HandCategory;
+
+    // a fixed deal so every test plays the same hand
+    fn game(stacks: Vec<u32>) -> Game {
+        make_game_seeded(stacks, GameConfig::default(), 0, 7).unwrap()
+    }
+
+    fn state(game: &Game) -> String {
+        format!("{:?}", game.full_snapshot())
+    }
+
+    // the action has to fail with exactly this error and leave nothing behind
+    fn assert_rejected(game: &mut Game, action: GamePlayerAction, error: ActionError) {
+        let before = state(game);
+        assert_eq!(game.advance_game_as(game.current_turn, action).err(), Some(error));
+        assert_eq!(state(game), before);
+    }
+
+    fn cards<const N: usize>(notation: &str) -> [Card; N] {
+        let cards: Vec<Card> = notation.split_whitespace().map(|card| card.parse().unwrap()).collect();
+        cards.try_into().unwrap()
+    }
+
+    #[test]
+    fn rollouts_leave_the_original_untouched() {
+        let game = game(vec![1000, 1000, 1000]);
+        let before = state(&game);
+        let mut results = Vec::new();
+        for _ in 0..3 {
+            let mut rollout = game.clone_for_simulation();
+            let mut events = Vec::new();
+            while let Ok(new_events) = rollout.advance_game(GamePlayerAction::Call) {
+                events.extend(new_events);
+            }
+            results.push(format!("{:?}", events));
+        }
+        assert_eq!(state(&game), before);
+        // the same actions from the same state play out the same way
+        assert!(results.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn one_player_is_too_few() {
+        assert_eq!(make_game(vec![1000], GameConfig::default(), 0).err(), Some(GameSetupError::TooFewPlayers));
+    }
+
+    #[test]
+    fn a_full_table_has_no_room_for_one_more() {
+        assert_eq!(make_game(vec![1000; MAX_PLAYERS + 1], GameConfig::default(), 0).err(), Some(GameSetupError::TooManyPlayers));
+    }
+
+    #[test]
+    fn stacks_below_the_minimum_arent_dealt_in() {
+        let config = GameConfig { buy_in: BuyInConfig { min_stack: 100, ..Default::default() }, ..Default::default() };
+        assert_eq!(make_game(vec![1000, 99, 1000], config, 0).err(), Some(GameSetupError::PlayerTooPoor(1)));
+    }
+
+    #[test]
+    fn a_stack_below_the_big_blind_is_dealt_in_all_in() {
+        let config = GameConfig { buy_in: BuyInConfig { min_stack: 5, ..Default::default() }, ..Default::default() };
+        let game = make_game_seeded(vec![1000, 1000, 6], config, 0, 7).unwrap();
+        let events = game.start_events();
+        // the big blind only has 6 of the 10
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAction(2, GamePlayerAction::AddMoney(6)))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAllIn(2))));
+        assert_eq!(game.players[2].money, 0);
+        assert!(!game.players[2].can_act());
+        assert_eq!(game.current_turn, 0);
+    }
+
+    #[test]
+    fn deals_that_cant_happen_are_rejected() {
+        let board = cards("2c 3c 4c 5c 6c");
+        // the same card twice, then a seat missing
+        assert_eq!(Game::new_with_cards(vec![(0, 1000, cards("As Ks")), (1, 1000, cards("As Qs"))], board, BlindConfig::default()).err(), Some(GameSetupError::InvalidDeal));
+        assert_eq!(Game::new_with_cards(vec![(0, 1000, cards("As Ks")), (2, 1000, cards("Ah Qs"))], board, BlindConfig::default()).err(), Some(GameSetupError::InvalidDeal));
+        assert!(Game::new_with_cards(vec![(0, 1000, cards("As Ks")), (1, 1000, cards("Ah Qs"))], board, BlindConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn checking_facing_the_big_blind() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::Check, ActionError::CannotCheckFacingBet);
+    }
+
+    #[test]
+    fn raising_by_less_than_the_big_blind() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::AddMoney(15), ActionError::RaiseBelowMinimum);
+    }
+
+    #[test]
+    fn an_all_in_for_less_than_a_raise_doesnt_reopen_the_betting() {
+        let mut game = game(vec![1000, 60, 1000]);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        // the small blind's last 55 only raise by 20
+        assert_eq!(game.current_turn, 1);
+        game.advance_game(GamePlayerAction::AddMoney(55)).unwrap();
+        assert_eq!((game.current_bet, game.min_raise), (60, 30));
+        // the big blind hasn't acted yet and may still raise, the opener can only call the difference
+        assert!(game.legal_actions().min_raise.is_some());
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        assert_eq!(game.current_turn, 0);
+        assert_eq!(game.legal_actions().min_raise, None);
+        assert_rejected(&mut game, GamePlayerAction::Raise(30), ActionError::BettingNotReopened);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        assert_eq!(game.current_phase, 1);
+    }
+
+    #[test]
+    fn betting_more_than_the_stack() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::AddMoney(1001), ActionError::InsufficientFunds);
+    }
+
+    #[test]
+    fn acting_out_of_turn() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        let before = state(&game);
+        assert_eq!(game.advance_game_as((game.current_turn + 1) % 3, GamePlayerAction::Call).err(), Some(ActionError::NotYourTurn));
+        assert_eq!(state(&game), before);
+    }
+
+    #[test]
+    fn acting_after_the_hand() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        assert_rejected(&mut game, GamePlayerAction::Call, ActionError::HandOver);
+    }
+
+    #[test]
+    fn mucking_during_the_betting() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::Muck, ActionError::NotAtShowdown);
+    }
+
+    #[test]
+    fn raising_over_the_pot_in_pot_limit() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { betting: BettingMode::PotLimit, ..Default::default() }, 0, 7).unwrap();
+        // 15 in the blinds and 10 to call makes a pot of 25
+        assert_rejected(&mut game, GamePlayerAction::Raise(26), ActionError::RaiseAboveMaximum);
+        assert!(game.advance_game(GamePlayerAction::Raise(25)).is_ok());
+    }
+
+    #[test]
+    fn raising_past_the_fixed_limit_cap() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { betting: BettingMode::FixedLimit { small: 10, big: 20 }, ..Default::default() }, 0, 7).unwrap();
+        for _ in 1..MAX_LIMIT_BETS {
+            game.advance_game(GamePlayerAction::Raise(10)).unwrap();
+        }
+        assert_rejected(&mut game, GamePlayerAction::Raise(10), ActionError::RaiseCapReached);
+    }
+
+    #[test]
+    fn pot_limit_raises_go_up_to_the_pot() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { betting: BettingMode::PotLimit, ..Default::default() }, 0, 7).unwrap();
+        let legal = game.legal_actions();
+        assert_eq!((legal.min_raise, legal.max_raise), (Some(10), 25));
+        game.advance_game(GamePlayerAction::Raise(25)).unwrap();
+        // 50 in the middle and the small blind's 30 to call
+        let legal = game.legal_actions();
+        assert_eq!((legal.can_call, legal.min_raise, legal.max_raise), (Some(30), Some(25), 80));
+        assert_rejected(&mut game, GamePlayerAction::Raise(81), ActionError::RaiseAboveMaximum);
+        game.advance_game(GamePlayerAction::Raise(80)).unwrap();
+        assert_eq!(game.current_bet, 115);
+    }
+
+    #[test]
+    fn fixed_limit_bets_are_the_size_of_the_street() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { betting: BettingMode::FixedLimit { small: 10, big: 20 }, ..Default::default() }, 0, 7).unwrap();
+        let legal = game.legal_actions();
+        assert_eq!((legal.min_raise, legal.max_raise), (Some(10), 10));
+        assert_rejected(&mut game, GamePlayerAction::Raise(15), ActionError::RaiseAboveMaximum);
+        assert_rejected(&mut game, GamePlayerAction::Raise(5), ActionError::RaiseBelowMinimum);
+        while game.current_phase < 2 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        // the turn and the river go by the big size
+        let legal = game.legal_actions();
+        assert_eq!((legal.min_raise, legal.max_raise), (Some(20), 20));
+        assert_rejected(&mut game, GamePlayerAction::Bet(10), ActionError::RaiseBelowMinimum);
+        for _ in 0..MAX_LIMIT_BETS {
+            game.advance_game(GamePlayerAction::AddMoney(game.current_bet - game.players[game.current_turn as usize].round_contribution + 20)).unwrap();
+        }
+        assert_eq!(game.current_bet, 20 * MAX_LIMIT_BETS);
+        // capped, calling is all that's left
+        assert_eq!(game.legal_actions().min_raise, None);
+    }
+
+    #[test]
+    fn voting_without_an_offer() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::RunItTwice(true), ActionError::NoRunItTwiceOffer);
+    }
+
+    #[test]
+    fn betting_into_a_bet() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        assert_rejected(&mut game, GamePlayerAction::Bet(20), ActionError::AlreadyABet);
+    }
+
+    #[test]
+    fn raising_without_a_bet() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        while game.current_phase == 0 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        assert_rejected(&mut game, GamePlayerAction::Raise(20), ActionError::NothingToRaise);
+    }
+
+    #[test]
+    fn bet_and_raise_add_the_same_as_add_money() {
+        let (mut named, mut added) = (game(vec![1000, 1000, 1000]), game(vec![1000, 1000, 1000]));
+        // a raise counts from the current bet, whatever the raiser already has in
+        named.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        added.advance_game(GamePlayerAction::AddMoney(40)).unwrap();
+        named.advance_game(GamePlayerAction::Raise(40)).unwrap();
+        added.advance_game(GamePlayerAction::AddMoney(75)).unwrap();
+        assert_eq!(named.current_bet, 80);
+        for game in [&mut named, &mut added] {
+            while game.current_phase == 0 {
+                game.advance_game(GamePlayerAction::Call).unwrap();
+            }
+        }
+        // a bet is the whole amount on a street nobody has bet on yet
+        named.advance_game(GamePlayerAction::Bet(50)).unwrap();
+        added.advance_game(GamePlayerAction::AddMoney(50)).unwrap();
+        assert_eq!(named.current_bet, 50);
+        assert_eq!(state(&named), state(&added));
+    }
+
+    #[test]
+    fn the_flop_starts_without_a_bet() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        let mut events = Vec::new();
+        while game.current_phase == 0 {
+            events.extend(game.advance_game(GamePlayerAction::Call).unwrap());
+        }
+        assert_eq!(game.current_bet, 0);
+        assert!(events.iter().any(|event| matches!(event, GameEvent::UpdateCurrentBet(0))));
+        assert!(game.legal_actions().can_check);
+    }
+
+    #[test]
+    fn table_size_boundaries() {
+        let deal = |players: usize, config: GameConfig| make_game(vec![1000; players], config, 0).map(|game| game.players.len());
+        assert_eq!(deal(1, GameConfig::default()), Err(GameSetupError::TooFewPlayers));
+        assert_eq!(deal(2, GameConfig::default()), Ok(2));
+        assert_eq!(deal(22, GameConfig::default()), Ok(22));
+        assert_eq!(deal(23, GameConfig::default()), Err(GameSetupError::TooManyPlayers));
+        // heads-up is only allowed when the table's minimum says so
+        assert_eq!(deal(2, GameConfig { min_players: 3, ..Default::default() }), Err(GameSetupError::TooFewPlayers));
+    }
+
+    fn deal(game: &Game) -> String {
+        let snapshot = game.full_snapshot();
+        format!("{:?} {:?}", snapshot.players.iter().map(|p| p.private_cards).collect::<Vec<_>>(), snapshot.public_cards)
+    }
+
+    #[test]
+    fn back_to_back_hands_get_different_decks() {
+        let first = make_game(vec![1000; 3], GameConfig::default(), 0).unwrap();
+        let second = make_game(vec![1000; 3], GameConfig::default(), 0).unwrap();
+        assert_ne!(first.seed, second.seed);
+        assert_ne!(deal(&first), deal(&second));
+        // the mixed seed is the one that deals the hand again
+        let replayed = make_game_seeded(vec![1000; 3], GameConfig::default(), 0, second.seed.unwrap()).unwrap();
+        assert_eq!(deal(&replayed), deal(&second));
+    }
+
+    #[test]
+    fn the_same_seed_mixes_differently_every_hand() {
+        let seeds: std::collections::HashSet<u64> = (0..1000).map(|hand| mix_seed(42, hand, 7)).collect();
+        assert_eq!(seeds.len(), 1000);
+    }
+
+    #[test]
+    fn action_error_codes_stay_put() {
+        let errors = [ActionError::CannotCheckFacingBet, ActionError::RaiseBelowMinimum, ActionError::InsufficientFunds, ActionError::NotYourTurn, ActionError::HandOver, ActionError::NotAtShowdown, ActionError::RaiseAboveMaximum, ActionError::RaiseCapReached, ActionError::NoRunItTwiceOffer, ActionError::AlreadyABet, ActionError::NothingToRaise, ActionError::BettingNotReopened];
+        assert_eq!(errors.map(|e| e.code()), [100, 101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111]);
+    }
+
+    #[test]
+    fn street_contributions_add_up_to_the_total() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { blinds: BlindConfig { ante: 2, ..Default::default() }, ..Default::default() }, 0, 7).unwrap();
+        game.advance_game(GamePlayerAction::Raise(20)).unwrap();
+        let mut bet_on = [false; 4];
+        while game.current_phase < 4 {
+            let phase = game.current_phase as usize;
+            // one bet on every street, called around
+            let action = if !bet_on[phase] && game.current_bet == 0 {
+                bet_on[phase] = true;
+                GamePlayerAction::Bet(30)
+            } else {
+                GamePlayerAction::Call
+            };
+            game.advance_game(action).unwrap();
+        }
+        for player in &game.players {
+            assert_eq!(player.street_contributions.iter().sum::<u32>(), player.total_contribution);
+            assert!(player.street_contributions.iter().all(|&street| street > 0), "{:?}", player.street_contributions);
+        }
+    }
+
+    fn stacks(game: &Game) -> Vec<u32> {
+        game.players.iter().map(|p| p.money).collect()
+    }
+
+    #[test]
+    fn configured_blinds_are_posted() {
+        let blinds = BlindConfig { small_blind: 25, big_blind: 50, ante: 0 };
+        let game = make_game_seeded(vec![1000, 1000, 1000], GameConfig { blinds, ..Default::default() }, 0, 7).unwrap();
+        assert_eq!(stacks(&game), [1000, 975, 950]);
+        assert_eq!((game.current_bet, game.min_raise, game.current_turn), (50, 50, 0));
+        // heads-up the button posts the small blind and acts first
+        let game = make_game_seeded(vec![1000, 1000], GameConfig { blinds, ..Default::default() }, 0, 7).unwrap();
+        assert_eq!(stacks(&game), [975, 950]);
+        assert_eq!((game.current_bet, game.current_turn), (50, 0));
+    }
+
+    fn pots(game: &Game) -> Vec<(u32, Vec<u8>)> {
+        game.compute_pots().into_iter().map(|pot| (pot.money, pot.eligible_players)).collect()
+    }
+
+    #[test]
+    fn a_short_ante_goes_all_in() {
+        let blinds = BlindConfig { ante: 5, ..Default::default() };
+        let game = make_game_seeded(vec![3, 500, 1000], GameConfig { blinds, ..Default::default() }, 0, 7).unwrap();
+        assert_eq!(stacks(&game), [0, 490, 985]);
+        assert_eq!(game.players.iter().map(|p| p.total_contribution).collect::<Vec<_>>(), [3, 10, 15]);
+        // the button is all-in for 3 and can only win that much from each player
+        assert_eq!(pots(&game), [(9, vec![0, 1, 2]), (14, vec![1, 2]), (5, vec![2])]);
+        assert_eq!((game.current_bet, game.current_turn), (10, 1));
+    }
+
+    #[test]
+    fn the_button_and_blinds_move_every_hand() {
+        let schedule = BlindSchedule { levels: Vec::new(), level_every_hands: 1 };
+        let mut game = game(vec![1000; 4]);
+        for hand in 1..=8 {
+            game = game.next_hand(&schedule, hand).unwrap();
+            let button = (hand % 4) as u8;
+            assert_eq!(game.button, button);
+            let (small_blind, big_blind) = blind_seats(4, button);
+            assert_eq!((small_blind, big_blind), ((button + 1) % 4, (button + 2) % 4));
+            assert_eq!(game.players[small_blind as usize].round_contribution, 5);
+            assert_eq!(game.players[big_blind as usize].round_contribution, 10);
+            assert_eq!(game.current_turn, (button + 3) % 4);
+        }
+        // heads-up the button is the small blind and the other seat the big blind
+        assert_eq!(blind_seats(2, 0), (0, 1));
+        assert_eq!(blind_seats(2, 1), (1, 0));
+    }
+
+    #[test]
+    fn the_same_seed_deals_the_same_cards() {
+        let first = make_game_seeded(vec![1000; 6], GameConfig::default(), 2, 1234).unwrap();
+        let second = make_game_seeded(vec![1000; 6], GameConfig::default(), 2, 1234).unwrap();
+        for (a, b) in first.players.iter().zip(&second.players) {
+            assert!(a.private_cards.iter().zip(&b.private_cards).all(|(a, b)| a.full_eq(b)));
+        }
+        assert!(first.public_cards.iter().zip(&second.public_cards).all(|(a, b)| a.full_eq(b)));
+        let other = make_game_seeded(vec![1000; 6], GameConfig::default(), 2, 1235).unwrap();
+        assert_ne!(deal(&first), deal(&other));
+    }
+
+    #[test]
+    fn a_three_way_all_in_runs_out_the_board_and_pays_the_side_pots() {
+        let board = cards("2c 7d 9h Js 3s");
+        let mut game = Game::new_with_cards(vec![(0, 100, cards("As Ah")), (1, 200, cards("Ks Kh")), (2, 300, cards("Qs Qh"))], board, BlindConfig::default()).unwrap();
+        game.advance_game(GamePlayerAction::AddMoney(100)).unwrap();
+        game.advance_game(GamePlayerAction::AddMoney(195)).unwrap();
+        // the big blind still has chips behind, but nobody is left to bet against
+        let events = game.advance_game(GamePlayerAction::Call).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::RevealFlop(_))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::RevealTurn(_))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::RevealRiver(_))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::Showdown(_) | GameEvent::MuckOrShow(_))));
+        assert_eq!(game.current_phase, 4);
+        // the aces take the main pot of 300, the kings the 200 side pot the aces weren't in
+        assert_eq!(stacks(&game), [300, 200, 100]);
+    }
+
+    // 5% rake up to the cap
+    fn raked(cap: u32) -> Game {
+        let mut game = three_pairs();
+        game.config.rake = RakeConfig { percent: 5.0, cap };
+        game
+    }
+
+    // everyone calls or checks to the end and the losers muck
+    fn call_down(game: &mut Game) -> Vec<GameEvent> {
+        let mut events = Vec::new();
+        while game.current_phase < 4 {
+            events.extend(game.advance_game(GamePlayerAction::Call).unwrap());
+        }
+        events.extend(game.muck_remaining());
+        events
+    }
+
+    fn showdown_rake(events: &[GameEvent]) -> Vec<u32> {
+        events.iter().filter_map(|event| match event {
+            GameEvent::Showdown((_, steps, _)) => Some(steps.iter().map(|step| step.rake_taken).collect::<Vec<_>>()),
+            _ => None,
+        }).flatten().collect()
+    }
+
+    #[test]
+    fn the_rake_is_a_percentage_of_the_pot() {
+        let mut game = raked(100);
+        game.advance_game(GamePlayerAction::Raise(90)).unwrap();
+        let events = call_down(&mut game);
+        // 5% of 300
+        assert_eq!(showdown_rake(&events), [15]);
+        assert_eq!(stacks(&game), [1185, 900, 900]);
+    }
+
+    #[test]
+    fn the_rake_stops_at_the_cap() {
+        let mut game = raked(10);
+        game.advance_game(GamePlayerAction::Raise(90)).unwrap();
+        let events = call_down(&mut game);
+        assert_eq!(showdown_rake(&events), [10]);
+        assert_eq!(stacks(&game), [1190, 900, 900]);
+    }
+
+    #[test]
+    fn an_uncontested_pot_is_only_raked_on_called_chips() {
+        let mut game = raked(100);
+        game.advance_game(GamePlayerAction::Raise(90)).unwrap();
+        while game.current_phase == 0 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        game.advance_game(GamePlayerAction::Bet(100)).unwrap();
+        game.advance_game(GamePlayerAction::Raise(200)).unwrap();
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        let events = game.advance_game(GamePlayerAction::Fold).unwrap();
+        // 200 of the raise went uncalled, so only 500 of the 700 is raked
+        assert!(events.iter().any(|event| matches!(event, GameEvent::WonUncontested(2, 675))), "{:?}", events);
+        assert_eq!(stacks(&game), [900, 800, 1275]);
+    }
+
+    #[test]
+    fn a_hand_over_before_the_flop_isnt_raked() {
+        let mut game = raked(100);
+        game.advance_game(GamePlayerAction::Raise(90)).unwrap();
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        let events = game.advance_game(GamePlayerAction::Fold).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::WonUncontested(0, 115))), "{:?}", events);
+        assert_eq!(stacks(&game), [1015, 995, 990]);
+    }
+
+    #[test]
+    fn blinds_go_up_and_busted_players_are_dropped() {
+        let schedule = BlindSchedule { levels: vec![(5, 10, 0), (10, 20, 2)], level_every_hands: 2 };
+        assert_eq!(schedule.level(1), Some(BlindConfig { small_blind: 5, big_blind: 10, ante: 0 }));
+        assert_eq!(schedule.level(2), Some(BlindConfig { small_blind: 10, big_blind: 20, ante: 2 }));
+        // past the last level the blinds stay there
+        assert_eq!(schedule.level(50), schedule.level(2));
+
+        let mut game = game(vec![1000; 4]);
+        game = game.next_hand(&schedule, 1).unwrap();
+        assert_eq!(game.current_bet, 10);
+        game.players[3].money = 0;
+        game = game.next_hand(&schedule, 2).unwrap();
+        assert_eq!(game.players.len(), 3);
+        assert_eq!(game.config.blinds, BlindConfig { small_blind: 10, big_blind: 20, ante: 2 });
+        assert_eq!(game.current_bet, 20);
+    }
+
+    // aces, kings and queens on a board that doesn't help anyone
+    fn three_pairs() -> Game {
+        Game::new_with_cards(vec![(0, 1000, cards("As Ah")), (1, 1000, cards("Ks Kh")), (2, 1000, cards("Qs Qh"))], cards("2c 7d 9h Js 3s"), BlindConfig::default()).unwrap()
+    }
+
+    #[test]
+    fn mucked_hands_stay_hidden() {
+        let mut game = three_pairs();
+        let mut events = Vec::new();
+        while game.current_phase < 4 {
+            events.extend(game.advance_game(GamePlayerAction::Call).unwrap());
+        }
+        assert!(matches!(events.last(), Some(GameEvent::MuckOrShow(seats)) if seats == &[1, 2]));
+        assert!(game.advance_game_as(1, GamePlayerAction::Muck).unwrap().iter().all(|event| !matches!(event, GameEvent::Showdown(_))));
+        let events = game.advance_game_as(2, GamePlayerAction::Show).unwrap();
+        let Some(GameEvent::Showdown((hands, steps, _))) = events.last() else { panic!("no showdown: {:?}", events) };
+        assert!(hands[0].is_some() && hands[1].is_none() && hands[2].is_some());
+        // the kings still lost the pot they were in
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].winners, [0]);
+        assert!(steps[0].eligible_players.contains(&1));
+    }
+
+    fn timed(time_bank: Duration) -> Game {
+        make_game_seeded(vec![1000, 1000, 1000], GameConfig { turn_time: Some(Duration::from_secs(30)), time_bank, ..Default::default() }, 0, 7).unwrap()
+    }
+
+    #[test]
+    fn running_out_of_time_folds_facing_a_bet() {
+        let mut game = timed(Duration::ZERO);
+        let deadline = game.turn_deadline.unwrap();
+        assert!(game.check_timeout(deadline - Duration::from_millis(1)).is_empty());
+        let events = game.check_timeout(deadline);
+        assert!(matches!(events.first(), Some(GameEvent::PlayerAction(0, GamePlayerAction::Fold))));
+        assert!(game.player(0).has_folded);
+        assert_eq!(game.current_turn, 1);
+    }
+
+    #[test]
+    fn running_out_of_time_checks_when_nothing_is_owed() {
+        let mut game = timed(Duration::ZERO);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        // the big blind's option
+        assert_eq!(game.current_turn, 2);
+        let events = game.check_timeout(game.turn_deadline.unwrap());
+        assert!(matches!(events.first(), Some(GameEvent::PlayerAction(2, GamePlayerAction::Check))));
+        assert!(!game.player(2).has_folded);
+        assert_eq!(game.current_phase, 1);
+    }
+
+    #[test]
+    fn the_time_bank_goes_on_top_of_the_turn_time() {
+        let mut game = timed(Duration::from_secs(10));
+        let deadline = game.turn_deadline.unwrap();
+        assert!(game.check_timeout(deadline).is_empty());
+        assert!(game.using_time_bank());
+        assert_eq!(game.turn_deadline, Some(deadline + Duration::from_secs(10)));
+        // acting in time keeps whatever wasn't used
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        assert!(!game.using_time_bank());
+        assert_eq!(game.time_bank(0), Duration::from_secs(10));
+
+        // seat 1 lets all of it run out
+        let deadline = game.turn_deadline.unwrap();
+        assert!(game.check_timeout(deadline).is_empty());
+        assert!(game.check_timeout(deadline + Duration::from_secs(10) - Duration::from_millis(1)).is_empty());
+        let events = game.check_timeout(deadline + Duration::from_secs(10));
+        assert!(matches!(events.first(), Some(GameEvent::PlayerAction(1, GamePlayerAction::Fold))));
+        assert!(!game.using_time_bank());
+        assert_eq!(game.time_bank(1), Duration::ZERO);
+        assert_eq!(game.time_bank(2), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn a_restored_snapshot_plays_on_the_same() {
+        let mut game = game(vec![1000, 600, 1000]);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        while game.current_phase == 0 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        game.advance_game(GamePlayerAction::Bet(50)).unwrap();
+
+        let mut restored = Game::from_snapshot(&game.full_snapshot()).unwrap();
+        assert_eq!(state(&restored), state(&game));
+        assert_eq!((restored.current_phase, restored.current_turn), (1, game.current_turn));
+        assert_eq!(pots(&restored), pots(&game));
+        for action in [GamePlayerAction::AddMoney(560), GamePlayerAction::Call, GamePlayerAction::Call] {
+            assert_eq!(format!("{:?}", restored.advance_game(action.clone())), format!("{:?}", game.advance_game(action)));
+        }
+        assert_eq!(state(&restored), state(&game));
+        // without everyone's cards there's no hand to restore
+        assert!(Game::from_snapshot(&game.snapshot(Some(0))).is_none());
+    }
+
+    #[test]
+    fn nobody_left_in_a_level_leaves_no_empty_pot() {
+        let mut game = game(vec![1000, 100, 60]);
+        game.advance_game(GamePlayerAction::Raise(190)).unwrap();
+        // the raiser leaves the table out of turn, and both blinds go all-in for less
+        game.player_mut(0).has_folded = true;
+        assert!(game.check_hand_over().is_none());
+        game.advance_game(GamePlayerAction::AddMoney(95)).unwrap();
+        let events = game.advance_game(GamePlayerAction::AddMoney(50)).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::MuckOrShow(_) | GameEvent::Showdown(_))));
+
+        // the folded raiser's last 100 had nobody to win it, so it joins the pot below
+        assert_eq!(pots(&game), [(180, vec![2, 1]), (180, vec![1])]);
+        assert!(game.compute_pots().iter().all(|pot| pot.money > 0 && !pot.eligible_players.is_empty()));
+        game.muck_remaining();
+        assert_eq!(stacks(&game).iter().sum::<u32>(), 1160);
+    }
+
+    #[test]
+    fn the_last_player_in_wins_without_a_showdown() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        let events = game.advance_game(GamePlayerAction::Fold).unwrap();
+        assert!(matches!(events.last(), Some(GameEvent::WonUncontested(2, 15))));
+        assert!(!events.iter().any(|event| matches!(event, GameEvent::RevealFlop(_) | GameEvent::Showdown(_) | GameEvent::MuckOrShow(_))));
+        assert_eq!(stacks(&game), [1000, 995, 1005]);
+        assert_rejected(&mut game, GamePlayerAction::Call, ActionError::HandOver);
+    }
+
+    #[test]
+    fn folding_out_of_turn_can_end_the_hand() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        assert!(game.check_hand_over().is_none());
+        // the big blind leaves while the small blind is to act
+        game.player_mut(2).has_folded = true;
+        let events = game.check_hand_over().unwrap();
+        assert!(matches!(events[..], [GameEvent::WonUncontested(1, 15)]));
+        assert_eq!(stacks(&game), [1000, 1010, 990]);
+        assert!(game.check_hand_over().is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "the same card was dealt twice")]
+    fn dealing_a_card_twice_is_caught() {
+        // the first two cards off the deck are the same one
+        let mut deck = get_shuffled_deck(DeckKind::Standard, 294);
+        let top = deck.len() - 1;
+        deck[top - 1] = deck[top];
+        let _ = make_game_with_deck(vec![1000, 1000, 1000], GameConfig::default(), 0, deck);
+    }
+
+    #[test]
+    fn calling_a_raise_adds_the_difference() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        // the small blind already has 5 of the 40 in
+        let events = game.advance_game(GamePlayerAction::Call).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAction(1, GamePlayerAction::AddMoney(35)))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::OwnedMoneyChange(1, 960))));
+        assert_eq!(game.players[1].round_contribution, 40);
+    }
+
+    #[test]
+    fn calling_with_too_little_goes_all_in() {
+        let mut game = game(vec![1000, 20, 1000]);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        let events = game.advance_game(GamePlayerAction::Call).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAction(1, GamePlayerAction::AddMoney(15)))));
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAllIn(1))));
+        assert_eq!(game.players[1].money, 0);
+        // the bet stays where the raise put it
+        assert_eq!(game.current_bet, 40);
+    }
+
+    fn reveal_order(events: &[GameEvent]) -> Vec<u8> {
+        events.iter().find_map(|event| match event {
+            GameEvent::Showdown((_, _, order)) => Some(order.clone()),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn the_last_aggressor_shows_first() {
+        let mut game = three_pairs();
+        while game.current_phase < 3 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        // seat 1 checks the river, seat 2 bets and gets called by both
+        game.advance_game_as(1, GamePlayerAction::Check).unwrap();
+        game.advance_game_as(2, GamePlayerAction::AddMoney(50)).unwrap();
+        game.advance_game_as(0, GamePlayerAction::Call).unwrap();
+        let mut events = game.advance_game_as(1, GamePlayerAction::Call).unwrap();
+        events.extend(game.muck_remaining());
+        assert_eq!(reveal_order(&events), [2, 0, 1]);
+
+        // checked down, whoever acted first on the river shows first
+        assert_eq!(reveal_order(&call_down(&mut three_pairs())), [1, 2, 0]);
+    }
+
+    #[test]
+    fn odd_chips_go_left_of_the_button_first() {
+        // the board plays for everyone
+        let mut game = Game::new_with_cards(vec![(0, 1000, cards("2c 3d")), (1, 1000, cards("2d 3h")), (2, 1000, cards("2h 3s")), (3, 1000, cards("2s 3c"))], cards("Ts Js Qs Ks As"), BlindConfig::default()).unwrap();
+        game.advance_game_as(3, GamePlayerAction::Call).unwrap();
+        game.advance_game_as(0, GamePlayerAction::Call).unwrap();
+        // the small blind's 5 stays in, which makes a pot of 35 for three
+        game.advance_game_as(1, GamePlayerAction::Fold).unwrap();
+        let events = call_down(&mut game);
+        let steps = events.iter().find_map(|event| match event { GameEvent::Showdown((_, steps, _)) => Some(steps.clone()), _ => None }).unwrap();
+        assert!(matches!(steps.as_slice(), [step] if step.winners == [2, 3, 0] && step.winnings == 35));
+        assert_eq!(stacks(&game), [1001, 995, 1002, 1002]);
+    }
+
+    #[test]
+    fn a_set_up_deal_plays_out_as_dealt() {
+        // seat 1 has the nut flush, seat 0 two pair and seat 2 nothing
+        let mut game = Game::new_with_cards(vec![(0, 1000, cards("Kc Qd")), (1, 1000, cards("Ah 3h")), (2, 1000, cards("2c 4s"))], cards("Kh Qh 8h 5c 9d"), BlindConfig::default()).unwrap();
+        assert!(game.players[1].private_cards[0].full_eq(&"Ah".parse().unwrap()));
+        let events = call_down(&mut game);
+        let (hands, steps) = events.iter().find_map(|event| match event { GameEvent::Showdown((hands, steps, _)) => Some((hands.clone(), steps.clone())), _ => None }).unwrap();
+        assert!(matches!(steps.as_slice(), [step] if step.winners == [1] && step.winnings == 30));
+        assert!(hands[1].as_ref().is_some_and(|(_, best, rank)| rank.category == HandCategory::Flush && best.iter().all(|card| card.suit == 2)));
+        assert_eq!(stacks(&game), [990, 1020, 990]);
+    }
+
+    #[test]
+    fn folding_on_the_river_keeps_the_cards_hidden() {
+        let mut game = three_pairs();
+        while game.current_phase < 3 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        game.advance_game_as(1, GamePlayerAction::Bet(50)).unwrap();
+        game.advance_game_as(2, GamePlayerAction::Fold).unwrap();
+        let mut events = game.advance_game_as(0, GamePlayerAction::Call).unwrap();
+        events.extend(game.muck_remaining());
+        let Some(GameEvent::Showdown((hands, _, order))) = events.iter().find(|event| matches!(event, GameEvent::Showdown(_))) else { panic!("no showdown: {:?}", events) };
+        assert!(hands[2].is_none());
+        assert!(!order.contains(&2));
+        // the queens are nowhere in what everyone is told
+        let queens = game.player(2).private_cards;
+        for (private, best, _) in hands.iter().flatten() {
+            assert!(!private.iter().chain(best).any(|card| queens.iter().any(|queen| queen.full_eq(card))));
+        }
+    }
+
+    #[test]
+    fn a_folded_player_pays_into_pots_they_cant_win() {
+        let mut game = game(vec![100, 1000, 1000]);
+        game.advance_game(GamePlayerAction::AddMoney(100)).unwrap();
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game_as(1, GamePlayerAction::Bet(200)).unwrap();
+        game.advance_game_as(2, GamePlayerAction::Call).unwrap();
+        game.advance_game_as(1, GamePlayerAction::Bet(300)).unwrap();
+        assert_eq!(pots(&game), [(300, vec![0, 2, 1]), (400, vec![2, 1]), (300, vec![1])]);
+        // seat 2's 300 stays in, but they're out of both pots they paid into
+        game.advance_game_as(2, GamePlayerAction::Fold).unwrap();
+        assert_eq!(pots(&game), [(300, vec![0, 1]), (400, vec![1]), (300, vec![1])]);
+        assert_eq!(game.total_pot(), 1000);
+    }
+
+    #[test]
+    fn running_it_twice_pays_half_the_pot_on_each_board() {
+        let mut game = make_game_seeded(vec![1000, 1000], GameConfig { run_it_twice: true, ..Default::default() }, 0, 329).unwrap();
+        game.advance_game(GamePlayerAction::AddMoney(995)).unwrap();
+        let events = game.advance_game(GamePlayerAction::Call).unwrap();
+        assert!(matches!(events.last(), Some(GameEvent::RunItTwiceOffer(seats)) if seats == &[0, 1]));
+        let mut events = game.vote_run_it_twice(0, true).unwrap();
+        events.extend(game.vote_run_it_twice(1, true).unwrap());
+        events.extend(game.muck_remaining());
+
+        let runs: Vec<u32> = events.iter().filter_map(|event| match event {
+            GameEvent::Showdown((_, steps, _)) => Some(steps.iter().map(|step| step.winnings).sum()),
+            _ => None,
+        }).collect();
+        assert_eq!(runs, [1000, 1000]);
+        assert!(game.second_board().is_some());
+        assert_eq!(stacks(&game).iter().sum::<u32>(), 2000);
+    }
+
+    #[test]
+    fn the_public_view_holds_no_cards() {
+        let mut game = game(vec![1000, 600, 1000]);
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        let view = game.public_view();
+        assert_eq!(view.len(), 3);
+        for (public, player) in view.iter().zip(&game.players) {
+            assert_eq!((public.id, public.money, public.total_contribution, public.has_folded), (player.id, player.money, player.total_contribution, player.has_folded));
+        }
+        assert!(!format!("{:?}", view).contains("Card"));
+        // the same goes for a spectator's snapshot before the flop
+        let snapshot = game.snapshot(None);
+        assert!(snapshot.players.iter().all(|player| player.private_cards.is_none()));
+        assert!(snapshot.public_cards.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn the_straddle_acts_last_preflop() {
+        let mut game = make_game_seeded(vec![1000; 4], GameConfig { straddle: Some(20), ..Default::default() }, 0, 335).unwrap();
+        assert_eq!(stacks(&game), [1000, 995, 990, 980]);
+        assert_eq!(game.current_bet, 20);
+        // the first one after the straddle opens, and a raise has to go at least another 20
+        assert_eq!(game.current_turn, 0);
+        assert_eq!(game.legal_actions().can_call, Some(20));
+        assert_eq!(game.legal_actions().min_raise, Some(20));
+        let mut order = Vec::new();
+        while game.current_phase == 0 {
+            order.push(game.current_turn);
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        // everyone just called, so the straddler gets the option the big blind usually has
+        assert_eq!(order, [0, 1, 2, 3]);
+        assert_eq!(stacks(&game), [980; 4]);
+        assert_eq!(game.current_turn, 1);
+    }
+
+    #[test]
+    fn missed_blinds_are_dead_money() {
+        let mut game = game(vec![1000, 1000, 1000, 8]);
+        game.post_missed_blinds(0, 10);
+        assert_eq!(stacks(&game), [990, 995, 990, 8]);
+        assert_eq!(game.total_pot(), 25);
+        // it doesn't count towards calling the big blind
+        assert_eq!(game.current_bet, 10);
+        assert_eq!(game.current_turn, 3);
+        // a stack that can't cover them goes all-in and the action skips it
+        game.post_missed_blinds(3, 10);
+        assert_eq!(game.player(3).money, 0);
+        assert_eq!(game.total_pot(), 33);
+        assert_eq!(game.current_turn, 0);
+        assert_eq!(game.legal_actions().can_call, Some(10));
+    }
+
+    #[test]
+    fn only_a_bet_of_the_whole_stack_is_all_in() {
+        let mut game = game(vec![1000, 200, 1000]);
+        for _ in 0..3 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+        }
+        assert_eq!((game.current_phase, game.current_turn), (1, 1));
+        let events = game.advance_game(GamePlayerAction::Bet(50)).unwrap();
+        assert!(!events.iter().any(|event| matches!(event, GameEvent::PlayerAllIn(_))));
+        assert_eq!(game.players[1].money, 140);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game(GamePlayerAction::Call).unwrap();
+
+        assert_eq!((game.current_phase, game.current_turn), (2, 1));
+        let events = game.advance_game(GamePlayerAction::Bet(140)).unwrap();
+        assert!(events.iter().any(|event| matches!(event, GameEvent::PlayerAllIn(1))));
+        assert_eq!(game.players[1].money, 0);
+        assert!(!game.players[1].can_act());
+    }
+
+    #[test]
+    fn the_pot_total_adds_up_the_side_pots() {
+        let mut game = game(vec![1000, 100, 300, 1000]);
+        let adds_up = |game: &Game| {
+            let contributions: u32 = game.players.iter().map(|p| p.total_contribution).sum();
+            let pots: u32 = game.compute_pots().iter().map(|pot| pot.money).sum();
+            assert_eq!(game.total_pot(), contributions);
+            assert_eq!(game.total_pot(), pots);
+        };
+        adds_up(&game);
+        assert_eq!(game.total_pot(), 15);
+
+        game.advance_game(GamePlayerAction::Raise(290)).unwrap();
+        for _ in 0..3 {
+            game.advance_game(GamePlayerAction::Call).unwrap();
+            adds_up(&game);
+        }
+        assert_eq!(pots(&game), vec![(400, vec![1, 0, 2, 3]), (600, vec![0, 2, 3])]);
+        assert_eq!(game.total_pot(), 1000);
+
+        game.advance_game(GamePlayerAction::Bet(200)).unwrap();
+        adds_up(&game);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        adds_up(&game);
+        assert_eq!(game.compute_pots().len(), 3);
+        assert_eq!(game.total_pot(), 1400);
+    }
+
+    #[test]
+    fn the_legal_actions_follow_the_bet() {
+        let mut game = game(vec![1000, 1000, 1000]);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        // the big blind's option, nothing more to put in
+        let option = LegalActions { can_check: true, can_call: None, min_raise: Some(10), max_raise: 990, can_fold: true };
+        assert_eq!(game.legal_actions(), option);
+        game.advance_game(GamePlayerAction::Raise(30)).unwrap();
+        let facing_a_raise = LegalActions { can_check: false, can_call: Some(30), min_raise: Some(30), max_raise: 960, can_fold: true };
+        assert_eq!(game.legal_actions(), facing_a_raise);
+    }
+
+    #[test]
+    fn a_short_stack_can_only_raise_what_it_has() {
+        let mut game = game(vec![1000, 1000, 1000, 25]);
+        assert_eq!(game.current_turn, 3);
+        let short = LegalActions { can_check: false, can_call: Some(10), min_raise: Some(10), max_raise: 15, can_fold: true };
+        assert_eq!(game.legal_actions(), short);
+        game.advance_game(GamePlayerAction::Call).unwrap();
+        game.advance_game(GamePlayerAction::Raise(100)).unwrap();
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        game.advance_game(GamePlayerAction::Fold).unwrap();
+        // 100 more to call and 15 left, calling is all-in and there's no raise
+        let all_in = LegalActions { can_check: false, can_call: Some(15), min_raise: None, max_raise: 0, can_fold: true };
+        assert_eq!(game.legal_actions(), all_in);
+    }
+}
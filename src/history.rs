@@ -0,0 +1,116 @@
+use std::time::SystemTime;
+
+use crate::{cards::{Card, format_cards}, events::{GameEvent, GamePlayerAction}, game::ShowdownStep};
+
+#[derive(Debug, Clone)]
+pub enum HistoryEvent {
+    AntePosted(u8, u32), // seat, amount
+    BlindPosted(u8, u32),
+    MissedBlindsPosted(u8, u32), // dead money from a player coming back
+    Action(u8, GamePlayerAction),
+    Board(Vec<Card>), // only the newly revealed cards
+    SecondBoard(Vec<Card>), // the cards the second run dealt instead
+    Showdown(Vec<ShowdownStep>),
+    WonUncontested(u8, u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub time: SystemTime,
+    pub event: HistoryEvent,
+}
+
+// everything that happened in a hand, in order
+#[derive(Debug, Clone, Default)]
+pub struct HandHistory {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl HandHistory {
+    pub fn push(&mut self, event: HistoryEvent) {
+        self.entries.push(HistoryEntry { time: SystemTime::now(), event });
+    }
+
+    // keeps the parts of the game events that are worth reading back later
+    pub fn record(&mut self, event: &GameEvent) {
+        let event = match event {
+            GameEvent::PlayerAction(seat, action) => HistoryEvent::Action(*seat, action.clone()),
+            GameEvent::RevealFlop(cards) => HistoryEvent::Board(cards.to_vec()),
+            GameEvent::RevealTurn(card) | GameEvent::RevealRiver(card) => HistoryEvent::Board(vec![*card]),
+            GameEvent::RevealSecondBoard(cards) => HistoryEvent::SecondBoard(cards.clone()),
+            GameEvent::Showdown((_, steps, _)) => HistoryEvent::Showdown(steps.clone()),
+            GameEvent::WonUncontested(seat, winnings) => HistoryEvent::WonUncontested(*seat, *winnings),
+            _ => return,
+        };
+        self.push(event);
+    }
+
+    // one line per entry, timed from the start of the hand
+    pub fn to_text(&self) -> String {
+        let Some(start) = self.entries.first().map(|e| e.time) else {
+            return String::new();
+        };
+
+        let mut text = String::new();
+        for entry in &self.entries {
+            let elapsed = entry.time.duration_since(start).unwrap_or_default().as_secs_f32();
+            let line = match &entry.event {
+                HistoryEvent::AntePosted(seat, amount) => format!("seat {} posts an ante of {}", seat, amount),
+                HistoryEvent::BlindPosted(seat, amount) => format!("seat {} posts a blind of {}", seat, amount),
+                HistoryEvent::MissedBlindsPosted(seat, amount) => format!("seat {} posts {} of missed blinds", seat, amount),
+                HistoryEvent::Action(seat, GamePlayerAction::Check) => format!("seat {} checks", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::AddMoney(amount)) => format!("seat {} puts in {}", seat, amount),
+                HistoryEvent::Action(seat, GamePlayerAction::Fold) => format!("seat {} folds", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::Call) => format!("seat {} calls", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::Bet(amount)) => format!("seat {} bets {}", seat, amount),
+                HistoryEvent::Action(seat, GamePlayerAction::Raise(amount)) => format!("seat {} raises by {}", seat, amount),
+                HistoryEvent::Action(seat, GamePlayerAction::Muck) => format!("seat {} mucks", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::Show) => format!("seat {} shows", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::RunItTwice(true)) => format!("seat {} agrees to run it twice", seat),
+                HistoryEvent::Action(seat, GamePlayerAction::RunItTwice(false)) => format!("seat {} wants to run it once", seat),
+                HistoryEvent::Board(cards) => format!("board: {}", format_cards(cards)),
+                HistoryEvent::SecondBoard(cards) => format!("second board: {}", format_cards(cards)),
+                HistoryEvent::Showdown(steps) => steps.iter().map(|step| {
+                    let winners = step.winners.iter().map(|w| w.to_string()).collect::<Vec<_>>().join(", ");
+                    if step.winners.len() == 1 {
+                        format!("seat {} wins {} from pot {}", winners, step.winnings, step.pot_start_index)
+                    } else {
+                        format!("seats {} split {} from pot {}", winners, step.winnings, step.pot_start_index)
+                    }
+                }).collect::<Vec<_>>().join("\n         "),
+                HistoryEvent::WonUncontested(seat, winnings) => format!("seat {} wins {} uncontested", seat, winnings),
+            };
+            text.push_str(&format!("[{:>5.1}s] {}\n", elapsed, line));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{cards::format_cards, events::GamePlayerAction, game::{GameConfig, make_game_seeded}};
+
+    #[test]
+    fn a_hand_reads_back_in_order() {
+        let mut game = make_game_seeded(vec![1000, 1000, 1000], GameConfig::default(), 0, 7).unwrap();
+        for action in [GamePlayerAction::Call, GamePlayerAction::Fold, GamePlayerAction::Check, GamePlayerAction::Bet(20), GamePlayerAction::Fold] {
+            game.advance_game(action).unwrap();
+        }
+        let flop = format!("board: {}", format_cards(&game.public_cards[..3]));
+        let history = game.take_history();
+        assert!(history.entries.windows(2).all(|pair| pair[0].time <= pair[1].time));
+        let text = history.to_text();
+        let lines: Vec<&str> = text.lines().map(|line| line.split_once("] ").unwrap().1).collect();
+        assert_eq!(lines, [
+            "seat 1 posts a blind of 5",
+            "seat 2 posts a blind of 10",
+            "seat 0 puts in 10",
+            "seat 1 folds",
+            "seat 2 checks",
+            &flop,
+            "seat 2 puts in 20",
+            "seat 0 folds",
+            "seat 2 wins 45 uncontested",
+        ]);
+    }
+}
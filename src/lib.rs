@@ -1,5 +1,12 @@
+pub mod bot;
 pub mod cards;
+pub mod equity;
 pub mod events;
 pub mod game;
+pub mod history;
 pub mod protocol;
+#[cfg(feature = "json")]
+pub mod protocol_json;
 pub mod networking;
+pub mod session;
+pub mod strategy;
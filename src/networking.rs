@@ -1,57 +1,194 @@
-use std::{io::{Read, Write, Result}, net::TcpStream, sync::mpsc::{Receiver, Sender}, thread};
+use std::{collections::VecDeque, io::{Error, ErrorKind, Read, Result, Write}, net::TcpStream, sync::{Arc, Mutex, Weak, mpsc::{Receiver, SendError, Sender, TryRecvError}}, thread, time::{Duration, Instant}};
 
-use crate::{events::{ClientBound, ServerBound}, protocol::{decode_client_bound, decode_server_bound, encode_client_bound, encode_server_bound}};
+use log::{debug, warn};
 
-pub fn client_network_loop(stream: &mut TcpStream, tx: Sender<ClientBound>) {
-    let mut remaining_packet_size = 0;
-    let mut packet_size_received = false;
-    let mut packet = Vec::<u8>::new();
+use crate::{events::{ClientBound, ServerBound}, protocol::{DecodeError, decode_client_bound, decode_server_bound, encode_client_bound, encode_server_bound}};
+
+pub const MAX_PACKET_SIZE: usize = 1 << 20;
+
+// every packet is prefixed with its length as a LEB128 varint, empty packets are allowed and ignored
+pub fn frame_packet(mut packet: Vec<u8>) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(packet.len() + 3);
+    let mut size = packet.len();
     loop {
-        let mut buffer = [0u8; 1024];
-        let bytes_read = match stream.read(&mut buffer[..]) {
-            Err(_) => panic!("real error???"),
-            Ok(0) => panic!("another error???"), // peer disconnected
-            Ok(n) => n,
-        };
+        let byte = (size & 0x7F) as u8;
+        size >>= 7;
+        if size == 0 {
+            msg.push(byte);
+            break;
+        }
+        msg.push(byte | 0x80);
+    }
+    msg.append(&mut packet);
+    msg
+}
 
-        let mut slice = &buffer[..bytes_read];
+// anything the protocol can run over, a socket or the in-memory pair below
+pub trait Transport: Read + Write {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()>;
+}
+impl Transport for TcpStream {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
 
-        while !slice.is_empty() {
-            if !packet_size_received {
-                let size = slice[0];
-                slice = &slice[1..];
+// one end of a connection that never leaves the process, what one end writes the other reads
+// reads after the other end is dropped return 0 like a closed socket once everything sent is read
+// clones are more handles to the same end, like a cloned TcpStream, so one thread can read while another writes
+#[derive(Clone)]
+pub struct MemoryTransport {
+    incoming: Arc<Mutex<VecDeque<u8>>>,
+    outgoing: Arc<Mutex<VecDeque<u8>>>,
+    nonblocking: bool,
+    end: Arc<()>, // shared by the handles to this end, the other end only holds on to it weakly
+    other_end: Weak<()>,
+}
+impl MemoryTransport {
+    pub fn pair() -> (MemoryTransport, MemoryTransport) {
+        let (a, b) = (Arc::new(Mutex::new(VecDeque::new())), Arc::new(Mutex::new(VecDeque::new())));
+        let (first_end, second_end) = (Arc::new(()), Arc::new(()));
+        let first = MemoryTransport { incoming: a.clone(), outgoing: b.clone(), nonblocking: false, other_end: Arc::downgrade(&second_end), end: first_end };
+        let second = MemoryTransport { incoming: b, outgoing: a, nonblocking: false, other_end: Arc::downgrade(&first.end), end: second_end };
+        (first, second)
+    }
 
-                if size > 0 {
-                    remaining_packet_size = size as usize;
-                    packet_size_received = true;
-                    packet.clear();
+    fn other_end_dropped(&self) -> bool {
+        self.other_end.strong_count() == 0
+    }
+}
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let mut incoming = self.incoming.lock().unwrap();
+            if !incoming.is_empty() || buf.is_empty() {
+                let n = buf.len().min(incoming.len());
+                for (slot, byte) in buf.iter_mut().zip(incoming.drain(..n)) {
+                    *slot = byte;
                 }
-            } else {
-                let to_take = remaining_packet_size.min(slice.len());
-                packet.extend_from_slice(&slice[..to_take]);
+                return Ok(n);
+            }
+            drop(incoming);
+            if self.other_end_dropped() {
+                return Ok(0);
+            }
+            if self.nonblocking {
+                return Err(Error::from(ErrorKind::WouldBlock));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.other_end_dropped() {
+            return Err(Error::from(ErrorKind::BrokenPipe));
+        }
+        self.outgoing.lock().unwrap().extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl Transport for MemoryTransport {
+    fn set_nonblocking(&mut self, nonblocking: bool) -> Result<()> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+}
 
-                slice = &slice[to_take..];
-                remaining_packet_size -= to_take;
+// reassembles framed packets from however the bytes happen to arrive
+#[derive(Default)]
+pub struct PacketReader {
+    size: usize,
+    size_shift: u32,
+    size_received: bool,
+    packet: Vec<u8>,
+}
+impl PacketReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-                if remaining_packet_size == 0 {
-                    if let Some(event) = decode_client_bound(&packet) {
-                        tx.send(event).expect("Networking failed to send message to client.");
-                    }
-                    packet_size_received = false;
+    // returns every packet completed by these bytes, errors if the stream can't be framed anymore
+    pub fn feed(&mut self, mut bytes: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut packets = Vec::new();
+        while !bytes.is_empty() {
+            if !self.size_received {
+                let byte = bytes[0];
+                bytes = &bytes[1..];
+
+                if self.size_shift >= 21 { // three bytes already cover MAX_PACKET_SIZE
+                    return Err(Error::new(ErrorKind::InvalidData, "packet size too long"));
+                }
+                self.size |= ((byte & 0x7F) as usize) << self.size_shift;
+                self.size_shift += 7;
+                if self.size > MAX_PACKET_SIZE {
+                    return Err(Error::new(ErrorKind::InvalidData, "packet size over the limit"));
+                }
+                if byte & 0x80 == 0 {
+                    self.size_received = self.size > 0;
+                    self.size_shift = 0;
+                    self.packet.clear();
+                }
+            } else {
+                let to_take = (self.size - self.packet.len()).min(bytes.len());
+                self.packet.extend_from_slice(&bytes[..to_take]);
+                bytes = &bytes[to_take..];
+
+                if self.packet.len() == self.size {
+                    packets.push(std::mem::take(&mut self.packet));
+                    self.size = 0;
+                    self.size_received = false;
                 }
             }
         }
+        Ok(packets)
     }
 }
 
-pub fn handle_client(id: u64, mut stream: TcpStream, client_bound_receiver: Receiver<ClientBound>, server_bound_sender: Sender<(u64, ServerBound)>) -> core::result::Result<(), Box<dyn std::error::Error>> {
+// the packet is dropped either way, an unknown opcode is most likely just a version mismatch though
+fn log_decode_error(from: &str, error: &DecodeError) {
+    match error {
+        DecodeError::UnknownOpcode(..) => debug!("ignoring a packet from {}: {}", from, error),
+        _ => warn!("malformed packet from {}: {}", from, error),
+    }
+}
+
+// returns once the server closes the connection, or with the error that broke it
+// either way tx gets dropped, which is how the receiving end finds out
+pub fn client_network_loop(stream: &mut impl Transport, tx: Sender<ClientBound>) -> Result<()> {
+    let mut reader = PacketReader::new();
+    loop {
+        let mut buffer = [0u8; 1024];
+        let bytes_read = match stream.read(&mut buffer[..])? {
+            0 => return Ok(()),
+            n => n,
+        };
+
+        for packet in reader.feed(&buffer[..bytes_read])? {
+            match decode_client_bound(&packet) {
+                Ok(ClientBound::Ping) => send_event(stream, ServerBound::Pong)?,
+                Ok(ClientBound::Pong) => {},
+                Err(e) => log_decode_error("server", &e),
+                Ok(event) => if tx.send(event).is_err() {
+                    return Ok(()); // nobody is listening anymore
+                },
+            }
+        }
+    }
+}
+
+// clients that stay quiet get pinged after half the timeout and disconnected after all of it
+pub fn handle_client(id: u64, mut stream: impl Transport, client_bound_receiver: Receiver<ClientBound>, server_bound_sender: Sender<(u64, ServerBound)>, timeout: Duration) -> core::result::Result<(), Box<dyn std::error::Error>> {
     stream.set_nonblocking(true)?;
 
     let mut buf = [0u8; 1024];
-
-    let mut remaining_packet_size = 0;
-    let mut received_packet_size = false;
-    let mut packet = Vec::<u8>::new();
+    let mut reader = PacketReader::new();
+    let mut last_seen = Instant::now();
+    let mut pinged = false;
 
     loop {
         let received_size = match stream.read(&mut buf) {
@@ -63,49 +200,234 @@ pub fn handle_client(id: u64, mut stream: TcpStream, client_bound_receiver: Rece
             Ok(n) => n,
         };
         if received_size != 0 {
-            let bytes = &buf[..received_size];
-            
-            for byte in bytes {
-                if !received_packet_size {
-                    if *byte > 0 {
-                        remaining_packet_size = *byte;
-                        received_packet_size = true;
-                    }
-                } else {
-                    packet.push(*byte);
-                    remaining_packet_size -= 1;
-                    if remaining_packet_size == 0 {
-                        if let Some(event) = decode_server_bound(&packet) {
-                            server_bound_sender.send((id, event.clone()))?;
-                            if matches!(event, ServerBound::Disconnect) {
-                                return Ok(())
-                            }
+            last_seen = Instant::now();
+            pinged = false;
+
+            let Ok(packets) = reader.feed(&buf[..received_size]) else {
+                server_bound_sender.send((id, ServerBound::Disconnect))?;
+                return Ok(());
+            };
+
+            for packet in packets {
+                match decode_server_bound(&packet) {
+                    Ok(ServerBound::Ping) => if !send_to_client(id, &mut stream, ClientBound::Pong, &server_bound_sender)? {
+                        return Ok(());
+                    },
+                    Ok(ServerBound::Pong) => {},
+                    Err(e) => {
+                        log_decode_error(&format!("client {}", id), &e);
+                        // whatever the server queued before it goes out first, so the error lands after it like any other reply
+                        if !send_queued(id, &mut stream, &client_bound_receiver, &server_bound_sender)? {
+                            return Ok(());
+                        }
+                        if !send_to_client(id, &mut stream, ClientBound::Error { code: e.code(), message: e.to_string() }, &server_bound_sender)? {
+                            return Ok(());
+                        }
+                    },
+                    Ok(event) => {
+                        server_bound_sender.send((id, event.clone()))?;
+                        if matches!(event, ServerBound::Disconnect) {
+                            return Ok(())
                         }
-                        received_packet_size = false;
-                        packet.clear();
                     }
                 }
             }
-        }
-
-        for event in client_bound_receiver.try_iter() {
-            let mut packet = encode_client_bound(event);
-            let mut msg = vec![packet.len() as u8];
-            msg.append(&mut packet);
-            if let Err(_) = stream.write_all(&msg) {
-                server_bound_sender.send((id, ServerBound::Disconnect))?;
+        } else if last_seen.elapsed() > timeout {
+            server_bound_sender.send((id, ServerBound::Disconnect))?;
+            return Ok(());
+        } else if !pinged && last_seen.elapsed() > timeout / 2 {
+            pinged = true;
+            if !send_to_client(id, &mut stream, ClientBound::Ping, &server_bound_sender)? {
                 return Ok(());
             }
         }
 
+        if !send_queued(id, &mut stream, &client_bound_receiver, &server_bound_sender)? {
+            return Ok(());
+        }
+
         thread::sleep(std::time::Duration::from_millis(1));
     }
 }
 
-pub fn send_event(conn: &mut TcpStream, event: ServerBound) -> Result<()> {
-    let mut packet = encode_server_bound(event);
-    let mut msg = vec![packet.len() as u8];
-    msg.append(&mut packet);
-    conn.write_all(&msg)?;
+// everything the server has queued for the client so far
+// false if the connection is gone or the server let go of this client, either way it closes
+fn send_queued(id: u64, stream: &mut impl Write, client_bound_receiver: &Receiver<ClientBound>, server_bound_sender: &Sender<(u64, ServerBound)>) -> core::result::Result<bool, SendError<(u64, ServerBound)>> {
+    loop {
+        let event = match client_bound_receiver.try_recv() {
+            Ok(event) => event,
+            Err(TryRecvError::Empty) => return Ok(true),
+            Err(TryRecvError::Disconnected) => return Ok(false),
+        };
+        if !send_to_client(id, stream, event, server_bound_sender)? {
+            return Ok(false);
+        }
+    }
+}
+
+// false if the connection is gone, the server has been told about it by then
+fn send_to_client(id: u64, stream: &mut impl Write, event: ClientBound, server_bound_sender: &Sender<(u64, ServerBound)>) -> core::result::Result<bool, SendError<(u64, ServerBound)>> {
+    if stream.write_all(&frame_packet(encode_client_bound(event))).is_err() {
+        server_bound_sender.send((id, ServerBound::Disconnect))?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+pub fn send_event(conn: &mut impl Write, event: ServerBound) -> Result<()> {
+    conn.write_all(&frame_packet(encode_server_bound(event)))?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::PlayerState;
+    use std::sync::mpsc;
+
+    #[test]
+    fn long_player_list_round_trips() {
+        let list: Vec<(PlayerState, u32, String)> = (0..20).map(|i| (PlayerState::Ready, 1000 + i, format!("player{:0>10}", i))).collect();
+        let packet = encode_client_bound(ClientBound::UpdatePlayerList(list.clone()));
+        assert!(packet.len() > 255);
+        let framed = frame_packet(packet.clone());
+        assert_eq!(framed.len(), packet.len() + 2);
+
+        // a byte at a time, the length prefix too
+        let mut reader = PacketReader::new();
+        let mut packets = Vec::new();
+        for byte in &framed {
+            packets.extend(reader.feed(std::slice::from_ref(byte)).unwrap());
+        }
+        assert_eq!(packets, vec![packet]);
+        let Ok(ClientBound::UpdatePlayerList(decoded)) = decode_client_bound(&packets[0]) else { panic!("not a player list") };
+        assert_eq!(format!("{:?}", decoded), format!("{:?}", list));
+    }
+
+    // whatever went through is delivered and gone, nothing is held on to for later
+    #[test]
+    fn nothing_piles_up_after_lots_of_traffic() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (client_bound_sender, client_bound_receiver) = mpsc::channel();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        let connection = thread::spawn(move || handle_client(1, server, client_bound_receiver, server_bound_sender, Duration::from_secs(30)).is_ok());
+
+        for i in 0..1000 {
+            send_event(&mut client, ServerBound::Chat(format!("message {}", i))).unwrap();
+            client_bound_sender.send(ClientBound::PotTotal(i)).unwrap();
+        }
+        for _ in 0..1000 {
+            assert!(matches!(server_bound_receiver.recv_timeout(Duration::from_secs(5)), Ok((1, ServerBound::Chat(_)))));
+        }
+        let mut reader = PacketReader::new();
+        let mut received = 0;
+        let mut buffer = [0u8; 1024];
+        while received < 1000 {
+            let n = client.read(&mut buffer).unwrap();
+            received += reader.feed(&buffer[..n]).unwrap().len();
+        }
+
+        assert_eq!(received, 1000);
+        assert!(reader.packet.is_empty());
+        assert!(client.incoming.lock().unwrap().is_empty());
+        assert!(client.outgoing.lock().unwrap().is_empty());
+        assert!(server_bound_receiver.try_recv().is_err());
+        drop(client_bound_sender);
+        assert!(connection.join().unwrap());
+    }
+
+    // a client that never answers gets one ping halfway through the timeout and is dropped at the end of it
+    #[test]
+    fn quiet_clients_are_pinged_then_disconnected() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (_client_bound_sender, client_bound_receiver) = mpsc::channel();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        let timeout = Duration::from_millis(200);
+        let started = Instant::now();
+        let connection = thread::spawn(move || handle_client(7, server, client_bound_receiver, server_bound_sender, timeout).is_ok());
+
+        let mut reader = PacketReader::new();
+        let mut buffer = [0u8; 64];
+        let n = client.read(&mut buffer).unwrap();
+        let pinged_after = started.elapsed();
+        let packets = reader.feed(&buffer[..n]).unwrap();
+        assert_eq!(packets.len(), 1);
+        assert!(matches!(decode_client_bound(&packets[0]), Ok(ClientBound::Ping)));
+        assert!(pinged_after >= timeout / 2 && pinged_after < timeout, "{:?}", pinged_after);
+
+        assert!(matches!(server_bound_receiver.recv_timeout(Duration::from_secs(5)), Ok((7, ServerBound::Disconnect))));
+        assert!(started.elapsed() >= timeout);
+        assert!(connection.join().unwrap());
+    }
+
+    #[test]
+    fn pings_are_answered_by_the_connection() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (_client_bound_sender, client_bound_receiver) = mpsc::channel();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        thread::spawn(move || handle_client(7, server, client_bound_receiver, server_bound_sender, Duration::from_secs(30)).is_ok());
+
+        send_event(&mut client, ServerBound::Ping).unwrap();
+        let mut reader = PacketReader::new();
+        let mut buffer = [0u8; 64];
+        let n = client.read(&mut buffer).unwrap();
+        let packets = reader.feed(&buffer[..n]).unwrap();
+        assert!(matches!(decode_client_bound(&packets[0]), Ok(ClientBound::Pong)));
+        // the server itself never hears about it
+        assert!(server_bound_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn a_closed_connection_ends_the_client_loop() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (sender, receiver) = mpsc::channel();
+        drop(server);
+        // the first read gets 0 bytes
+        assert!(client_network_loop(&mut client, sender).is_ok());
+        assert!(receiver.recv().is_err());
+
+        // whatever came before the close is still delivered
+        let (mut client, mut server) = MemoryTransport::pair();
+        let (sender, receiver) = mpsc::channel();
+        server.write_all(&frame_packet(encode_client_bound(ClientBound::PotTotal(15)))).unwrap();
+        drop(server);
+        assert!(client_network_loop(&mut client, sender).is_ok());
+        assert!(matches!(receiver.recv(), Ok(ClientBound::PotTotal(15))));
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn a_bad_message_is_answered_after_what_was_already_queued() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (client_bound_sender, client_bound_receiver) = mpsc::channel();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        client_bound_sender.send(ClientBound::PotTotal(15)).unwrap();
+        client.write_all(&frame_packet(vec![255])).unwrap();
+        thread::spawn(move || handle_client(3, server, client_bound_receiver, server_bound_sender, Duration::from_secs(30)).is_ok());
+
+        let mut reader = PacketReader::new();
+        let mut packets = Vec::new();
+        let mut buffer = [0u8; 256];
+        while packets.len() < 2 {
+            let n = client.read(&mut buffer).unwrap();
+            packets.extend(reader.feed(&buffer[..n]).unwrap());
+        }
+        assert!(matches!(decode_client_bound(&packets[0]), Ok(ClientBound::PotTotal(15))));
+        assert!(matches!(decode_client_bound(&packets[1]), Ok(ClientBound::Error { code: 202, .. })));
+        // a bad message doesn't end the connection or reach the server
+        assert!(server_bound_receiver.try_recv().is_err());
+        drop(client_bound_sender);
+    }
+
+    #[test]
+    fn a_bad_message_from_a_closed_connection_still_disconnects() {
+        let (mut client, server) = MemoryTransport::pair();
+        let (_client_bound_sender, client_bound_receiver) = mpsc::channel();
+        let (server_bound_sender, server_bound_receiver) = mpsc::channel();
+        client.write_all(&frame_packet(vec![255])).unwrap();
+        drop(client);
+        // the message is still read, it's the error going back that fails
+        assert!(handle_client(3, server, client_bound_receiver, server_bound_sender, Duration::from_secs(30)).is_ok());
+        assert!(matches!(server_bound_receiver.try_recv(), Ok((3, ServerBound::Disconnect))));
+    }
+}
@@ -1,51 +1,215 @@
-use crate::{cards::{Card, HandCategory, HandRank, ShowdownDecidingFactor}, events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound}, game::{Pot, ShowdownStep}};
+use std::fmt::Display;
+
+use crate::{cards::{Card, DeckKind, HandCategory, HandRank, ShowdownDecidingFactor}, events::{ClientBound, GameEvent, GamePlayerAction, PlayerState, ServerBound}, game::{Pot, ShowdownStep}};
+
+// bump this with every change to the wire format
+pub const PROTOCOL_VERSION: u16 = 26;
+
+// in bytes, longer chat messages don't decode
+pub const MAX_CHAT_LENGTH: usize = 200;
+
+pub const MAX_USERNAME_LENGTH: usize = 16;
+
+// the server checks this on every login, the client only to avoid a round trip
+pub fn validate_username(username: &str) -> Result<(), &'static str> {
+    if !username.is_ascii() {
+        return Err("Usernames can only contain ASCII characters!");
+    }
+    if username.chars().any(|c| c.is_ascii_whitespace() || c.is_ascii_control()) {
+        return Err("Usernames can't contain spaces!");
+    }
+    if username.len() < 3 {
+        return Err("Usernames have to have at least 3 characters!");
+    }
+    if username.len() > MAX_USERNAME_LENGTH {
+        return Err("Usernames can't have more than 16 characters!");
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    TooShort, // shorter than the message's fixed size, or empty
+    TooLong, // longer than the message's fixed size, or a chat message over the limit
+    UnknownOpcode(u8, u8), // a byte that should pick a message or a variant but doesn't, and where in the message it was
+    BadUtf8,
+    BadCard(u8),
+    Truncated, // a list or number that runs past the end of the message
+    BadPotRange(u8, u8), // a showdown step whose pots end before they start
+    BadValue(u8, u8), // a byte outside what its field can hold, like a player state or hand category, and where in the message it was
+}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::TooShort => write!(f, "the message is too short"),
+            DecodeError::TooLong => write!(f, "the message is too long"),
+            DecodeError::UnknownOpcode(byte, idx) => write!(f, "unknown opcode or variant {} at byte {}", byte, idx),
+            DecodeError::BadUtf8 => write!(f, "a string isn't valid UTF-8"),
+            DecodeError::BadCard(byte) => write!(f, "{} isn't a card", byte),
+            DecodeError::Truncated => write!(f, "the message ends in the middle of a field"),
+            DecodeError::BadPotRange(start, end) => write!(f, "pots {} to {} aren't a range", start, end),
+            DecodeError::BadValue(byte, idx) => write!(f, "{} at byte {} isn't a value that field can have", byte, idx),
+        }
+    }
+}
+impl std::error::Error for DecodeError {}
+impl DecodeError {
+    // what ClientBound::Error carries, action errors are in the 100s
+    pub fn code(&self) -> u16 {
+        match self {
+            DecodeError::TooShort => 200,
+            DecodeError::TooLong => 201,
+            DecodeError::UnknownOpcode(..) => 202,
+            DecodeError::BadUtf8 => 203,
+            DecodeError::BadCard(_) => 204,
+            DecodeError::Truncated => 205,
+            DecodeError::BadPotRange(..) => 206,
+            DecodeError::BadValue(..) => 207,
+        }
+    }
+}
 
 pub fn encode_server_bound(event: ServerBound) -> Vec<u8> {
     match event {
-        ServerBound::Login(username) => append_username(vec![0], username),
+        ServerBound::Login { username, buy_in } => append_string(append_money(vec![0], buy_in.unwrap_or(0)), username), // 0 for no buy-in
         ServerBound::Disconnect => vec![1],
         ServerBound::Ready(ready) => vec![2, if ready {1} else {0}],
         ServerBound::GetPlayerList => vec![3],
         ServerBound::GameAction(action) => match action {
             GamePlayerAction::Check => vec![4],
             GamePlayerAction::AddMoney(money) => append_money(vec![5], money),
-            GamePlayerAction::Fold => vec![6]
-        }
+            GamePlayerAction::Fold => vec![6],
+            GamePlayerAction::Muck => vec![16],
+            GamePlayerAction::Show => vec![17],
+            GamePlayerAction::Call => vec![18],
+            GamePlayerAction::RunItTwice(agree) => vec![22, if agree {1} else {0}],
+            GamePlayerAction::Bet(money) => append_money(vec![23], money),
+            GamePlayerAction::Raise(money) => append_money(vec![24], money),
+        },
+        ServerBound::Hello(version) => [vec![7], version.to_le_bytes().to_vec()].concat(),
+        ServerBound::Ping => vec![8],
+        ServerBound::Pong => vec![9],
+        ServerBound::Chat(message) => append_string(vec![10], message),
+        ServerBound::Spectate => vec![11],
+        ServerBound::Reconnect(token) => [vec![12], token.to_le_bytes().to_vec()].concat(),
+        ServerBound::JoinRoom(room) => [vec![13], room.to_le_bytes().to_vec()].concat(),
+        ServerBound::CreateRoom => vec![14],
+        ServerBound::Admin(command) => append_string(vec![15], command),
+        ServerBound::Rebuy(money) => append_money(vec![19], money),
+        ServerBound::SitOut(sitting_out) => vec![20, if sitting_out {1} else {0}],
+        ServerBound::RequestGameState => vec![21],
     }
 }
 
-pub fn decode_server_bound(msg: &Vec<u8>) -> Option<ServerBound> {
-    if msg.is_empty() { return None }
-    match msg[0] {
+// both decoders take bytes straight off the socket and have to return an error for anything, never panic.
+// every index into msg is either behind expect_len or a length check, or goes through msg.get and the read_/decode_ helpers,
+// which all return Truncated instead of reading past the end
+pub fn decode_server_bound(msg: &[u8]) -> Result<ServerBound, DecodeError> {
+    let &opcode = msg.first().ok_or(DecodeError::TooShort)?;
+    match opcode {
         0 => {
-            if msg.len() < 3 { return None }
-            Some(ServerBound::Login(String::from_utf8(msg[1..].to_vec()).ok()?))
+            if msg.len() < 7 { return Err(DecodeError::TooShort) }
+            let buy_in = read_u32(msg, 1)?;
+            Ok(ServerBound::Login { username: decode_string(&msg[5..])?, buy_in: (buy_in > 0).then_some(buy_in) })
         },
         1 => {
-            if msg.len() != 1 { return None }
-            Some(ServerBound::Disconnect)
+            expect_len(msg, 1)?;
+            Ok(ServerBound::Disconnect)
         },
         2 => {
-            if msg.len() != 2 { return None }
-            Some(ServerBound::Ready(msg[1] != 0))
+            expect_len(msg, 2)?;
+            Ok(ServerBound::Ready(msg[1] != 0))
         }
         3 => {
-            if msg.len() != 1 { return None }
-            Some(ServerBound::GetPlayerList)
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GetPlayerList)
         },
         4 => {
-            if msg.len() != 1 { return None }
-            Some(ServerBound::GameAction(GamePlayerAction::Check))
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Check))
         },
         5 => {
-            if msg.len() != 5 { return None }
-            Some(ServerBound::GameAction(GamePlayerAction::AddMoney(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]]))))
+            expect_len(msg, 5)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::AddMoney(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]]))))
         },
         6 => {
-            if msg.len() != 1 { return None }
-            Some(ServerBound::GameAction(GamePlayerAction::Fold))
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Fold))
+        },
+        7 => {
+            expect_len(msg, 3)?;
+            Ok(ServerBound::Hello(u16::from_le_bytes([msg[1], msg[2]])))
+        },
+        8 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::Ping)
+        },
+        9 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::Pong)
+        },
+        10 => {
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            if msg.len() - 1 > MAX_CHAT_LENGTH { return Err(DecodeError::TooLong) }
+            Ok(ServerBound::Chat(decode_string(&msg[1..])?))
+        },
+        11 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::Spectate)
+        },
+        12 => {
+            expect_len(msg, 9)?;
+            Ok(ServerBound::Reconnect(read_u64(msg, 1)?))
         },
-        _ => None
+        13 => {
+            expect_len(msg, 5)?;
+            Ok(ServerBound::JoinRoom(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]])))
+        },
+        14 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::CreateRoom)
+        },
+        15 => {
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            Ok(ServerBound::Admin(decode_string(&msg[1..])?))
+        },
+        16 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Muck))
+        },
+        17 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Show))
+        },
+        18 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Call))
+        },
+        19 => {
+            expect_len(msg, 5)?;
+            Ok(ServerBound::Rebuy(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]])))
+        },
+        20 => {
+            expect_len(msg, 2)?;
+            Ok(ServerBound::SitOut(decode_bool(msg, 1)?))
+        },
+        21 => {
+            expect_len(msg, 1)?;
+            Ok(ServerBound::RequestGameState)
+        },
+        22 => {
+            expect_len(msg, 2)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::RunItTwice(decode_bool(msg, 1)?)))
+        },
+        23 => {
+            expect_len(msg, 5)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Bet(read_u32(msg, 1)?)))
+        },
+        24 => {
+            expect_len(msg, 5)?;
+            Ok(ServerBound::GameAction(GamePlayerAction::Raise(read_u32(msg, 1)?)))
+        },
+        _ => Err(DecodeError::UnknownOpcode(opcode, 0))
     }
 }
 
@@ -54,20 +218,26 @@ pub fn encode_client_bound(event: ClientBound) -> Vec<u8> {
         ClientBound::UpdatePlayerList(players) => {
             let mut msg = vec![0];
             for (player_state, money, username) in players {
-                msg.extend(append_username(append_money(vec![player_state as u8], money), username));
+                msg.extend(append_string(append_money(vec![player_state as u8], money), username));
                 msg.push(255);
             }
             msg
         },
         ClientBound::YourIndex(id) => vec![1, id],
-        ClientBound::PlayerLeft(username) => append_username(vec![2], username),
-        ClientBound::PlayerJoined(username) => append_username(vec![3], username),
+        ClientBound::PlayerLeft(username) => append_string(vec![2], username),
+        ClientBound::PlayerJoined(username) => append_string(vec![3], username),
         ClientBound::GameStarted(cards) => vec![4, cards[0].to_byte(), cards[1].to_byte()],
         ClientBound::GameEvent(game_event) => match game_event {
             GameEvent::PlayerAction(player, action) => match action {
                 GamePlayerAction::Check => vec![5, player],
                 GamePlayerAction::AddMoney(money) => append_money(vec![6, player], money),
-                GamePlayerAction::Fold => vec![7, player]
+                GamePlayerAction::Fold => vec![7, player],
+                GamePlayerAction::Muck => vec![23, player],
+                GamePlayerAction::Show => vec![24, player],
+                GamePlayerAction::Call => vec![27, player],
+                GamePlayerAction::RunItTwice(agree) => vec![34, player, if agree {1} else {0}],
+                GamePlayerAction::Bet(money) => append_money(vec![37, player], money),
+                GamePlayerAction::Raise(money) => append_money(vec![38, player], money),
             },
             GameEvent::OwnedMoneyChange(player, money) => append_money(vec![8, player], money),
             GameEvent::NextPlayer(player) => vec![9, player],
@@ -84,9 +254,16 @@ pub fn encode_client_bound(event: ClientBound) -> Vec<u8> {
             GameEvent::RevealFlop(cards) => vec![12, cards[0].to_byte(), cards[1].to_byte(), cards[2].to_byte()],
             GameEvent::RevealTurn(card) => vec![13, card.to_byte()],
             GameEvent::RevealRiver(card) => vec![14, card.to_byte()],
-            GameEvent::Showdown((hand_ranks, steps)) => {
+            GameEvent::Showdown((hand_ranks, steps, mut reveal_order)) => {
                 let mut msg = vec![15];
-                for (private_cards, hand_cards, hand_rank) in hand_ranks {
+                msg.append(&mut reveal_order);
+                msg.push(255);
+                for hand in hand_ranks {
+                    // 254 can't be a category, it stands in for a mucked hand
+                    let Some((private_cards, hand_cards, hand_rank)) = hand else {
+                        msg.push(254);
+                        continue;
+                    };
                     msg.push(hand_rank.category as u8);
                     msg.append(&mut private_cards.iter().map(|c| c.to_byte()).collect());
                     msg.append(&mut hand_cards.iter().map(|c| c.to_byte()).collect());
@@ -100,6 +277,7 @@ pub fn encode_client_bound(event: ClientBound) -> Vec<u8> {
                     msg.append(&mut step.winners);
                     msg.push(255);
                     msg.append(&mut step.winnings.to_le_bytes().to_vec());
+                    msg.append(&mut step.rake_taken.to_le_bytes().to_vec());
                     msg.push(step.pot_start_index);
                     msg.push(step.pot_end_index);
                     msg.append(&mut step.eligible_players);
@@ -119,138 +297,289 @@ pub fn encode_client_bound(event: ClientBound) -> Vec<u8> {
                 }
                 msg
             },
-            GameEvent::InGamePlayerLeave(id) => vec![16, id]
-        }
+            GameEvent::InGamePlayerLeave(id) => vec![16, id],
+            GameEvent::WonUncontested(seat, winnings) => append_money(vec![26, seat], winnings),
+            GameEvent::PlayerAllIn(seat) => vec![39, seat],
+            GameEvent::MuckOrShow(mut seats) => {
+                let mut msg = vec![25];
+                msg.append(&mut seats);
+                msg
+            },
+            GameEvent::RunItTwiceOffer(mut seats) => {
+                let mut msg = vec![35];
+                msg.append(&mut seats);
+                msg
+            },
+            GameEvent::RevealSecondBoard(cards) => [vec![36], cards.iter().map(|c| c.to_byte()).collect()].concat(),
+        },
+        ClientBound::Welcome { version, accepted } => [vec![17], version.to_le_bytes().to_vec(), vec![accepted as u8]].concat(),
+        ClientBound::Ping => vec![18],
+        ClientBound::Pong => vec![19],
+        ClientBound::ChatBroadcast { player, message } => append_string(vec![20, player], message),
+        ClientBound::SessionToken(token) => [vec![21], token.to_le_bytes().to_vec()].concat(),
+        ClientBound::RoomCreated(room) => [vec![22], room.to_le_bytes().to_vec()].concat(),
+        ClientBound::LoginRejected(reason) => append_string(vec![28], reason),
+        ClientBound::ServerShutdown => vec![29],
+        ClientBound::RebuyResult { accepted, money } => append_money(vec![30, if accepted {1} else {0}], money),
+        ClientBound::Countdown(secs) => vec![32, secs],
+        ClientBound::TimeBankUsed { player, remaining } => append_money(vec![33, player], remaining),
+        ClientBound::PotTotal(money) => append_money(vec![40], money),
+        ClientBound::ServerFull => vec![41],
+        ClientBound::Error { code, message } => append_string(vec![42, code as u8, (code >> 8) as u8], message),
+        ClientBound::YourTurn { to_call, min_raise, can_check } => append_money(append_money(vec![31, if can_check {1} else {0}], to_call), min_raise),
     }
 }
 
-pub fn decode_client_bound(msg: &Vec<u8>) -> Option<ClientBound> {
-    if msg.is_empty() { return None }
-    match msg[0] {
+pub fn decode_client_bound(msg: &[u8]) -> Result<ClientBound, DecodeError> {
+    let &opcode = msg.first().ok_or(DecodeError::TooShort)?;
+    match opcode {
         0 => {
             let mut players = Vec::new();
             let mut idx = 1;
             while idx < msg.len() {
-                if idx + 5 >= msg.len() { return None }
-                let player_state = PlayerState::from_byte(msg[idx])?;
-                let money = u32::from_le_bytes(msg.get(idx+1..idx+5)?.try_into().ok()?);
+                if idx + 5 >= msg.len() { return Err(DecodeError::Truncated) }
+                let player_state = PlayerState::from_byte(msg[idx]).ok_or(DecodeError::BadValue(msg[idx], idx as u8))?;
+                let money = read_u32(msg, idx + 1)?;
                 idx += 5;
-                let username = String::from_utf8(decode_byte_list(msg, &mut idx)?).ok()?;
+                let username = decode_string(&decode_byte_list(msg, &mut idx)?)?;
                 players.push((player_state, money, username));
             }
-            Some(ClientBound::UpdatePlayerList(players))
+            Ok(ClientBound::UpdatePlayerList(players))
         },
         1 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::YourIndex(msg[1]))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::YourIndex(msg[1]))
         },
         2 => {
-            if msg.len() < 2 { return None }
-            Some(ClientBound::PlayerLeft(String::from_utf8(msg[1..].to_vec()).ok()?))
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::PlayerLeft(decode_string(&msg[1..])?))
         },
         3 => {
-            if msg.len() < 2 { return None }
-            Some(ClientBound::PlayerJoined(String::from_utf8(msg[1..].to_vec()).ok()?))
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::PlayerJoined(decode_string(&msg[1..])?))
         },
         4 => {
-            if msg.len() != 3 { return None }
-            Some(ClientBound::GameStarted([Card::from_byte(msg[1])?, Card::from_byte(msg[2])?]))
+            expect_len(msg, 3)?;
+            Ok(ClientBound::GameStarted([decode_card(msg[1])?, decode_card(msg[2])?]))
         },
         5 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Check)))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Check)))
         },
         6 => {
-            if msg.len() != 6 { return None }
-            Some(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::AddMoney(u32::from_le_bytes(msg.get(2..)?.try_into().ok()?)))))
+            expect_len(msg, 6)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::AddMoney(read_u32(msg, 2)?))))
         },
         7 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Fold)))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Fold)))
         },
         8 => {
-            if msg.len() < 6 { return None }
+            expect_len(msg, 6)?;
             let player = msg[1];
-            let money = u32::from_le_bytes(msg.get(2..6)?.try_into().ok()?);
-            Some(ClientBound::GameEvent(GameEvent::OwnedMoneyChange(player, money)))
+            let money = read_u32(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::OwnedMoneyChange(player, money)))
         },
         9 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::NextPlayer(msg[1])))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::NextPlayer(msg[1])))
         },
         10 => {
-            if msg.len() != 5 { return None }
-            Some(ClientBound::GameEvent(GameEvent::UpdateCurrentBet(u32::from_le_bytes(msg.get(1..)?.try_into().ok()?))))
+            expect_len(msg, 5)?;
+            Ok(ClientBound::GameEvent(GameEvent::UpdateCurrentBet(read_u32(msg, 1)?)))
         },
         11 => {
             let mut pots = Vec::new();
             let mut idx = 1;
             while idx < msg.len() {
-                if idx + 4 >= msg.len() { return None }
-                let money = u32::from_le_bytes([msg[idx], msg[idx+1], msg[idx+2], msg[idx+3]]);
+                if idx + 4 >= msg.len() { return Err(DecodeError::Truncated) }
+                let money = read_u32(msg, idx)?;
                 idx += 4;
                 let eligible_players = decode_byte_list(msg, &mut idx)?;
                 pots.push(Pot { money, eligible_players });
             }
-            Some(ClientBound::GameEvent(GameEvent::UpdatePots(pots)))
+            Ok(ClientBound::GameEvent(GameEvent::UpdatePots(pots)))
         },
         12 => {
-            if msg.len() != 4 { return None }
-            Some(ClientBound::GameEvent(GameEvent::RevealFlop([Card::from_byte(msg[1])?, Card::from_byte(msg[2])?, Card::from_byte(msg[3])?])))
+            expect_len(msg, 4)?;
+            Ok(ClientBound::GameEvent(GameEvent::RevealFlop([decode_card(msg[1])?, decode_card(msg[2])?, decode_card(msg[3])?])))
         },
         13 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::RevealTurn(Card::from_byte(msg[1])?)))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::RevealTurn(decode_card(msg[1])?)))
         },
         14 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::RevealRiver(Card::from_byte(msg[1])?)))
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::RevealRiver(decode_card(msg[1])?)))
         },
         15 => {
             let mut hand_ranks = Vec::new();
             let mut idx = 1;
+            let reveal_order = decode_byte_list(msg, &mut idx)?;
             while idx < msg.len() && msg[idx] != 255 {
-                if idx + 8 >= msg.len() { return None }
-                let category = msg[idx];
-                let private_cards = [Card::from_byte(msg[idx+1])?, Card::from_byte(msg[idx+2])?];
-                let hand_cards = [Card::from_byte(msg[idx+3])?, Card::from_byte(msg[idx+4])?, Card::from_byte(msg[idx+5])?, Card::from_byte(msg[idx+6])?, Card::from_byte(msg[idx+7])?,];
+                if msg[idx] == 254 {
+                    hand_ranks.push(None);
+                    idx += 1;
+                    continue;
+                }
+                if idx + 8 >= msg.len() { return Err(DecodeError::Truncated) }
+                let category = HandCategory::from_byte(msg[idx]).ok_or(DecodeError::BadValue(msg[idx], idx as u8))?;
+                let private_cards = [decode_card(msg[idx+1])?, decode_card(msg[idx+2])?];
+                let hand_cards = [decode_card(msg[idx+3])?, decode_card(msg[idx+4])?, decode_card(msg[idx+5])?, decode_card(msg[idx+6])?, decode_card(msg[idx+7])?,];
                 idx += 8;
                 let primary = decode_card_list(msg, &mut idx)?;
                 let secondary = decode_card_list(msg, &mut idx)?;
                 let kickers = decode_card_list(msg, &mut idx)?;
-                let hand_rank = HandRank { category: HandCategory::from_byte(category)?, primary, secondary, kickers };
-                hand_ranks.push((private_cards, hand_cards, hand_rank));
+                let hand_rank = HandRank { category, primary, secondary, kickers, deck: DeckKind::Standard }; // only the server compares hands
+                hand_ranks.push(Some((private_cards, hand_cards, hand_rank)));
             }
+            // the hands have to end in a 255 even when no steps follow
+            if idx >= msg.len() { return Err(DecodeError::Truncated) }
             idx += 1;
 
             let mut steps = Vec::new();
             while idx < msg.len() {
                 let winners = decode_byte_list(msg, &mut idx)?;
-                if idx + 6 >= msg.len() { return None }
-                let winnings = u32::from_le_bytes([msg[idx], msg[idx+1], msg[idx+2], msg[idx+3]]);
-                let pot_start_index = msg[idx+4];
-                let pot_end_index = msg[idx+5];
-                idx += 6;
+                if idx + 10 >= msg.len() { return Err(DecodeError::Truncated) }
+                let winnings = read_u32(msg, idx)?;
+                let rake_taken = read_u32(msg, idx + 4)?;
+                let pot_start_index = msg[idx+8];
+                let pot_end_index = msg[idx+9];
+                // the client prints these one based, so 255 can't be a pot either
+                if pot_start_index > pot_end_index || pot_end_index == 255 { return Err(DecodeError::BadPotRange(pot_start_index, pot_end_index)) }
+                idx += 10;
                 let eligible_players = decode_byte_list(msg, &mut idx)?;
-                let win_reason;
-                match msg[idx] {
-                    255 => {win_reason = None; idx += 4}
-                    0 => {win_reason = Some((ShowdownDecidingFactor::Category, *msg.get(idx+1)?)); idx += 4},
-                    1 => {win_reason = Some((ShowdownDecidingFactor::Primary(decode_card_list(msg, &mut idx)?, decode_card_list(msg, &mut idx)?), *msg.get(idx+1)?)); idx += 1}
-                    2 => {win_reason = Some((ShowdownDecidingFactor::Secondary(decode_card_list(msg, &mut idx)?, decode_card_list(msg, &mut idx)?), *msg.get(idx+1)?)); idx += 1}
-                    3 => {win_reason = Some((ShowdownDecidingFactor::Kicker(decode_card_list(msg, &mut idx)?, decode_card_list(msg, &mut idx)?), *msg.get(idx+1)?)); idx += 1}
-                    4 => {win_reason = Some((ShowdownDecidingFactor::Tie, *msg.get(idx+1)?)); idx += 4}
-                    _ => return None,
+                // the tag, two card lists that are empty for the tags without cards, then the player, all 255 without a reason
+                let tag_idx = idx;
+                let tag = *msg.get(idx).ok_or(DecodeError::Truncated)?;
+                idx += 1;
+                let (cards1, cards2) = (decode_card_list(msg, &mut idx)?, decode_card_list(msg, &mut idx)?);
+                let player = *msg.get(idx).ok_or(DecodeError::Truncated)?;
+                idx += 1;
+                let win_reason = match tag {
+                    255 => None,
+                    0 => Some((ShowdownDecidingFactor::Category, player)),
+                    1 => Some((ShowdownDecidingFactor::Primary(cards1, cards2), player)),
+                    2 => Some((ShowdownDecidingFactor::Secondary(cards1, cards2), player)),
+                    3 => Some((ShowdownDecidingFactor::Kicker(cards1, cards2), player)),
+                    4 => Some((ShowdownDecidingFactor::Tie, player)),
+                    _ => return Err(DecodeError::UnknownOpcode(tag, tag_idx as u8)),
                 };
-                steps.push(ShowdownStep { winners, winnings, pot_start_index, pot_end_index, eligible_players, win_reason });
+                steps.push(ShowdownStep { winners, winnings, rake_taken, pot_start_index, pot_end_index, eligible_players, win_reason });
             }
-            Some(ClientBound::GameEvent(GameEvent::Showdown((hand_ranks, steps))))
+            Ok(ClientBound::GameEvent(GameEvent::Showdown((hand_ranks, steps, reveal_order))))
         },
         16 => {
-            if msg.len() != 2 { return None }
-            Some(ClientBound::GameEvent(GameEvent::InGamePlayerLeave(msg[1])))
-        }
-        _ => None,
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::InGamePlayerLeave(msg[1])))
+        },
+        17 => {
+            expect_len(msg, 4)?;
+            Ok(ClientBound::Welcome { version: u16::from_le_bytes([msg[1], msg[2]]), accepted: msg[3] != 0 })
+        },
+        18 => {
+            expect_len(msg, 1)?;
+            Ok(ClientBound::Ping)
+        },
+        19 => {
+            expect_len(msg, 1)?;
+            Ok(ClientBound::Pong)
+        },
+        20 => {
+            if msg.len() < 3 { return Err(DecodeError::TooShort) }
+            if msg.len() - 2 > MAX_CHAT_LENGTH { return Err(DecodeError::TooLong) }
+            Ok(ClientBound::ChatBroadcast { player: msg[1], message: decode_string(&msg[2..])? })
+        },
+        21 => {
+            expect_len(msg, 9)?;
+            Ok(ClientBound::SessionToken(read_u64(msg, 1)?))
+        },
+        22 => {
+            expect_len(msg, 5)?;
+            Ok(ClientBound::RoomCreated(u32::from_le_bytes([msg[1], msg[2], msg[3], msg[4]])))
+        },
+        23 => {
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Muck)))
+        },
+        24 => {
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Show)))
+        },
+        25 => {
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::GameEvent(GameEvent::MuckOrShow(msg[1..].to_vec())))
+        },
+        26 => {
+            expect_len(msg, 6)?;
+            Ok(ClientBound::GameEvent(GameEvent::WonUncontested(msg[1], read_u32(msg, 2)?)))
+        },
+        27 => {
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Call)))
+        },
+        28 => {
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::LoginRejected(decode_string(&msg[1..])?))
+        },
+        29 => {
+            expect_len(msg, 1)?;
+            Ok(ClientBound::ServerShutdown)
+        },
+        30 => {
+            expect_len(msg, 6)?;
+            Ok(ClientBound::RebuyResult { accepted: decode_bool(msg, 1)?, money: read_u32(msg, 2)? })
+        },
+        31 => {
+            expect_len(msg, 10)?;
+            Ok(ClientBound::YourTurn { can_check: decode_bool(msg, 1)?, to_call: read_u32(msg, 2)?, min_raise: read_u32(msg, 6)? })
+        },
+        32 => {
+            expect_len(msg, 2)?;
+            Ok(ClientBound::Countdown(msg[1]))
+        },
+        33 => {
+            expect_len(msg, 6)?;
+            Ok(ClientBound::TimeBankUsed { player: msg[1], remaining: read_u32(msg, 2)? })
+        },
+        34 => {
+            expect_len(msg, 3)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::RunItTwice(decode_bool(msg, 2)?))))
+        },
+        35 => {
+            if msg.len() < 3 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::GameEvent(GameEvent::RunItTwiceOffer(msg[1..].to_vec())))
+        },
+        36 => {
+            if msg.len() < 2 { return Err(DecodeError::TooShort) }
+            if msg.len() > 6 { return Err(DecodeError::TooLong) }
+            Ok(ClientBound::GameEvent(GameEvent::RevealSecondBoard(msg[1..].iter().map(|&byte| decode_card(byte)).collect::<Result<_, _>>()?)))
+        },
+        37 => {
+            expect_len(msg, 6)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Bet(read_u32(msg, 2)?))))
+        },
+        38 => {
+            expect_len(msg, 6)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAction(msg[1], GamePlayerAction::Raise(read_u32(msg, 2)?))))
+        },
+        39 => {
+            expect_len(msg, 2)?;
+            Ok(ClientBound::GameEvent(GameEvent::PlayerAllIn(msg[1])))
+        },
+        40 => {
+            expect_len(msg, 5)?;
+            Ok(ClientBound::PotTotal(read_u32(msg, 1)?))
+        },
+        41 => {
+            expect_len(msg, 1)?;
+            Ok(ClientBound::ServerFull)
+        },
+        42 => {
+            if msg.len() < 3 { return Err(DecodeError::TooShort) }
+            Ok(ClientBound::Error { code: u16::from_le_bytes([msg[1], msg[2]]), message: decode_string(&msg[3..])? })
+        },
+        _ => Err(DecodeError::UnknownOpcode(opcode, 0)),
     }
 }
 
@@ -259,8 +588,8 @@ fn append_money(mut msg: Vec<u8>, money: u32) -> Vec<u8> {
     msg
 }
 
-fn append_username(mut msg: Vec<u8>, username: String) -> Vec<u8> {
-    msg.append(&mut username.as_bytes().to_vec());
+fn append_string(mut msg: Vec<u8>, string: String) -> Vec<u8> {
+    msg.append(&mut string.as_bytes().to_vec());
     msg
 }
 
@@ -280,20 +609,169 @@ fn encode_showdown_deciding_factor(id: u8, cards1: Vec<Card>, cards2: Vec<Card>)
     part
 }
 
-fn decode_byte_list(msg: &Vec<u8>, idx: &mut usize) -> Option<Vec<u8>> {
+// a list that runs past the end of the message is truncated
+fn decode_byte_list(msg: &[u8], idx: &mut usize) -> Result<Vec<u8>, DecodeError> {
     let mut bytes = Vec::new();
-    while *msg.get(*idx)? != 255 {
+    while *msg.get(*idx).ok_or(DecodeError::Truncated)? != 255 {
         bytes.push(msg[*idx]);
         *idx += 1;
     }
     *idx += 1;
-    Some(bytes)
+    Ok(bytes)
+}
+
+fn decode_card_list(msg: &[u8], idx: &mut usize) -> Result<Vec<Card>, DecodeError> {
+    decode_byte_list(msg, idx)?.into_iter().map(decode_card).collect()
+}
+
+fn decode_card(byte: u8) -> Result<Card, DecodeError> {
+    Card::from_byte(byte).ok_or(DecodeError::BadCard(byte))
 }
 
-fn decode_card_list(msg: &Vec<u8>, idx: &mut usize) -> Option<Vec<Card>> {
-    let mut list = Vec::new();
-    for byte in decode_byte_list(msg, idx)? {
-        list.push(Card::from_byte(byte)?);
+fn decode_string(bytes: &[u8]) -> Result<String, DecodeError> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::BadUtf8)
+}
+
+// bools only come as 0 or 1
+fn decode_bool(msg: &[u8], idx: usize) -> Result<bool, DecodeError> {
+    match msg.get(idx) {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        Some(&byte) => Err(DecodeError::BadValue(byte, idx as u8)),
+        None => Err(DecodeError::TooShort),
+    }
+}
+
+fn read_u32(msg: &[u8], idx: usize) -> Result<u32, DecodeError> {
+    Ok(u32::from_le_bytes(msg.get(idx..idx + 4).ok_or(DecodeError::Truncated)?.try_into().unwrap()))
+}
+
+fn read_u64(msg: &[u8], idx: usize) -> Result<u64, DecodeError> {
+    Ok(u64::from_le_bytes(msg.get(idx..idx + 8).ok_or(DecodeError::Truncated)?.try_into().unwrap()))
+}
+
+// fixed size messages have to be exactly their size
+fn expect_len(msg: &[u8], len: usize) -> Result<(), DecodeError> {
+    match msg.len() {
+        n if n < len => Err(DecodeError::TooShort),
+        n if n > len => Err(DecodeError::TooLong),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::samples;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    #[test]
+    fn in_game_leave_round_trips() {
+        let encoded = encode_client_bound(ClientBound::GameEvent(GameEvent::InGamePlayerLeave(3)));
+        assert!(matches!(decode_client_bound(&encoded), Ok(ClientBound::GameEvent(GameEvent::InGamePlayerLeave(3)))));
+    }
+
+    // what advance_game hands the server goes out through encode_client_bound, and has to come back as the same message
+    #[test]
+    fn game_events_round_trip() {
+        for event in samples::game_events() {
+            let encoded = encode_client_bound(ClientBound::GameEvent(event.clone()));
+            let decoded = decode_client_bound(&encoded).unwrap_or_else(|e| panic!("{:?} doesn't decode: {}", event, e));
+            assert_eq!(encode_client_bound(decoded), encoded, "{:?}", event);
+        }
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for event in samples::client_bound() {
+            let encoded = encode_client_bound(event.clone());
+            let decoded = decode_client_bound(&encoded).unwrap_or_else(|e| panic!("{:?} doesn't decode: {}", event, e));
+            assert_eq!(encode_client_bound(decoded), encoded, "{:?}", event);
+        }
+        for event in samples::server_bound() {
+            let encoded = encode_server_bound(event.clone());
+            let decoded = decode_server_bound(&encoded).unwrap_or_else(|e| panic!("{:?} doesn't decode: {}", event, e));
+            assert_eq!(encode_server_bound(decoded), encoded, "{:?}", event);
+        }
+    }
+
+    // whatever a decoder accepts has to encode again, and nothing it's given may panic it
+    fn decode_anything(msg: &[u8]) {
+        if let Ok(event) = decode_client_bound(msg) {
+            encode_client_bound(event);
+        }
+        if let Ok(event) = decode_server_bound(msg) {
+            encode_server_bound(event);
+        }
+    }
+
+    #[test]
+    fn decoders_never_panic() {
+        let mut rng = StdRng::seed_from_u64(340);
+        for _ in 0..100_000 {
+            let len = rng.gen_range(0..64);
+            let msg: Vec<u8> = (0..len).map(|_| rng.r#gen()).collect();
+            decode_anything(&msg);
+        }
+
+        // random bytes rarely get past the opcode, valid messages with a few bytes changed get a lot further
+        let valid: Vec<Vec<u8>> = samples::client_bound().into_iter().map(encode_client_bound).chain(samples::server_bound().into_iter().map(encode_server_bound)).collect();
+        for _ in 0..200 {
+            for msg in &valid {
+                let mut msg = msg.clone();
+                for _ in 0..rng.gen_range(1..4) {
+                    let idx = rng.gen_range(0..msg.len());
+                    match rng.gen_range(0..3) {
+                        0 => msg[idx] = rng.r#gen(),
+                        1 => msg.truncate(idx),
+                        _ => msg.insert(idx, rng.r#gen()),
+                    }
+                    if msg.is_empty() {
+                        break;
+                    }
+                }
+                decode_anything(&msg);
+            }
+        }
+    }
+
+    #[test]
+    fn bad_values_are_told_apart_from_unknown_opcodes() {
+        // the first player's state comes right after the opcode
+        let mut list = encode_client_bound(ClientBound::UpdatePlayerList(vec![(PlayerState::Ready, 1000, "alice".to_string())]));
+        list[1] = 9;
+        assert_eq!(decode_client_bound(&list).err(), Some(DecodeError::BadValue(9, 1)));
+        let mut sit_out = encode_server_bound(ServerBound::SitOut(true));
+        sit_out[1] = 2;
+        assert_eq!(decode_server_bound(&sit_out).err(), Some(DecodeError::BadValue(2, 1)));
+        assert_eq!(decode_client_bound(&[200]).err(), Some(DecodeError::UnknownOpcode(200, 0)));
+    }
+
+    #[test]
+    fn every_decode_error_has_a_case() {
+        let mut too_long_chat = vec![10];
+        too_long_chat.extend([b'a'; MAX_CHAT_LENGTH + 1]);
+        let mut bad_card = encode_client_bound(ClientBound::GameStarted(["As".parse().unwrap(), "Kd".parse().unwrap()]));
+        bad_card[2] = 13; // a rank past the ace
+        let mut truncated = encode_client_bound(ClientBound::UpdatePlayerList(vec![(PlayerState::Ready, 1000, "alice".to_string())]));
+        truncated.truncate(4);
+        let step = ShowdownStep { winners: vec![0], winnings: 30, rake_taken: 0, pot_start_index: 2, pot_end_index: 1, eligible_players: vec![0, 1], win_reason: None };
+        let bad_pots = encode_client_bound(ClientBound::GameEvent(GameEvent::Showdown((vec![None, None], vec![step], vec![0, 1]))));
+
+        let cases = [
+            (decode_server_bound(&[]).err(), DecodeError::TooShort),
+            (decode_server_bound(&[7, 26]).err(), DecodeError::TooShort),
+            (decode_server_bound(&[1, 0]).err(), DecodeError::TooLong),
+            (decode_server_bound(&too_long_chat).err(), DecodeError::TooLong),
+            (decode_server_bound(&[250]).err(), DecodeError::UnknownOpcode(250, 0)),
+            (decode_server_bound(&[10, b'h', 0xff]).err(), DecodeError::BadUtf8),
+            (decode_client_bound(&bad_card).err(), DecodeError::BadCard(13)),
+            (decode_client_bound(&truncated).err(), DecodeError::Truncated),
+            (decode_client_bound(&bad_pots).err(), DecodeError::BadPotRange(2, 1)),
+            (decode_server_bound(&[22, 3]).err(), DecodeError::BadValue(3, 1)),
+        ];
+        for (decoded, expected) in cases {
+            assert_eq!(decoded, Some(expected));
+        }
     }
-    Some(list)
 }
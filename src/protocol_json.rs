@@ -0,0 +1,45 @@
+// the same messages as protocol.rs, as JSON for debugging and web clients
+use crate::events::{ClientBound, ServerBound};
+
+pub fn to_json(event: &ClientBound) -> String {
+    serde_json::to_string(event).expect("ClientBound should always be serializable.")
+}
+
+pub fn from_json(json: &str) -> Option<ClientBound> {
+    serde_json::from_str(json).ok()
+}
+
+pub fn server_bound_to_json(event: &ServerBound) -> String {
+    serde_json::to_string(event).expect("ServerBound should always be serializable.")
+}
+
+pub fn server_bound_from_json(json: &str) -> Option<ServerBound> {
+    serde_json::from_str(json).ok()
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+    use crate::events::samples;
+
+    // none of the types compare, so a round trip has to give the same JSON again
+    #[test]
+    fn every_message_round_trips() {
+        for event in samples::client_bound() {
+            let json = to_json(&event);
+            assert_eq!(from_json(&json).map(|decoded| to_json(&decoded)), Some(json));
+        }
+        for event in samples::server_bound() {
+            let json = server_bound_to_json(&event);
+            assert_eq!(server_bound_from_json(&json).map(|decoded| server_bound_to_json(&decoded)), Some(json));
+        }
+    }
+
+    #[test]
+    fn malformed_cards_are_rejected() {
+        assert!(from_json(r#"{"GameStarted":["As","Td"]}"#).is_some());
+        for cards in [r#"["A","Td"]"#, r#"["As","10d"]"#, r#"["Ax","Td"]"#, r#"["As",""]"#, r#"["As",12]"#] {
+            assert!(from_json(&format!(r#"{{"GameStarted":{}}}"#, cards)).is_none(), "{}", cards);
+        }
+    }
+}
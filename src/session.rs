@@ -0,0 +1,188 @@
+use std::fmt::Display;
+
+use crate::{cards::{Card, all_distinct}, events::{GameEvent, GamePlayerAction}, game::{ActionError, Game, GameConfig, GameSetupError, make_game_seeded, make_game_with_deck}};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+// everything needed to play a hand again: who sat where with how much, the deal, and what everyone did
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HandRecord {
+    pub stacks: Vec<u32>, // indexed by seat, before the hand
+    pub config: GameConfig,
+    pub button: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub seed: Option<u64>,
+    pub private_cards: Vec<[Card; 2]>,
+    pub public_cards: [Card; 5],
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub second_board: Option<[Card; 5]>, // only when the hand was run twice
+    pub actions: Vec<(u8, GamePlayerAction)>, // seat and what they did, in order
+    pub final_stacks: Vec<u32>,
+}
+impl HandRecord {
+    // plays the recorded actions on the recorded deal, none if an action doesn't fit the hand anymore
+    pub fn replay(&self) -> Option<Vec<GameEvent>> {
+        let mut deck: Vec<Card> = self.private_cards.iter().flatten().chain(self.public_cards.iter()).copied().collect();
+        // the second run is dealt from what's left of the deck, in board order
+        if let Some(second_board) = self.second_board {
+            deck.extend(second_board.iter().zip(self.public_cards).filter(|(second, first)| !second.full_eq(first)).map(|(second, _)| *second));
+        }
+        deck.reverse();
+        // a record edited by hand could deal the same card twice
+        if !all_distinct(&deck) {
+            return None;
+        }
+
+        let mut game = make_game_with_deck(self.stacks.clone(), self.config, self.button, deck).ok()?;
+        let mut events = Vec::new();
+        for (seat, action) in &self.actions {
+            events.extend(game.advance_game_as(*seat, action.clone()).ok()?);
+        }
+        // records from before mucking was a thing end without the decisions
+        events.extend(game.muck_remaining());
+        Some(events)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    Setup(GameSetupError),
+    IllegalAction(usize, ActionError), // index into the script of the first action that didn't fit
+}
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReplayError::Setup(e) => write!(f, "couldn't set up the hand: {}", e),
+            ReplayError::IllegalAction(index, e) => write!(f, "action {} of the script is illegal: {}", index, e),
+        }
+    }
+}
+impl std::error::Error for ReplayError {}
+
+// deals the hand the seed deals and plays the script on it, so a seed and a list of actions are enough to reproduce a hand
+pub fn replay(seed: u64, stacks: Vec<u32>, config: GameConfig, button: u8, actions: &[(u8, GamePlayerAction)]) -> Result<Vec<GameEvent>, ReplayError> {
+    let mut game = make_game_seeded(stacks, config, button, seed).map_err(ReplayError::Setup)?;
+    let mut events = game.start_events();
+    for (index, (seat, action)) in actions.iter().enumerate() {
+        events.extend(game.advance_game_as(*seat, action.clone()).map_err(|e| ReplayError::IllegalAction(index, e))?);
+    }
+    events.extend(game.muck_remaining());
+    Ok(events)
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Session {
+    pub hands: Vec<HandRecord>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    current: Option<HandRecord>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_hand(&mut self, game: &Game) {
+        self.current = Some(HandRecord {
+            stacks: game.players.iter().map(|p| p.money + p.total_contribution).collect(),
+            config: game.config,
+            button: game.button,
+            seed: game.seed,
+            private_cards: game.players.iter().map(|p| p.private_cards).collect(),
+            public_cards: game.public_cards,
+            second_board: None,
+            actions: Vec::new(),
+            final_stacks: Vec::new(),
+        });
+    }
+
+    pub fn record_action(&mut self, seat: u8, action: GamePlayerAction) {
+        if let Some(hand) = self.current.as_mut() {
+            hand.actions.push((seat, action));
+        }
+    }
+
+    pub fn end_hand(&mut self, game: &Game) {
+        if let Some(mut hand) = self.current.take() {
+            hand.final_stacks = game.players.iter().map(|p| p.money).collect();
+            hand.second_board = game.second_board();
+            self.hands.push(hand);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn export_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Session should always be serializable.")
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn import_json(json: &str) -> Option<Session> {
+        serde_json::from_str(json).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cards::HandCategory;
+
+    // plays a seeded hand through the recorder, the first few actions from the script and calls after that
+    #[cfg(feature = "serde")]
+    fn record_hand(session: &mut Session, seed: u64, script: &[GamePlayerAction]) {
+        let mut game = make_game_seeded(vec![1000, 800, 1200], GameConfig::default(), 1, seed).unwrap();
+        session.start_hand(&game);
+        let mut actions = script.iter().cloned();
+        loop {
+            let seat = game.current_turn;
+            let action = actions.next().unwrap_or(GamePlayerAction::Call);
+            if game.advance_game(action.clone()).is_err() {
+                break;
+            }
+            session.record_action(seat, action);
+        }
+        game.muck_remaining();
+        session.end_hand(&game);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn exported_hands_replay_the_same() {
+        let mut session = Session::new();
+        record_hand(&mut session, 3, &[]);
+        record_hand(&mut session, 11, &[GamePlayerAction::AddMoney(60), GamePlayerAction::Fold]);
+        let imported = Session::import_json(&session.export_json()).unwrap();
+        assert_eq!(imported.hands.len(), 2);
+        assert!(matches!(imported.hands[1].actions[..2], [(_, GamePlayerAction::AddMoney(60)), (_, GamePlayerAction::Fold)]));
+
+        for (original, hand) in session.hands.iter().zip(&imported.hands) {
+            assert_eq!(hand.final_stacks, original.final_stacks);
+            let from_record = hand.replay().unwrap();
+            let from_seed = replay(hand.seed.unwrap(), hand.stacks.clone(), hand.config, hand.button, &hand.actions).unwrap();
+            // replaying from the seed also has the events that start the hand
+            let start = from_seed.len() - from_record.len();
+            assert_eq!(format!("{:?}", &from_seed[start..]), format!("{:?}", from_record));
+            assert_eq!(format!("{:?}", original.replay().unwrap()), format!("{:?}", from_record));
+        }
+    }
+
+    #[test]
+    fn a_seed_and_a_script_reproduce_the_winner() {
+        use GamePlayerAction::*;
+        // the button limps, the small blind completes, and everyone checks it down
+        let mut script = vec![(0, Call), (1, Call), (2, Check)];
+        for _ in 0..3 {
+            script.extend([(1, Check), (2, Check), (0, Check)]);
+        }
+        let events = replay(5, vec![1000, 1000, 1000], GameConfig::default(), 0, &script).unwrap();
+        let showdown = events.iter().find_map(|event| match event { GameEvent::Showdown(showdown) => Some(showdown), _ => None }).unwrap();
+        // seat 1 makes an ace-high flush in diamonds
+        let (hands, steps, _) = showdown;
+        assert!(matches!(steps.as_slice(), [step] if step.winners == [1] && step.winnings == 30));
+        assert!(hands[1].as_ref().is_some_and(|(_, _, rank)| rank.category == HandCategory::Flush));
+        // replaying it again deals and plays out the same
+        assert_eq!(format!("{:?}", replay(5, vec![1000, 1000, 1000], GameConfig::default(), 0, &script).unwrap()), format!("{:?}", events));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{cards::Card, equity::estimate_win_probability, events::GamePlayerAction};
+
+// everything a seat is allowed to know when it has to act
+#[derive(Debug, Clone)]
+pub struct PlayerView {
+    pub seat: u8,
+    pub private_cards: [Card; 2],
+    pub public_cards: Vec<Card>, // only the revealed ones
+    pub pot: u32,
+    pub current_bet: u32,
+    pub min_raise: u32,
+    pub contribution: u32,
+    pub money: u32,
+    pub opponents: usize, // players still in the hand, not counting this one
+}
+impl PlayerView {
+    pub fn to_call(&self) -> u32 {
+        self.current_bet.saturating_sub(self.contribution)
+    }
+}
+
+pub trait Strategy {
+    fn decide(&mut self, view: &PlayerView) -> GamePlayerAction;
+}
+
+// baseline bot: estimates its equity against random hands and compares it to the pot odds
+#[derive(Debug, Clone)]
+pub struct EquityBot {
+    pub aggression: f64, // 0.0 only ever calls, 1.0 raises whenever it's ahead of its fair share
+    pub iterations: u32,
+}
+impl EquityBot {
+    pub fn new(aggression: f64) -> Self {
+        EquityBot { aggression: aggression.clamp(0.0, 1.0), iterations: 500 }
+    }
+}
+impl Strategy for EquityBot {
+    fn decide(&mut self, view: &PlayerView) -> GamePlayerAction {
+        let equity = estimate_win_probability(&view.private_cards, &view.public_cards, view.opponents, self.iterations);
+        let to_call = view.to_call();
+
+        let fair_share = 1.0 / (view.opponents + 1) as f64;
+        let raise_threshold = fair_share + (1.0 - fair_share) * (1.0 - self.aggression) / 2.0;
+
+        if equity > raise_threshold && self.aggression > 0.0 {
+            let raise = to_call + ((view.pot as f64 * self.aggression) as u32).max(view.min_raise);
+            return GamePlayerAction::AddMoney(raise.min(view.money));
+        }
+
+        if to_call == 0 {
+            return GamePlayerAction::Check;
+        }
+
+        let pot_odds = to_call as f64 / (view.pot + to_call) as f64;
+        if equity < pot_odds {
+            GamePlayerAction::Fold
+        } else {
+            GamePlayerAction::AddMoney(to_call.min(view.money))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(private_cards: [&str; 2], public_cards: &[&str], pot: u32, current_bet: u32) -> PlayerView {
+        PlayerView {
+            seat: 0,
+            private_cards: private_cards.map(|c| c.parse().unwrap()),
+            public_cards: public_cards.iter().map(|c| c.parse().unwrap()).collect(),
+            pot,
+            current_bet,
+            min_raise: 10,
+            contribution: 0,
+            money: 1000,
+            opponents: 1,
+        }
+    }
+
+    // enough iterations that the equity can't land anywhere near the thresholds by chance
+    fn equity_bot() -> EquityBot {
+        EquityBot { aggression: 0.5, iterations: 3000 }
+    }
+
+    #[test]
+    fn equity_bot_folds_a_hopeless_hand() {
+        let action = equity_bot().decide(&view(["7d", "2c"], &["As", "Kh", "Qc"], 200, 100));
+        assert!(matches!(action, GamePlayerAction::Fold), "{:?}", action);
+    }
+
+    #[test]
+    fn equity_bot_raises_a_strong_hand() {
+        let action = equity_bot().decide(&view(["Ah", "As"], &["Ac", "Ad", "Kh"], 200, 100));
+        assert!(matches!(action, GamePlayerAction::AddMoney(money) if money > 100), "{:?}", action);
+    }
+}